@@ -1 +1,4 @@
+//! Direct Memory Access Controller peripheral.
+
 mod register;
+pub use register::*;
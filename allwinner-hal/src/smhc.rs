@@ -31,4 +31,6 @@ pub enum ResponseMode {
 pub enum SdCardError {
     Unknown,
     UnexpectedResponse(u8, u128),
+    /// Command of given index failed with error interrupt.
+    TransferFailed(u8, Interrupt),
 }
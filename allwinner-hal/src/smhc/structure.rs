@@ -1,7 +1,8 @@
 use super::{
     ResponseMode, SdCardError, TransferMode,
     register::{
-        AccessMode, BlockSize, BusWidth, CardType, Command, RegisterBlock, TransferDirection,
+        AccessMode, BlockSize, BusWidth, CardType, Command, Interrupt, RegisterBlock,
+        TransferDirection,
     },
 };
 use crate::ccu::{self, Clocks, SmhcClockSource};
@@ -143,6 +144,7 @@ impl<SMHC: AsRef<RegisterBlock>, PADS> Smhc<SMHC, PADS> {
                     .modify(|w| w.set_access_mode(AccessMode::Ahb));
             }
         }
+        self.clear_interrupt_state();
         unsafe {
             smhc.argument.write(arg);
             smhc.command.write({
@@ -168,6 +170,16 @@ impl<SMHC: AsRef<RegisterBlock>, PADS> Smhc<SMHC, PADS> {
             });
         };
     }
+    /// Clear raw interrupt states, so that they only reflect the next command.
+    #[inline]
+    pub fn clear_interrupt_state(&self) {
+        let smhc = self.smhc.as_ref();
+        // Raw interrupt states are cleared by writing 1 to them.
+        unsafe {
+            smhc.interrupt_state_raw
+                .write(smhc.interrupt_state_raw.read())
+        };
+    }
     /// Read the response from the card.
     #[inline]
     pub fn read_response(&self) -> u128 {
@@ -193,8 +205,38 @@ impl<SMHC: AsRef<RegisterBlock>, PADS> Smhc<SMHC, PADS> {
             buf[i * 4 + 3] = ((data >> 24) & 0xff) as u8;
         }
     }
+    /// Write data into first-in-first-out buffer.
+    #[inline]
+    pub fn write_data(&self, buf: &[u8]) {
+        let smhc = self.smhc.as_ref();
+        for word in buf.chunks_exact(4) {
+            while smhc.status.read().fifo_full() {
+                core::hint::spin_loop();
+            }
+            let data = u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+            unsafe { smhc.fifo.write(data) };
+        }
+    }
 }
 
+/// Interrupts signalling a failed command response.
+const COMMAND_ERRORS: [Interrupt; 3] = [
+    Interrupt::ResponseError,
+    Interrupt::ResponseCrcError,
+    Interrupt::ResponseTimeoutBootAckReceived,
+];
+/// Interrupts signalling a failed command or data transfer.
+const TRANSFER_ERRORS: [Interrupt; 8] = [
+    Interrupt::ResponseError,
+    Interrupt::ResponseCrcError,
+    Interrupt::ResponseTimeoutBootAckReceived,
+    Interrupt::DataCrcError,
+    Interrupt::DataTimeoutBootDataStart,
+    Interrupt::DataStartError,
+    Interrupt::DataEndBitError,
+    Interrupt::FifoUnderrunOrOverflow,
+];
+
 pub struct SdCard<'a, S, P> {
     smhc: &'a mut Smhc<S, P>,
     block_count: u32,
@@ -291,6 +333,102 @@ impl<'a, S: AsRef<RegisterBlock>, P> SdCard<'a, S, P> {
             block_count: (c_size + 1) * 1024,
         })
     }
+    /// Create an SD card instance for an eMMC device.
+    ///
+    /// Initializes by MMC protocol and keeps the 1-bit bus set up by `Smhc`.
+    /// Only sector addressed (high capacity) devices are supported.
+    #[inline]
+    pub fn new_mmc(smhc: &'a mut Smhc<S, P>) -> Result<Self, SdCardError> {
+        /// Sector access mode and 2.7-3.6V voltage window.
+        const OCR_SECTOR_MODE: u32 = 0x40FF8080;
+        /// Device has finished power up routine if bit is high
+        const OCR_NBUSY: u32 = 0x80000000;
+        /// Access mode bits of OCR
+        const OCR_ACCESS_MODE: u32 = 0x60000000;
+        /// Relative card address assigned by host.
+        const RCA: u32 = 1 << 16;
+        /// Offset of SEC_COUNT in EXT_CSD.
+        const EXT_CSD_SEC_COUNT: usize = 212;
+
+        // CMD0(reset) -> CMD1(init and read OCR)
+        smhc.send_card_command(0, 0, TransferMode::Disable, ResponseMode::Disable, false);
+        Self::sleep(100);
+        const MAX_RETRIES: u16 = 1000;
+        let mut ocr = 0;
+        for _ in 0..MAX_RETRIES {
+            smhc.send_card_command(
+                1,
+                OCR_SECTOR_MODE,
+                TransferMode::Disable,
+                ResponseMode::Short,
+                false,
+            );
+            Self::sleep(10);
+            ocr = smhc.read_response() as u32;
+            if (ocr & OCR_NBUSY) == OCR_NBUSY {
+                break;
+            }
+        }
+        if (ocr & OCR_NBUSY) != OCR_NBUSY || (ocr & OCR_ACCESS_MODE) != 0x40000000 {
+            return Err(SdCardError::UnexpectedResponse(1, ocr as u128));
+        }
+
+        // Send CMD2 to get CID.
+        smhc.send_card_command(2, 0, TransferMode::Disable, ResponseMode::Long, true);
+        Self::sleep(100);
+        Self::check_command(smhc, 2)?;
+        let cid = smhc.read_response();
+        if cid == 0 {
+            return Err(SdCardError::UnexpectedResponse(2, cid));
+        }
+
+        // Send CMD3 to assign RCA.
+        smhc.send_card_command(3, RCA, TransferMode::Disable, ResponseMode::Short, true);
+        Self::sleep(100);
+        Self::check_card_status(smhc, 3)?;
+
+        // Send CMD7 to select card.
+        smhc.send_card_command(7, RCA, TransferMode::Disable, ResponseMode::Short, true);
+        Self::sleep(100);
+        Self::check_card_status(smhc, 7)?;
+
+        // Send CMD8 to read EXT_CSD, which holds capacity of sector addressed devices.
+        let mut ext_csd = Block::new();
+        let smhc_regs = smhc.smhc.as_ref();
+        unsafe {
+            smhc_regs.global_control.modify(|val| val.set_fifo_reset());
+            while !smhc_regs.global_control.read().is_fifo_reset_cleared() {
+                core::hint::spin_loop();
+            }
+        }
+        smhc.send_card_command(8, 0, TransferMode::Read, ResponseMode::Short, true);
+        smhc.read_data(&mut ext_csd.contents);
+        while !smhc_regs
+            .interrupt_state_raw
+            .read()
+            .has_interrupt(Interrupt::DataTransferComplete)
+        {
+            core::hint::spin_loop();
+        }
+        let sec_count = u32::from_le_bytes(
+            ext_csd.contents[EXT_CSD_SEC_COUNT..EXT_CSD_SEC_COUNT + 4]
+                .try_into()
+                .unwrap(),
+        );
+        if sec_count == 0 {
+            return Err(SdCardError::UnexpectedResponse(8, 0));
+        }
+
+        Ok(SdCard {
+            smhc,
+            block_count: sec_count,
+        })
+    }
+    /// Get the number of 512-byte blocks on the SD card.
+    #[inline]
+    pub fn block_count(&self) -> u32 {
+        self.block_count
+    }
     /// Get the size of the SD card in kilobytes.
     #[inline]
     pub fn get_size_kb(&self) -> f64 {
@@ -298,46 +436,105 @@ impl<'a, S: AsRef<RegisterBlock>, P> SdCard<'a, S, P> {
     }
     /// Read a block from the SD card.
     #[inline]
-    pub fn read_block(&self, block: &mut Block, block_idx: u32) {
+    pub fn read_block(&self, block: &mut Block, block_idx: u32) -> Result<(), SdCardError> {
+        let smhc = self.smhc.smhc.as_ref();
+        unsafe {
+            smhc.global_control.modify(|val| val.set_fifo_reset());
+            while !smhc.global_control.read().is_fifo_reset_cleared() {
+                core::hint::spin_loop();
+            }
+            smhc.global_control
+                .modify(|val| val.set_access_mode(AccessMode::Ahb));
+            smhc.fifo_water_level.modify(|val| {
+                use super::register::BurstSize;
+                val.set_burst_size(BurstSize::SixteenBit)
+                    .set_receive_trigger_level(15)
+                    .set_transmit_trigger_level(240)
+            });
+        }
+        self.smhc
+            .send_card_command(17, block_idx, TransferMode::Read, ResponseMode::Short, true);
+        self.smhc.read_data(&mut block.contents);
         loop {
-            let smhc = self.smhc.smhc.as_ref();
-            unsafe {
-                smhc.global_control.modify(|val| val.set_fifo_reset());
-                while !smhc.global_control.read().is_fifo_reset_cleared() {
-                    core::hint::spin_loop();
-                }
-                smhc.global_control
-                    .modify(|val| val.set_access_mode(AccessMode::Ahb));
-                self.smhc.smhc.as_ref().fifo_water_level.modify(|val| {
-                    use super::register::BurstSize;
-                    val.set_burst_size(BurstSize::SixteenBit)
-                        .set_receive_trigger_level(15)
-                        .set_transmit_trigger_level(240)
-                });
+            let status = smhc.interrupt_state_raw.read();
+            if let Some(&error) = TRANSFER_ERRORS
+                .iter()
+                .find(|&&error| status.has_interrupt(error))
+            {
+                return Err(SdCardError::TransferFailed(17, error));
             }
-            self.smhc.send_card_command(
-                17,
-                block_idx,
-                TransferMode::Read,
-                ResponseMode::Short,
-                true,
-            );
-            self.smhc.read_data(&mut block.contents);
-            loop {
-                use super::register::Interrupt;
-                let status = self.smhc.smhc.as_ref().interrupt_state_raw.read();
-                if status.has_interrupt(Interrupt::CommandComplete) {
-                    break;
-                }
-                Self::sleep(100);
+            if status.has_interrupt(Interrupt::DataTransferComplete) {
+                break;
+            }
+            core::hint::spin_loop();
+        }
+        Ok(())
+    }
+    /// Write a block to the SD card.
+    ///
+    /// Returns once the card finishes programming the block.
+    #[inline]
+    pub fn write_block(&self, block: &Block, block_idx: u32) -> Result<(), SdCardError> {
+        let smhc = self.smhc.smhc.as_ref();
+        unsafe {
+            smhc.global_control.modify(|val| val.set_fifo_reset());
+            while !smhc.global_control.read().is_fifo_reset_cleared() {
+                core::hint::spin_loop();
+            }
+            smhc.global_control
+                .modify(|val| val.set_access_mode(AccessMode::Ahb));
+        }
+        self.smhc.send_card_command(
+            24,
+            block_idx,
+            TransferMode::Write,
+            ResponseMode::Short,
+            true,
+        );
+        self.smhc.write_data(&block.contents);
+        loop {
+            let status = smhc.interrupt_state_raw.read();
+            if let Some(&error) = TRANSFER_ERRORS
+                .iter()
+                .find(|&&error| status.has_interrupt(error))
+            {
+                return Err(SdCardError::TransferFailed(24, error));
             }
-            use super::register::Interrupt;
-            let status = self.smhc.smhc.as_ref().interrupt_state_raw.read();
             if status.has_interrupt(Interrupt::DataTransferComplete) {
                 break;
             }
+            core::hint::spin_loop();
+        }
+        // Card holds DAT0 low while programming the block.
+        while smhc.status.read().card_busy() {
+            core::hint::spin_loop();
+        }
+        Ok(())
+    }
+    /// Check that the last command got a response without errors.
+    #[inline]
+    fn check_command(smhc: &Smhc<S, P>, cmd: u8) -> Result<(), SdCardError> {
+        let status = smhc.smhc.as_ref().interrupt_state_raw.read();
+        match COMMAND_ERRORS
+            .iter()
+            .find(|&&error| status.has_interrupt(error))
+        {
+            Some(&error) => Err(SdCardError::TransferFailed(cmd, error)),
+            None => Ok(()),
         }
     }
+    /// Check that the last command got an R1 card status without error bits.
+    #[inline]
+    fn check_card_status(smhc: &Smhc<S, P>, cmd: u8) -> Result<(), SdCardError> {
+        /// Error bits of R1 card status.
+        const R1_ERRORS: u32 = 0xFDF9_8008;
+        Self::check_command(smhc, cmd)?;
+        let status = smhc.read_response() as u32;
+        if status & R1_ERRORS != 0 {
+            return Err(SdCardError::UnexpectedResponse(cmd, status as u128));
+        }
+        Ok(())
+    }
     /// Parse CSD register version 2.
     #[inline]
     fn parse_csd_v2(csd: u128) -> (u32, u32) {
@@ -355,7 +552,7 @@ impl<'a, S: AsRef<RegisterBlock>, P> SdCard<'a, S, P> {
 }
 
 impl<'a, S: AsRef<RegisterBlock>, P> BlockDevice for SdCard<'a, S, P> {
-    type Error = core::convert::Infallible;
+    type Error = SdCardError;
 
     #[inline]
     fn read(
@@ -365,14 +562,17 @@ impl<'a, S: AsRef<RegisterBlock>, P> BlockDevice for SdCard<'a, S, P> {
         _reason: &str,
     ) -> Result<(), Self::Error> {
         for (i, block) in blocks.iter_mut().enumerate() {
-            self.read_block(block, start_block_idx.0 + i as u32);
+            self.read_block(block, start_block_idx.0 + i as u32)?;
         }
         Ok(())
     }
 
     #[inline]
-    fn write(&self, _blocks: &[Block], _start_block_idx: BlockIdx) -> Result<(), Self::Error> {
-        todo!();
+    fn write(&self, blocks: &[Block], start_block_idx: BlockIdx) -> Result<(), Self::Error> {
+        for (i, block) in blocks.iter().enumerate() {
+            self.write_block(block, start_block_idx.0 + i as u32)?;
+        }
+        Ok(())
     }
 
     #[inline]
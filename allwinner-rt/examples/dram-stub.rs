@@ -0,0 +1,32 @@
+// Build this example with:
+// cargo build --example dram-stub --target riscv64imac-unknown-none-elf --release -p allwinner-rt --features d1,fel-return
+// Then wrap it into an eGON.BT0 image for `rfel ddr`:
+// rfel mkimage target/riscv64imac-unknown-none-elf/release/examples/dram-stub dram-stub.bin
+
+#![no_std]
+#![no_main]
+use allwinner_rt::{Clocks, Peripherals, dram_parameters, entry, init_dram};
+use core::mem::size_of;
+
+const PARA_WORDS: usize = size_of::<dram_parameters>() / 4;
+
+/// DRAM parameters followed by detected size in MiB.
+///
+/// Placed right after eGON.BT0 head; `rfel ddr` fills parameters in before
+/// running this image and reads the size back once it returns to FEL.
+#[unsafe(no_mangle)]
+#[unsafe(link_section = ".head.meta")]
+static mut DRAM_META: [u32; PARA_WORDS + 1] = [0; PARA_WORDS + 1];
+
+#[entry]
+fn main(p: Peripherals, _c: Clocks) {
+    let meta = &raw mut DRAM_META;
+    let para = unsafe { &mut *(meta as *mut dram_parameters) };
+    let size = init_dram(para, &p.ccu, &p.phy);
+    unsafe { (*meta)[PARA_WORDS] = size as u32 };
+}
+
+#[panic_handler]
+fn panic(_: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
@@ -18,7 +18,7 @@ pub struct EgonHead {
 #[unsafe(link_section = ".head.egon")]
 static EGON_HEAD: EgonHead = EgonHead {
     magic: *b"eGON.BT0",
-    checksum: 0x5F0A6C39, // real checksum will be filled by blob generator
+    checksum: 0x5F0A6C39, // real checksum will be filled by `rfel mkimage`
     length: 0x8000,
     pub_head_size: 0,
     pub_head_version: *b"3000",
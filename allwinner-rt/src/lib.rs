@@ -25,6 +25,9 @@ mod mctl;
 #[cfg(any(feature = "nezha", feature = "lichee"))]
 /// Dram initializing function.
 pub use mctl::init as dram_init;
+#[cfg(any(feature = "nezha", feature = "lichee"))]
+/// Dram initializing function with board parameters.
+pub use mctl::{DramType, dram_parameters, init_dram};
 
 pub use allwinner_rt_macros::entry;
 
@@ -36,6 +39,9 @@ pub mod soc {
 
 /// Jump over head data to executable code.
 ///
+/// Halts once `main` returns, or with `fel-return` feature returns to caller,
+/// so images loaded over FEL resume FEL mode when finished.
+///
 /// # Safety
 ///
 /// Naked function.
@@ -44,7 +50,7 @@ pub mod soc {
 /// It allows for configuring special eXtensions. See further below for details.
 #[unsafe(naked)]
 #[unsafe(link_section = ".text.entry")]
-unsafe extern "C" fn start() -> ! {
+unsafe extern "C" fn start() {
     const STACK_SIZE: usize = 4 * 1024;
     #[unsafe(link_section = ".bss.uninit")]
     static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
@@ -58,10 +64,14 @@ unsafe extern "C" fn start() -> ! {
         "csrs   0x7C2, t1",
         // Disable interrupt
         "csrw   mie, zero",
-        // Prepare programming language stack
-        "la     sp, {stack}
+        // Prepare programming language stack, saving caller stack and return address
+        "mv     t2, sp
+        la      sp, {stack}
         li      t0, {stack_size}
-        add     sp, sp, t0",
+        add     sp, sp, t0
+        addi    sp, sp, -16
+        sd      t2, 0(sp)
+        sd      ra, 8(sp)",
             // Clear `.bss` section
             "la     t1, sbss
         la      t2, ebss
@@ -72,12 +82,19 @@ unsafe extern "C" fn start() -> ! {
     3:  ",
         // Start Rust main function
         "call   {main}",
-        // Platform halt if main function returns
-    "3: wfi
+        // Return to caller (BROM or FEL) if main function returns and
+        // `fel-return` feature is enabled, otherwise platform halt
+        "li     t0, {fel_return}
+        beqz    t0, 3f
+        ld      ra, 8(sp)
+        ld      sp, 0(sp)
+        ret
+    3:  wfi
         j       3b",
         stack      =   sym STACK,
         stack_size = const STACK_SIZE,
         main       =   sym main,
+        fel_return = const cfg!(feature = "fel-return") as usize,
     )
 }
 
@@ -1664,6 +1664,8 @@ fn auto_scan_dram_config(
     Ok(())
 }
 
+/// Initialize DRAM with board parameters, returning detected size in MiB or 0 on failure.
+///
 /// # Safety
 ///
 /// No warranty. Use at own risk. Be lucky to get values from vendor.
@@ -34,6 +34,8 @@ soc! {
     pub struct CCU => 0x02001000, allwinner_hal::ccu::RegisterBlock;
     /// Universal Asynchronous Receiver/Transmitter 0.
     pub struct UART0 => 0x02500000, allwinner_hal::uart::RegisterBlock;
+    /// Direct Memory Access Controller peripheral.
+    pub struct DMAC => 0x03002000, allwinner_hal::dma::RegisterBlock;
     /// Common control peripheral of DDR SDRAM.
     pub struct COM => 0x03102000, allwinner_hal::com::RegisterBlock;
     /// Memory controller physical layer (PHY) of DDR SDRAM.
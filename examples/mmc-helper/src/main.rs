@@ -0,0 +1,123 @@
+//! FEL helper reading and writing SD cards or eMMC for `rfel mmc`.
+//!
+//! Build with:
+//! cargo build -p mmc-helper --target riscv64imac-unknown-none-elf --release
+//! then wrap into an eGON.BT0 image:
+//! rfel mkimage target/riscv64imac-unknown-none-elf/release/mmc-helper mmc-helper.bin
+//!
+//! `rfel` fills in the mailbox right after eGON.BT0 head and runs this image
+//! once for each buffer of blocks; it returns to FEL when done.
+//!
+//! SD cards on SMHC0 are initialized by SD protocol, eMMC on SMHC2 by MMC
+//! protocol; both are then accessed through `SdCard`.
+
+#![no_std]
+#![no_main]
+
+use allwinner_hal::{
+    prelude::*,
+    smhc::{SdCard, SdCardError, Smhc},
+};
+use allwinner_rt::{Clocks, Peripherals, entry};
+use embedded_sdmmc::Block;
+use panic_halt as _;
+
+/// Mailbox words, see `rfel::mmc` for their meaning.
+#[repr(C)]
+struct Mailbox {
+    smhc: u32,
+    operation: u32,
+    block: u32,
+    count: u32,
+    buffer: u32,
+    status: u32,
+    card_blocks: u32,
+}
+
+const OPERATION_READ: u32 = 1;
+const OPERATION_WRITE: u32 = 2;
+
+const STATUS_DONE: u32 = 0;
+const STATUS_INVALID_REQUEST: u32 = 1;
+const STATUS_INIT_FAILED: u32 = 2;
+const STATUS_OUT_OF_RANGE: u32 = 3;
+const STATUS_WRITE_FAILED: u32 = 4;
+const STATUS_READ_FAILED: u32 = 5;
+
+#[unsafe(no_mangle)]
+#[unsafe(link_section = ".head.meta")]
+static mut MAILBOX: Mailbox = Mailbox {
+    smhc: 0,
+    operation: 0,
+    block: 0,
+    count: 0,
+    buffer: 0,
+    status: 0,
+    card_blocks: 0,
+};
+
+#[entry]
+fn main(p: Peripherals, c: Clocks) {
+    let mailbox = unsafe { &mut *(&raw mut MAILBOX) };
+    let (status, card_blocks) = match mailbox.smhc {
+        0 => {
+            let pads = (
+                p.gpio.pf0.into_function::<2>(),
+                p.gpio.pf1.into_function::<2>(),
+                p.gpio.pf2.into_function::<2>(),
+                p.gpio.pf3.into_function::<2>(),
+                p.gpio.pf4.into_function::<2>(),
+                p.gpio.pf5.into_function::<2>(),
+            );
+            let mut smhc = Smhc::new::<0>(p.smhc0, pads, &c, &p.ccu);
+            serve(mailbox, SdCard::new(&mut smhc))
+        }
+        2 => {
+            let pads = (
+                p.gpio.pc2.into_function::<3>(),
+                p.gpio.pc3.into_function::<3>(),
+                p.gpio.pc4.into_function::<3>(),
+                p.gpio.pc5.into_function::<3>(),
+                p.gpio.pc6.into_function::<3>(),
+                p.gpio.pc7.into_function::<3>(),
+            );
+            let mut smhc = Smhc::new::<2>(p.smhc2, pads, &c, &p.ccu);
+            serve(mailbox, SdCard::new_mmc(&mut smhc))
+        }
+        _ => (STATUS_INVALID_REQUEST, 0),
+    };
+    mailbox.card_blocks = card_blocks;
+    mailbox.status = status;
+}
+
+/// Run requested operation on initialized card, returning status and card size in blocks.
+fn serve<S: AsRef<allwinner_hal::smhc::RegisterBlock>, P>(
+    mailbox: &Mailbox,
+    card: Result<SdCard<'_, S, P>, SdCardError>,
+) -> (u32, u32) {
+    let Ok(card) = card else {
+        return (STATUS_INIT_FAILED, 0);
+    };
+    let card_blocks = card.block_count();
+    if mailbox.block as u64 + mailbox.count as u64 > card_blocks as u64 {
+        return (STATUS_OUT_OF_RANGE, card_blocks);
+    }
+    let buffer = mailbox.buffer as usize as *mut Block;
+    for i in 0..mailbox.count {
+        let block = unsafe { &mut *buffer.add(i as usize) };
+        match mailbox.operation {
+            OPERATION_READ => {
+                if card.read_block(block, mailbox.block + i).is_err() {
+                    return (STATUS_READ_FAILED, card_blocks);
+                }
+            }
+            OPERATION_WRITE => {
+                if card.write_block(block, mailbox.block + i).is_err() {
+                    return (STATUS_WRITE_FAILED, card_blocks);
+                }
+            }
+            _ => return (STATUS_INVALID_REQUEST, card_blocks),
+        }
+    }
+    (STATUS_DONE, card_blocks)
+}
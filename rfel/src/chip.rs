@@ -0,0 +1,330 @@
+//! Parameters of FEL capable Allwinner chips.
+
+use crate::{
+    ddr::{self, DramParameters},
+    regs::{self, Peripheral},
+    spi::SpiInfo,
+};
+use allwinner_hal::{ccu, gpio};
+use core::mem::{offset_of, size_of};
+
+/// Allwinner chip connected in FEL mode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Chip {
+    /// H2+ or H3 chip.
+    H3,
+    /// A64 chip.
+    A64,
+    /// H6 chip.
+    H6,
+    /// H616, H313 or T507 chip.
+    H616,
+    /// D1-H, D1s or F133 chip.
+    D1,
+    /// T113-S3, T113-S4 or R528 chip.
+    ///
+    /// These chips share chip ID with D1 but run ARM cores.
+    T113,
+    /// V853, V851s or V851se chip.
+    V853,
+    /// V821 chip.
+    V821,
+}
+
+/// Instruction set of the core running FEL in BROM.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Arch {
+    /// 32-bit ARM (AArch32), including 64-bit chips whose BROM runs in AArch32.
+    Arm,
+    /// 64-bit or 32-bit RISC-V.
+    RiscV,
+}
+
+/// Memory region on chip.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Region {
+    /// Start address.
+    pub address: u32,
+    /// Size in bytes.
+    pub size: u32,
+}
+
+impl Region {
+    #[inline]
+    const fn new(address: u32, size: u32) -> Self {
+        Region { address, size }
+    }
+    /// End address, exclusive.
+    #[inline]
+    pub const fn end(&self) -> u32 {
+        self.address + self.size
+    }
+    /// Check if this region shares any byte with `other`.
+    #[inline]
+    pub const fn overlaps(&self, other: &Region) -> bool {
+        self.address < other.end() && other.address < self.end()
+    }
+}
+
+/// Chip specific parameters used by FEL operations.
+#[derive(Debug)]
+pub struct ChipInfo {
+    /// Human readable chip name.
+    pub name: &'static str,
+    /// Chip ID reported by FEL version request.
+    pub id: u32,
+    /// Instruction set of FEL code.
+    pub arch: Arch,
+    /// Width of general purpose registers of the core running FEL, in bits.
+    pub xlen: u32,
+    /// SRAM region where SPL images are loaded and run.
+    pub spl: Region,
+    /// SRAM region for helper payloads and their buffers.
+    pub scratch: Region,
+    /// SRAM region BROM uses as stack while in FEL mode; `spl` and `scratch`
+    /// must stay clear of it.
+    pub stack: Region,
+    /// Address of SID (security ID) eFuse words.
+    pub sid: u32,
+    /// Register writes, in order, that reset the chip through its watchdog.
+    pub watchdog_reset: &'static [(u32, u32)],
+    /// SPI0 controller used for boot flash, if supported by rfel.
+    pub spi0: Option<SpiInfo>,
+    /// Built-in DRAM profiles of boards, if `dram-stub` of `allwinner-rt`
+    /// supports the chip.
+    pub dram_profiles: Option<&'static [(&'static str, DramParameters)]>,
+    /// Peripherals with register tables for symbolic register access.
+    pub peripherals: &'static [Peripheral],
+}
+
+/// Watchdog at `0x01c20ca0`, found on H3 and A64.
+const WATCHDOG_H3: &[(u32, u32)] = &[
+    // WDOG0_CFG: reset whole system.
+    (0x01c2_0cb4, 0x1),
+    // WDOG0_MODE: enable with shortest interval.
+    (0x01c2_0cb8, 0x1),
+];
+/// Watchdog at `0x030090a0`, found on H6 and H616.
+const WATCHDOG_H6: &[(u32, u32)] = &[(0x0309_00b4, 0x1), (0x0309_00b8, 0x1)];
+/// Watchdog at `0x020500a0` with write key `0x16aa`, found on D1, T113 and V853.
+const WATCHDOG_D1: &[(u32, u32)] = &[(0x0205_00b4, 0x16aa_0001), (0x0205_00b8, 0x16aa_0001)];
+/// Watchdog at `0x43031000` with write key `0x16aa`, found on V821.
+const WATCHDOG_V821: &[(u32, u32)] = &[(0x4303_1014, 0x16aa_0001), (0x4303_1018, 0x16aa_0001)];
+
+const D1_CCU: u32 = 0x0200_1000;
+const D1_GPIO: u32 = 0x0200_0000;
+/// SPI0 on port C pins `PC2` to `PC7`, found on D1 and T113.
+const SPI0_D1: SpiInfo = SpiInfo {
+    base: 0x0402_5000,
+    setup: &[
+        // PC_CFG0: PC2 to PC7 as SPI0 function 2.
+        (
+            D1_GPIO
+                + (offset_of!(gpio::RegisterBlock, sys_port) + 2 * size_of::<gpio::Port>()) as u32,
+            0xffff_ff00,
+            0x2222_2200,
+        ),
+        // SPI_BGR: deassert SPI0 reset and pass SPI0 bus clock.
+        (
+            D1_CCU + offset_of!(ccu::RegisterBlock, spi_bgr) as u32,
+            0x0001_0001,
+            0x0001_0001,
+        ),
+        // SPI0_CLK: enable from 24 MHz HOSC with both dividers 1.
+        (
+            D1_CCU + offset_of!(ccu::RegisterBlock, spi_clk) as u32,
+            0xffff_ffff,
+            0x8000_0000,
+        ),
+    ],
+};
+
+// BROM of H3 and A64 keeps its IRQ stack below offset 0x2000 of SRAM A1 and
+// its FEL stack from 0x5c00 to 0x7000, so SPL takes the window between them.
+const H3: ChipInfo = ChipInfo {
+    name: "H3",
+    id: 0x0016_8000,
+    arch: Arch::Arm,
+    xlen: 32,
+    spl: Region::new(0x0000_2000, 0x3c00),
+    scratch: Region::new(0x0000_8000, 0x2000),
+    stack: Region::new(0x0000_5c00, 0x1400),
+    sid: 0x01c1_4200,
+    watchdog_reset: WATCHDOG_H3,
+    spi0: None,
+    dram_profiles: None,
+    peripherals: &[],
+};
+const A64: ChipInfo = ChipInfo {
+    name: "A64",
+    id: 0x0016_8900,
+    arch: Arch::Arm,
+    xlen: 32,
+    spl: Region::new(0x0001_2000, 0x3c00),
+    scratch: Region::new(0x0001_8000, 0x2000),
+    stack: Region::new(0x0001_5c00, 0x1400),
+    sid: 0x01c1_4200,
+    watchdog_reset: WATCHDOG_H3,
+    spi0: None,
+    dram_profiles: None,
+    peripherals: &[],
+};
+const H6: ChipInfo = ChipInfo {
+    name: "H6",
+    id: 0x0017_2800,
+    arch: Arch::Arm,
+    xlen: 32,
+    spl: Region::new(0x0002_0000, 0x8000),
+    scratch: Region::new(0x0002_8000, 0x2000),
+    stack: Region::new(0x0002_a400, 0x1c00),
+    sid: 0x0300_6200,
+    watchdog_reset: WATCHDOG_H6,
+    spi0: None,
+    dram_profiles: None,
+    peripherals: &[],
+};
+const H616: ChipInfo = ChipInfo {
+    name: "H616",
+    id: 0x0018_2300,
+    arch: Arch::Arm,
+    xlen: 32,
+    spl: Region::new(0x0002_0000, 0x8000),
+    scratch: Region::new(0x0002_8000, 0x8000),
+    stack: Region::new(0x0003_0000, 0x2000),
+    sid: 0x0300_6200,
+    watchdog_reset: WATCHDOG_H6,
+    spi0: None,
+    dram_profiles: None,
+    peripherals: &[],
+};
+const D1: ChipInfo = ChipInfo {
+    name: "D1",
+    id: 0x0018_5900,
+    arch: Arch::RiscV,
+    xlen: 64,
+    spl: Region::new(0x0002_0000, 0x10000),
+    scratch: Region::new(0x0003_0000, 0x8000),
+    stack: Region::new(0x0003_8000, 0x2000),
+    sid: 0x0300_6200,
+    watchdog_reset: WATCHDOG_D1,
+    spi0: Some(SPI0_D1),
+    dram_profiles: Some(ddr::PROFILES),
+    peripherals: regs::D1,
+};
+const T113: ChipInfo = ChipInfo {
+    name: "T113/R528",
+    id: 0x0018_5900,
+    arch: Arch::Arm,
+    xlen: 32,
+    spl: Region::new(0x0002_0000, 0x10000),
+    scratch: Region::new(0x0003_0000, 0x8000),
+    stack: Region::new(0x0003_8000, 0x2000),
+    sid: 0x0300_6200,
+    watchdog_reset: WATCHDOG_D1,
+    spi0: Some(SPI0_D1),
+    dram_profiles: None,
+    peripherals: regs::D1,
+};
+const V853: ChipInfo = ChipInfo {
+    name: "V853",
+    id: 0x0018_8600,
+    arch: Arch::Arm,
+    xlen: 32,
+    spl: Region::new(0x0002_0000, 0x10000),
+    scratch: Region::new(0x0003_0000, 0x8000),
+    stack: Region::new(0x0003_8000, 0x2000),
+    sid: 0x0300_6200,
+    watchdog_reset: WATCHDOG_D1,
+    spi0: None,
+    dram_profiles: None,
+    peripherals: regs::V853,
+};
+const V821: ChipInfo = ChipInfo {
+    name: "V821",
+    id: 0x0018_8200,
+    arch: Arch::RiscV,
+    xlen: 32,
+    spl: Region::new(0x0002_0000, 0x10000),
+    scratch: Region::new(0x0003_0000, 0x8000),
+    stack: Region::new(0x0003_8000, 0x2000),
+    sid: 0x4300_6200,
+    watchdog_reset: WATCHDOG_V821,
+    spi0: None,
+    dram_profiles: None,
+    peripherals: regs::V821,
+};
+
+impl Chip {
+    /// All chips known to rfel.
+    pub const ALL: [Chip; 8] = [
+        Chip::H3,
+        Chip::A64,
+        Chip::H6,
+        Chip::H616,
+        Chip::D1,
+        Chip::T113,
+        Chip::V853,
+        Chip::V821,
+    ];
+
+    /// Get chip from FEL chip ID.
+    ///
+    /// Chips sharing the same ID resolve to the first listed one; use
+    /// `Fel::chip` to tell them apart on a connected device.
+    #[inline]
+    pub fn from_id(id: u32) -> Option<Chip> {
+        Self::ALL.into_iter().find(|chip| chip.info().id == id)
+    }
+
+    /// Get parameters of this chip.
+    #[inline]
+    pub const fn info(self) -> &'static ChipInfo {
+        match self {
+            Chip::H3 => &H3,
+            Chip::A64 => &A64,
+            Chip::H6 => &H6,
+            Chip::H616 => &H616,
+            Chip::D1 => &D1,
+            Chip::T113 => &T113,
+            Chip::V853 => &V853,
+            Chip::V821 => &V821,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Arch, Chip};
+
+    #[test]
+    fn chip_from_id() {
+        assert_eq!(Chip::from_id(0x0018_5900), Some(Chip::D1));
+        assert_eq!(Chip::from_id(0x0016_8900), Some(Chip::A64));
+        assert_eq!(Chip::from_id(0x1234_5678), None);
+    }
+
+    #[test]
+    fn chip_regions() {
+        for chip in Chip::ALL {
+            let info = chip.info();
+            assert!(!info.spl.overlaps(&info.scratch), "{:?}", chip);
+            assert!(!info.spl.overlaps(&info.stack), "{:?}", chip);
+            assert!(!info.scratch.overlaps(&info.stack), "{:?}", chip);
+            assert!(
+                matches!(
+                    (info.arch, info.xlen),
+                    (Arch::Arm, 32) | (Arch::RiscV, 32 | 64)
+                ),
+                "{:?}",
+                chip
+            );
+        }
+    }
+
+    #[test]
+    fn chip_spi0_setup() {
+        let spi0 = Chip::D1.info().spi0.as_ref().unwrap();
+        let addresses: Vec<u32> = spi0.setup.iter().map(|(address, _, _)| *address).collect();
+        assert_eq!(addresses, [0x0200_0060, 0x0200_196c, 0x0200_1940]);
+    }
+}
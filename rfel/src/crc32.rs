@@ -0,0 +1,54 @@
+//! CRC-32 (IEEE 802.3) as used by zlib and Ethernet.
+
+/// Reflected polynomial of CRC-32.
+const POLYNOMIAL: u32 = 0xedb8_8320;
+
+/// Lookup table of CRC-32 for each byte value.
+pub const TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut index = 0;
+    while index < 256 {
+        let mut value = index as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            value = if value & 1 != 0 {
+                (value >> 1) ^ POLYNOMIAL
+            } else {
+                value >> 1
+            };
+            bit += 1;
+        }
+        table[index] = value;
+        index += 1;
+    }
+    table
+};
+
+/// Initial CRC-32 state.
+pub const INIT: u32 = 0xffff_ffff;
+
+/// Update CRC-32 state with data.
+#[inline]
+pub fn update(state: u32, data: &[u8]) -> u32 {
+    data.iter().fold(state, |state, byte| {
+        (state >> 8) ^ TABLE[((state ^ *byte as u32) & 0xff) as usize]
+    })
+}
+
+/// Calculate CRC-32 of data.
+#[inline]
+pub fn crc32(data: &[u8]) -> u32 {
+    !update(INIT, data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{INIT, crc32, update};
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(!update(update(INIT, b"1234"), b"56789"), 0xcbf4_3926);
+    }
+}
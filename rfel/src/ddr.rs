@@ -0,0 +1,253 @@
+//! DRAM initialization by an `allwinner-rt` stub image.
+//!
+//! The stub is an eGON.BT0 image built from the `dram-stub` example of
+//! `allwinner-rt`. Its head is followed by [`DramParameters`] and one word
+//! where the stub stores detected DRAM size in MiB before returning to FEL.
+//!
+//! Besides built-in board profiles, parameters may be given by a TOML file.
+//! It either lists every field, or names a built-in profile as `base` and
+//! overrides some of its fields:
+//!
+//! ```toml
+//! base = "nezha"
+//! dram_clk = 528
+//! dram_tpr13 = 0x34050100
+//! ```
+
+use crate::egon::EgonHead;
+use core::{fmt, mem::size_of};
+use serde::{Deserialize, Serialize};
+
+/// Offset of DRAM parameters in stub image.
+pub const PARAMS_OFFSET: usize = EgonHead::OFFSET + EgonHead::SIZE;
+/// Offset of detected DRAM size word in stub image.
+pub const SIZE_OFFSET: usize = PARAMS_OFFSET + size_of::<DramParameters>();
+
+/// DRAM parameters, as `dram_parameters` in `allwinner-rt`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[repr(C)]
+pub struct DramParameters {
+    pub dram_clk: u32,
+    /// 2 for DDR2, 3 for DDR3, 6 for LPDDR2 or 7 for LPDDR3.
+    pub dram_type: u32,
+    pub dram_zq: u32,
+    pub dram_odt_en: u32,
+    pub dram_para1: u32,
+    pub dram_para2: u32,
+    pub dram_mr0: u32,
+    pub dram_mr1: u32,
+    pub dram_mr2: u32,
+    pub dram_mr3: u32,
+    pub dram_tpr0: u32,
+    pub dram_tpr1: u32,
+    pub dram_tpr2: u32,
+    pub dram_tpr3: u32,
+    pub dram_tpr4: u32,
+    pub dram_tpr5: u32,
+    pub dram_tpr6: u32,
+    pub dram_tpr7: u32,
+    pub dram_tpr8: u32,
+    pub dram_tpr9: u32,
+    pub dram_tpr10: u32,
+    pub dram_tpr11: u32,
+    pub dram_tpr12: u32,
+    pub dram_tpr13: u32,
+}
+
+impl DramParameters {
+    /// DDR3 parameters of Nezha D1 board.
+    #[rustfmt::skip]
+    pub const NEZHA: Self = DramParameters {
+        dram_clk:    792,
+        dram_type:   3,
+        dram_zq:     0x007b_7bfb,
+        dram_odt_en: 0x0000_0001,
+        dram_para1:  0x0000_10f2,
+        dram_para2:  0x0000_0000,
+        dram_mr0:    0x0000_1c70,
+        dram_mr1:    0x0000_0042,
+        dram_mr2:    0x0000_0000,
+        dram_mr3:    0x0000_0000,
+        dram_tpr0:   0x004a_2195,
+        dram_tpr1:   0x0242_3190,
+        dram_tpr2:   0x0008_b061,
+        dram_tpr3:   0xb478_7896,
+        dram_tpr4:   0x0000_0000,
+        dram_tpr5:   0x4848_4848,
+        dram_tpr6:   0x0000_0048,
+        dram_tpr7:   0x1620_121e,
+        dram_tpr8:   0x0000_0000,
+        dram_tpr9:   0x0000_0000,
+        dram_tpr10:  0x0000_0000,
+        dram_tpr11:  0x0076_0000,
+        dram_tpr12:  0x0000_0035,
+        dram_tpr13:  0x3405_0101,
+    };
+    /// DDR3 parameters of Lichee RV board.
+    #[rustfmt::skip]
+    pub const LICHEE: Self = DramParameters {
+        dram_para1:  0x0000_10d2,
+        dram_mr2:    0x0000_0018,
+        dram_tpr11:  0x0087_0000,
+        dram_tpr12:  0x0000_0024,
+        dram_tpr13:  0x3405_0100,
+        ..Self::NEZHA
+    };
+
+    /// Get built-in profile by board name.
+    pub fn profile(name: &str) -> Option<Self> {
+        PROFILES
+            .iter()
+            .find(|(profile, _)| *profile == name)
+            .map(|(_, params)| *params)
+    }
+
+    /// Parse parameters from TOML text.
+    pub fn parse(text: &str) -> Result<Self, DdrError> {
+        let mut table: toml::Table =
+            toml::from_str(text).map_err(|e| DdrError::Parse(e.to_string()))?;
+        if let Some(base) = table.remove("base") {
+            let Some(name) = base.as_str() else {
+                return Err(DdrError::Parse(
+                    "`base` should be a profile name".to_string(),
+                ));
+            };
+            let base =
+                Self::profile(name).ok_or_else(|| DdrError::UnknownProfile(name.to_string()))?;
+            let mut merged = toml::Table::try_from(base).expect("parameters serialize into table");
+            merged.extend(table);
+            table = merged;
+        }
+        let params: Self = table
+            .try_into()
+            .map_err(|e: toml::de::Error| DdrError::Parse(e.to_string()))?;
+        params.validate()?;
+        Ok(params)
+    }
+
+    /// Check that stub can accept these parameters.
+    pub fn validate(&self) -> Result<(), DdrError> {
+        match self.dram_type {
+            2 | 3 | 6 | 7 => Ok(()),
+            other => Err(DdrError::InvalidDramType(other)),
+        }
+    }
+
+    /// Parameters in little endian, as the stub reads them.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let words = [
+            self.dram_clk,
+            self.dram_type,
+            self.dram_zq,
+            self.dram_odt_en,
+            self.dram_para1,
+            self.dram_para2,
+            self.dram_mr0,
+            self.dram_mr1,
+            self.dram_mr2,
+            self.dram_mr3,
+            self.dram_tpr0,
+            self.dram_tpr1,
+            self.dram_tpr2,
+            self.dram_tpr3,
+            self.dram_tpr4,
+            self.dram_tpr5,
+            self.dram_tpr6,
+            self.dram_tpr7,
+            self.dram_tpr8,
+            self.dram_tpr9,
+            self.dram_tpr10,
+            self.dram_tpr11,
+            self.dram_tpr12,
+            self.dram_tpr13,
+        ];
+        words.iter().flat_map(|word| word.to_le_bytes()).collect()
+    }
+}
+
+/// Built-in board profiles.
+pub const PROFILES: &[(&str, DramParameters)] = &[
+    ("nezha", DramParameters::NEZHA),
+    ("lichee", DramParameters::LICHEE),
+];
+
+/// Write parameters into stub image and clear its size word.
+///
+/// Returns `None` if image is too short to hold them.
+pub fn patch(image: &mut [u8], params: &DramParameters) -> Option<()> {
+    image
+        .get_mut(PARAMS_OFFSET..SIZE_OFFSET)?
+        .copy_from_slice(&params.to_bytes());
+    image.get_mut(SIZE_OFFSET..SIZE_OFFSET + 4)?.fill(0);
+    Some(())
+}
+
+/// Error in DRAM parameters.
+#[derive(Debug, PartialEq, Eq)]
+pub enum DdrError {
+    /// Parameters are not valid TOML or miss fields.
+    Parse(String),
+    /// No built-in profile has given name.
+    UnknownProfile(String),
+    /// DRAM type is not one supported by `allwinner-rt`.
+    InvalidDramType(u32),
+}
+
+impl fmt::Display for DdrError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DdrError::Parse(e) => write!(f, "invalid DRAM parameters: {}", e),
+            DdrError::UnknownProfile(name) => write!(f, "unknown DRAM profile {:?}", name),
+            DdrError::InvalidDramType(value) => write!(f, "invalid DRAM type {}", value),
+        }
+    }
+}
+
+impl std::error::Error for DdrError {}
+
+#[cfg(test)]
+mod tests {
+    use super::{DdrError, DramParameters, PARAMS_OFFSET, SIZE_OFFSET, patch};
+
+    #[test]
+    fn ddr_parse() {
+        let text = "base = \"lichee\"\ndram_clk = 528\n";
+        let params = DramParameters::parse(text).unwrap();
+        assert_eq!(params.dram_clk, 528);
+        assert_eq!(params.dram_tpr13, 0x3405_0100);
+        assert_eq!(params.dram_tpr3, 0xb478_7896);
+
+        let full = toml::to_string(&DramParameters::NEZHA).unwrap();
+        assert_eq!(DramParameters::parse(&full), Ok(DramParameters::NEZHA));
+
+        assert!(matches!(
+            DramParameters::parse("dram_clk = 528\n"),
+            Err(DdrError::Parse(_))
+        ));
+        assert_eq!(
+            DramParameters::parse("base = \"d1\"\n"),
+            Err(DdrError::UnknownProfile("d1".to_string()))
+        );
+        assert_eq!(
+            DramParameters::parse("base = \"nezha\"\ndram_type = 4\n"),
+            Err(DdrError::InvalidDramType(4))
+        );
+        assert!(matches!(
+            DramParameters::parse("base = \"nezha\"\ndram_clock = 528\n"),
+            Err(DdrError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn ddr_patch() {
+        assert_eq!((PARAMS_OFFSET, SIZE_OFFSET), (0x30, 0x90));
+        let mut image = vec![0xff; 0x100];
+        patch(&mut image, &DramParameters::NEZHA).unwrap();
+        assert_eq!(image[0x30..0x34], 792u32.to_le_bytes());
+        assert_eq!(image[0x8c..0x90], 0x3405_0101u32.to_le_bytes());
+        assert_eq!(image[0x90..0x94], [0; 4]);
+        assert_eq!(image[0x94], 0xff);
+        assert_eq!(patch(&mut image[..0x40], &DramParameters::NEZHA), None);
+    }
+}
@@ -0,0 +1,281 @@
+//! Enumerate and select Allwinner devices in FEL mode.
+
+use crate::transport::block_on_until;
+use core::{fmt, str::FromStr};
+use futures::StreamExt;
+use nusb::{
+    DeviceId, DeviceInfo,
+    hotplug::{HotplugEvent, HotplugWatch},
+};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+/// USB vendor ID 0x1f3a: Allwinner Technology Co., Ltd.
+pub const VENDOR_ALLWINNER: u16 = 0x1f3a;
+/// Product 0xefe8: sunxi SoC OTG connector in FEL/flashing mode.
+pub const PRODUCT_FEL: u16 = 0xefe8;
+
+/// List all connected Allwinner devices in FEL mode.
+pub fn list_devices() -> std::io::Result<Vec<DeviceInfo>> {
+    Ok(nusb::list_devices()?.filter(is_fel).collect())
+}
+
+/// Check if device is an Allwinner device in FEL mode.
+pub fn is_fel(info: &DeviceInfo) -> bool {
+    info.vendor_id() == VENDOR_ALLWINNER && info.product_id() == PRODUCT_FEL
+}
+
+/// Attach or detach of a device in FEL mode.
+#[derive(Clone, Debug)]
+pub enum DeviceEvent {
+    /// Device entered FEL mode or was plugged in.
+    Attached(DeviceInfo),
+    /// Device left FEL mode or was unplugged; carries its last known information.
+    Detached(DeviceInfo),
+}
+
+/// Watch of devices in FEL mode attaching and detaching, by USB hotplug events.
+pub struct DeviceWatch {
+    watch: HotplugWatch,
+    attached: HashMap<DeviceId, DeviceInfo>,
+}
+
+impl DeviceWatch {
+    /// Start watching, returning watch and devices already attached.
+    ///
+    /// Hotplug events are subscribed before listing, so no device attaching
+    /// in between is missed.
+    pub fn new() -> std::io::Result<(Self, Vec<DeviceInfo>)> {
+        let watch = nusb::watch_devices()?;
+        let devices = list_devices()?;
+        let attached = devices
+            .iter()
+            .map(|info| (info.id(), info.clone()))
+            .collect();
+        Ok((DeviceWatch { watch, attached }, devices))
+    }
+
+    /// Wait for next event, or return `Ok(None)` once `deadline` passes.
+    pub fn next(&mut self, deadline: Option<Instant>) -> std::io::Result<Option<DeviceEvent>> {
+        loop {
+            let Some(event) = block_on_until(self.watch.next(), deadline) else {
+                return Ok(None);
+            };
+            let Some(event) = event else {
+                return Err(std::io::Error::other("hotplug watch ended"));
+            };
+            match event {
+                HotplugEvent::Connected(info) if is_fel(&info) => {
+                    self.attached.insert(info.id(), info.clone());
+                    return Ok(Some(DeviceEvent::Attached(info)));
+                }
+                HotplugEvent::Disconnected(id) => {
+                    if let Some(info) = self.attached.remove(&id) {
+                        return Ok(Some(DeviceEvent::Detached(info)));
+                    }
+                }
+                HotplugEvent::Connected(_) => {}
+            }
+        }
+    }
+}
+
+/// Wait until a device selected by `selector`, or any device if `None`, is
+/// in FEL mode.
+///
+/// Returns at once if such device is already attached, or `None` on timeout.
+pub fn wait_for_device(
+    selector: Option<&DeviceSelector>,
+    timeout: Option<Duration>,
+) -> std::io::Result<Option<DeviceInfo>> {
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let selected = |info: &DeviceInfo| selector.is_none_or(|selector| selector.matches(info));
+    let (mut watch, devices) = DeviceWatch::new()?;
+    if let Some(info) = devices.into_iter().find(|info| selected(info)) {
+        return Ok(Some(info));
+    }
+    while let Some(event) = watch.next(deadline)? {
+        if let DeviceEvent::Attached(info) = event
+            && selected(&info)
+        {
+            return Ok(Some(info));
+        }
+    }
+    Ok(None)
+}
+
+/// Physical location of a USB device, e.g. `1-2.3` for port 3 of a hub on port 2 of bus 1.
+///
+/// Falls back to bus number and device address like `1:5` on platforms where
+/// nusb does not report ports.
+pub fn port_path(info: &DeviceInfo) -> String {
+    let Some(chain) = port_chain(info) else {
+        return format!("{}:{}", info.bus_number(), info.device_address());
+    };
+    let mut ans = info.bus_number().to_string();
+    for (index, port) in chain.iter().enumerate() {
+        ans.push(if index == 0 { '-' } else { '.' });
+        ans.push_str(&port.to_string());
+    }
+    ans
+}
+
+/// Ports from root hub to device, or `None` if platform does not report them.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn port_chain(info: &DeviceInfo) -> Option<Vec<u8>> {
+    // Sysfs names devices by port path, like `1-2.3`.
+    let name = info.sysfs_path().file_name()?.to_str()?;
+    let (_, ports) = name.split_once('-')?;
+    ports.split('.').map(|port| port.parse().ok()).collect()
+}
+
+/// Ports from root hub to device, or `None` if platform does not report them.
+#[cfg(target_os = "macos")]
+fn port_chain(info: &DeviceInfo) -> Option<Vec<u8>> {
+    // IOKit location ID holds bus number in its top byte, followed by one
+    // nibble per port, ending at first zero nibble.
+    let location = info.location_id();
+    let ports: Vec<u8> = (0..6)
+        .map(|index| ((location >> (20 - 4 * index)) & 0xf) as u8)
+        .take_while(|&port| port != 0)
+        .collect();
+    (!ports.is_empty()).then_some(ports)
+}
+
+/// Ports from root hub to device, or `None` if platform does not report them.
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos")))]
+fn port_chain(_info: &DeviceInfo) -> Option<Vec<u8>> {
+    None
+}
+
+/// Wait until a device re-enumerates in FEL mode on the same port as `info`.
+///
+/// Where ports are not reported, any other FEL device on the same bus counts.
+/// Returns `None` on timeout.
+pub fn wait_for_reenumeration(
+    info: &DeviceInfo,
+    timeout: Duration,
+) -> std::io::Result<Option<DeviceInfo>> {
+    let deadline = Instant::now() + timeout;
+    let chain = port_chain(info);
+    let same_port = |dev: &DeviceInfo| {
+        dev.id() != info.id() && dev.bus_number() == info.bus_number() && port_chain(dev) == chain
+    };
+    let (mut watch, devices) = DeviceWatch::new()?;
+    if let Some(found) = devices.into_iter().find(|dev| same_port(dev)) {
+        return Ok(Some(found));
+    }
+    while let Some(event) = watch.next(Some(deadline))? {
+        if let DeviceEvent::Attached(dev) = event
+            && same_port(&dev)
+        {
+            return Ok(Some(dev));
+        }
+    }
+    Ok(None)
+}
+
+/// Selects one device among all connected FEL devices.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DeviceSelector {
+    /// Bus number and device address, written as `1:5`.
+    BusAddress { bus: u8, address: u8 },
+    /// Bus number and port chain, written as `1-2.3`.
+    PortPath { bus: u8, ports: Vec<u8> },
+    /// USB serial number string.
+    Serial(String),
+}
+
+impl DeviceSelector {
+    /// Check if device matches this selector.
+    pub fn matches(&self, info: &DeviceInfo) -> bool {
+        match self {
+            DeviceSelector::BusAddress { bus, address } => {
+                info.bus_number() == *bus && info.device_address() == *address
+            }
+            DeviceSelector::PortPath { bus, ports } => {
+                info.bus_number() == *bus && port_chain(info).as_ref() == Some(ports)
+            }
+            DeviceSelector::Serial(serial) => info.serial_number() == Some(serial.as_str()),
+        }
+    }
+}
+
+impl FromStr for DeviceSelector {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid device selector {:?}", s);
+        if let Some((bus, address)) = s.split_once(':') {
+            let bus = bus.parse().map_err(|_| invalid())?;
+            let address = address.parse().map_err(|_| invalid())?;
+            return Ok(DeviceSelector::BusAddress { bus, address });
+        }
+        if let Some((bus, ports)) = s.split_once('-')
+            && let Ok(bus) = bus.parse()
+        {
+            let ports = ports
+                .split('.')
+                .map(|port| port.parse())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| invalid())?;
+            return Ok(DeviceSelector::PortPath { bus, ports });
+        }
+        if s.is_empty() {
+            return Err(invalid());
+        }
+        Ok(DeviceSelector::Serial(s.to_string()))
+    }
+}
+
+impl fmt::Display for DeviceSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeviceSelector::BusAddress { bus, address } => write!(f, "{}:{}", bus, address),
+            DeviceSelector::PortPath { bus, ports } => {
+                write!(f, "{}", bus)?;
+                for (index, port) in ports.iter().enumerate() {
+                    write!(f, "{}{}", if index == 0 { '-' } else { '.' }, port)?;
+                }
+                Ok(())
+            }
+            DeviceSelector::Serial(serial) => f.write_str(serial),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DeviceSelector;
+
+    #[test]
+    fn parse_device_selector() {
+        assert_eq!(
+            "1:5".parse(),
+            Ok(DeviceSelector::BusAddress { bus: 1, address: 5 })
+        );
+        assert_eq!(
+            "3-1.4.2".parse(),
+            Ok(DeviceSelector::PortPath {
+                bus: 3,
+                ports: vec![1, 4, 2]
+            })
+        );
+        assert_eq!(
+            "20080411".parse(),
+            Ok(DeviceSelector::Serial("20080411".to_string()))
+        );
+        assert_eq!(
+            "board-7".parse(),
+            Ok(DeviceSelector::Serial("board-7".to_string()))
+        );
+        assert!("1:x".parse::<DeviceSelector>().is_err());
+        assert!("1-2.x".parse::<DeviceSelector>().is_err());
+        assert!("".parse::<DeviceSelector>().is_err());
+        for s in ["1:5", "3-1.4.2", "20080411"] {
+            assert_eq!(s.parse::<DeviceSelector>().unwrap().to_string(), s);
+        }
+    }
+}
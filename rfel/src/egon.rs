@@ -0,0 +1,245 @@
+use core::fmt;
+
+/// eGON.BT0 identifying structure.
+///
+/// Layout matches `allwinner_rt::EgonHead`; on image it comes after the
+/// 4-byte jump instruction, see [`EgonHead::OFFSET`].
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct EgonHead {
+    /// Magic number, ="eGON.BT0".
+    pub magic: [u8; 8],
+    pub checksum: u32,
+    pub length: u32,
+    pub pub_head_size: u32,
+    pub pub_head_version: [u8; 4],
+    pub return_addr: u32,
+    pub run_addr: u32,
+    pub boot_cpu: u32,
+    pub platform: [u8; 8],
+}
+
+impl EgonHead {
+    /// Magic number of eGON.BT0 images.
+    pub const MAGIC: [u8; 8] = *b"eGON.BT0";
+    /// Offset of eGON.BT0 head from the start of image.
+    pub const OFFSET: usize = 4;
+    /// Size of eGON.BT0 head in bytes.
+    pub const SIZE: usize = core::mem::size_of::<EgonHead>();
+    /// Value of checksum field when calculating image checksum.
+    pub const STAMP_VALUE: u32 = 0x5F0A6C39;
+
+    /// Parse eGON.BT0 head from image.
+    ///
+    /// Returns `None` if image is too short or magic number mismatches.
+    pub fn parse(image: &[u8]) -> Option<Self> {
+        let head = image.get(Self::OFFSET..Self::OFFSET + Self::SIZE)?;
+        let word = |offset: usize| u32::from_le_bytes(head[offset..offset + 4].try_into().unwrap());
+        let ans = EgonHead {
+            magic: head[0..8].try_into().unwrap(),
+            checksum: word(8),
+            length: word(12),
+            pub_head_size: word(16),
+            pub_head_version: head[20..24].try_into().unwrap(),
+            return_addr: word(24),
+            run_addr: word(28),
+            boot_cpu: word(32),
+            platform: head[36..44].try_into().unwrap(),
+        };
+        if ans.magic != Self::MAGIC {
+            return None;
+        }
+        Some(ans)
+    }
+
+    /// Calculate eGON.BT0 checksum of image.
+    ///
+    /// Image is summed up as little endian 32-bit words, with checksum field
+    /// regarded as [`EgonHead::STAMP_VALUE`].
+    pub fn checksum(image: &[u8]) -> u32 {
+        stamp_checksum(image, Self::OFFSET + 8)
+    }
+
+    /// Pad image with zeros to a multiple of `align` bytes, then fill its
+    /// length and checksum fields.
+    ///
+    /// Returns `None` if image does not contain an eGON.BT0 head.
+    pub fn finalize(image: &mut Vec<u8>, align: usize) -> Option<Self> {
+        const LENGTH_OFFSET: usize = EgonHead::OFFSET + 12;
+        const CHECKSUM_OFFSET: usize = EgonHead::OFFSET + 8;
+        Self::parse(image)?;
+        // checksum is summed in words, so length is at least word aligned.
+        let align = align.max(4);
+        image.resize(image.len().div_ceil(align) * align, 0);
+        let length = image.len() as u32;
+        image[LENGTH_OFFSET..LENGTH_OFFSET + 4].copy_from_slice(&length.to_le_bytes());
+        let checksum = Self::checksum(image);
+        image[CHECKSUM_OFFSET..CHECKSUM_OFFSET + 4].copy_from_slice(&checksum.to_le_bytes());
+        Self::parse(image)
+    }
+
+    /// Check head, length and checksum of an eGON.BT0 image.
+    ///
+    /// Image may be longer than length in head; trailing bytes are ignored.
+    pub fn verify(image: &[u8]) -> Result<Self, EgonError> {
+        let head = Self::parse(image).ok_or(EgonError::InvalidMagic)?;
+        let length = head.length as usize;
+        if length > image.len() {
+            return Err(EgonError::LengthExceedsFile {
+                length,
+                file: image.len(),
+            });
+        }
+        if !length.is_multiple_of(4) || length < Self::OFFSET + Self::SIZE {
+            return Err(EgonError::InvalidLength(length));
+        }
+        let computed = Self::checksum(&image[..length]);
+        if computed != head.checksum {
+            return Err(EgonError::ChecksumMismatch {
+                head: head.checksum,
+                computed,
+            });
+        }
+        Ok(head)
+    }
+}
+
+/// Sum image as little endian 32-bit words, regarding word at `checksum_offset`
+/// as [`EgonHead::STAMP_VALUE`].
+///
+/// Shared by eGON.BT0, TOC0 and TOC1 images.
+pub(crate) fn stamp_checksum(image: &[u8], checksum_offset: usize) -> u32 {
+    let mut sum = 0u32;
+    for (index, word) in image.chunks(4).enumerate() {
+        let value = if index * 4 == checksum_offset {
+            EgonHead::STAMP_VALUE
+        } else {
+            let mut buf = [0u8; 4];
+            buf[..word.len()].copy_from_slice(word);
+            u32::from_le_bytes(buf)
+        };
+        sum = sum.wrapping_add(value);
+    }
+    sum
+}
+
+/// Error found when verifying an eGON.BT0 image.
+#[derive(Debug, PartialEq, Eq)]
+pub enum EgonError {
+    /// Image does not contain eGON.BT0 magic number.
+    InvalidMagic,
+    /// Length in head is longer than the image.
+    LengthExceedsFile {
+        /// Length in head.
+        length: usize,
+        /// Length of image.
+        file: usize,
+    },
+    /// Length in head is not word aligned or shorter than the head.
+    InvalidLength(usize),
+    /// Checksum in head mismatches checksum of image.
+    ChecksumMismatch {
+        /// Checksum in head.
+        head: u32,
+        /// Checksum computed from image.
+        computed: u32,
+    },
+}
+
+impl fmt::Display for EgonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EgonError::InvalidMagic => write!(f, "not an eGON.BT0 image"),
+            EgonError::LengthExceedsFile { length, file } => write!(
+                f,
+                "eGON.BT0 image length {} exceeds file size {}",
+                length, file
+            ),
+            EgonError::InvalidLength(length) => {
+                write!(f, "invalid eGON.BT0 image length {}", length)
+            }
+            EgonError::ChecksumMismatch { head, computed } => write!(
+                f,
+                "eGON.BT0 checksum mismatch, head has 0x{:08x} but image sums to 0x{:08x}",
+                head, computed
+            ),
+        }
+    }
+}
+
+impl std::error::Error for EgonError {}
+
+#[cfg(test)]
+mod tests {
+    use super::{EgonError, EgonHead};
+
+    fn image(length: u32) -> Vec<u8> {
+        let mut image = vec![0u8; length as usize];
+        image[0..4].copy_from_slice(&0x0000_406fu32.to_le_bytes());
+        image[4..12].copy_from_slice(b"eGON.BT0");
+        image[12..16].copy_from_slice(&EgonHead::STAMP_VALUE.to_le_bytes());
+        image[16..20].copy_from_slice(&length.to_le_bytes());
+        image[24..28].copy_from_slice(b"3000");
+        image
+    }
+
+    #[test]
+    fn egon_head_parse() {
+        let image = image(0x200);
+        let head = EgonHead::parse(&image).unwrap();
+        assert_eq!(head.length, 0x200);
+        assert_eq!(head.pub_head_version, *b"3000");
+        assert!(EgonHead::parse(&image[..0x20]).is_none());
+        let mut bad_magic = image.clone();
+        bad_magic[4] = b'x';
+        assert!(EgonHead::parse(&bad_magic).is_none());
+    }
+
+    #[test]
+    fn egon_head_checksum() {
+        let mut image = image(0x200);
+        let checksum = EgonHead::checksum(&image);
+        assert_eq!(
+            checksum,
+            0x0000_406fu32
+                .wrapping_add(u32::from_le_bytes(*b"eGON"))
+                .wrapping_add(u32::from_le_bytes(*b".BT0"))
+                .wrapping_add(EgonHead::STAMP_VALUE)
+                .wrapping_add(0x200)
+                .wrapping_add(u32::from_le_bytes(*b"3000"))
+        );
+        // checksum field itself does not take part in calculation.
+        image[12..16].copy_from_slice(&checksum.to_le_bytes());
+        assert_eq!(EgonHead::checksum(&image), checksum);
+    }
+
+    #[test]
+    fn egon_finalize_verify() {
+        let mut image = image(0x200);
+        image.truncate(0x1234);
+        image.resize(0x1235, 0xaa);
+        assert!(matches!(
+            EgonHead::verify(&image),
+            Err(EgonError::ChecksumMismatch { .. })
+        ));
+        let head = EgonHead::finalize(&mut image, 0x2000).unwrap();
+        assert_eq!(image.len(), 0x2000);
+        assert_eq!(head.length, 0x2000);
+        assert_eq!(head.checksum, EgonHead::checksum(&image));
+        assert_eq!(EgonHead::verify(&image).unwrap().checksum, head.checksum);
+
+        image[0x100] ^= 1;
+        assert!(matches!(
+            EgonHead::verify(&image),
+            Err(EgonError::ChecksumMismatch { .. })
+        ));
+        assert_eq!(
+            EgonHead::verify(&image[..0x1000]).unwrap_err(),
+            EgonError::LengthExceedsFile {
+                length: 0x2000,
+                file: 0x1000
+            }
+        );
+        assert!(EgonHead::finalize(&mut vec![0u8; 0x100], 0x2000).is_none());
+    }
+}
@@ -0,0 +1,153 @@
+use core::fmt;
+use nusb::transfer::TransferError;
+
+/// Error of an FEL operation.
+#[derive(Debug)]
+pub enum Error {
+    /// Device does not provide one bulk in and one bulk out endpoint.
+    MalformedDevice,
+    /// USB transfer failed.
+    Usb(TransferError),
+    /// USB transfer did not complete in time.
+    Timeout,
+    /// Response magic number mismatches, e.g. not an `AWUS` response.
+    InvalidMagic {
+        /// Magic number expected by protocol.
+        expected: &'static [u8],
+        /// Bytes actually received.
+        found: Vec<u8>,
+    },
+    /// Device returned fewer bytes than requested.
+    ShortRead {
+        /// Requested length in bytes.
+        expected: usize,
+        /// Received length in bytes.
+        actual: usize,
+    },
+    /// USB response reported a non-zero status.
+    UsbStatus(u8),
+    /// FEL status reported a non-zero state.
+    FelStatus(u8),
+    /// Flash chip is neither known by JEDEC ID nor describes itself by SFDP.
+    UnknownFlash {
+        /// JEDEC manufacturer and device ID.
+        id: [u8; 3],
+    },
+    /// Data read back differs from data written.
+    VerifyMismatch {
+        /// First address where data differs.
+        address: u32,
+    },
+    /// Flash reported an uncorrectable ECC error when reading a page.
+    EccUncorrectable {
+        /// Address of the page.
+        address: u32,
+    },
+    /// Flash reported failure programming a page.
+    ProgramFailed {
+        /// Address of the page.
+        address: u32,
+    },
+    /// Flash reported failure erasing a block.
+    EraseFailed {
+        /// Address of the block.
+        address: u32,
+    },
+    /// Not enough good blocks left on flash to hold the data.
+    OutOfSpace,
+    /// Memory region reaches beyond the 32-bit address space.
+    AddressOverflow {
+        /// Start address of the region.
+        address: u32,
+        /// Length of the region in bytes.
+        length: usize,
+    },
+    /// SD/MMC helper failed to initialize the card.
+    CardInitFailed,
+    /// Access reaches beyond end of SD/MMC card.
+    CardOutOfRange {
+        /// Card size in 512-byte blocks.
+        blocks: u32,
+    },
+    /// SD/MMC card reported an error reading blocks.
+    CardReadFailed,
+    /// SD/MMC card reported an error writing blocks.
+    CardWriteFailed,
+    /// SD/MMC helper reported an unexpected status, or did not run at all.
+    HelperStatus(u32),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::MalformedDevice => write!(
+                f,
+                "malformed device, Allwinner USB FEL device should include exactly one bulk in and one bulk out endpoint"
+            ),
+            Error::Usb(e) => write!(f, "USB transfer error: {}", e),
+            Error::Timeout => write!(f, "USB transfer timed out"),
+            Error::InvalidMagic { expected, found } => write!(
+                f,
+                "invalid response magic, expected {:?} but found {:?}",
+                String::from_utf8_lossy(expected),
+                String::from_utf8_lossy(found)
+            ),
+            Error::ShortRead { expected, actual } => write!(
+                f,
+                "short read, expected {} bytes but received {}",
+                expected, actual
+            ),
+            Error::UsbStatus(status) => write!(f, "USB response status 0x{:02x}", status),
+            Error::FelStatus(state) => write!(f, "FEL status 0x{:02x}", state),
+            Error::UnknownFlash { id } => write!(
+                f,
+                "unknown flash with JEDEC ID {:02x}{:02x}{:02x}",
+                id[0], id[1], id[2]
+            ),
+            Error::VerifyMismatch { address } => {
+                write!(f, "verify failed at address 0x{:08x}", address)
+            }
+            Error::EccUncorrectable { address } => {
+                write!(f, "uncorrectable ECC error in page 0x{:08x}", address)
+            }
+            Error::ProgramFailed { address } => {
+                write!(f, "program failed in page 0x{:08x}", address)
+            }
+            Error::EraseFailed { address } => {
+                write!(f, "erase failed in block 0x{:08x}", address)
+            }
+            Error::OutOfSpace => write!(f, "not enough good blocks left on flash"),
+            Error::AddressOverflow { address, length } => write!(
+                f,
+                "region of 0x{:x} bytes at 0x{:08x} exceeds 32-bit address space",
+                length, address
+            ),
+            Error::CardInitFailed => write!(f, "SD/MMC card initialization failed"),
+            Error::CardOutOfRange { blocks } => {
+                write!(f, "access beyond end of card with {} blocks", blocks)
+            }
+            Error::CardReadFailed => write!(f, "SD/MMC card read failed"),
+            Error::CardWriteFailed => write!(f, "SD/MMC card write failed"),
+            Error::HelperStatus(status) => write!(f, "SD/MMC helper status 0x{:08x}", status),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Usb(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<TransferError> for Error {
+    #[inline]
+    fn from(value: TransferError) -> Self {
+        Error::Usb(value)
+    }
+}
+
+/// Result type of FEL operations.
+pub type Result<T> = core::result::Result<T, Error>;
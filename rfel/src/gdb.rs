@@ -0,0 +1,433 @@
+//! GDB remote serial protocol server over FEL memory access.
+//!
+//! FEL gives no access to CPU registers of the halted BROM, so the server
+//! exposes chip memory only: GDB reads and writes memory, `load` uploads code,
+//! and `continue` runs it through the FEL exec request, reporting a stop once
+//! the code returns to FEL. Register reads report values as unavailable,
+//! except program counter written by GDB, which `continue` starts from.
+//!
+//! Besides `continue`, code can be started with `monitor exec <address>`;
+//! `monitor reset` resets chip through its watchdog.
+
+use crate::{Arch, Fel, FelTransport};
+use log::debug;
+use std::io::{self, Read, Write};
+
+/// Largest packet accepted, reported to GDB in `qSupported`.
+const PACKET_SIZE: usize = 0x4000;
+
+/// Stop reply reporting SIGTRAP, sent once code returns to FEL.
+const STOPPED: &[u8] = b"S05";
+
+/// Reply to a GDB packet.
+#[derive(Debug, PartialEq, Eq)]
+enum Reply {
+    /// Send packet and wait for next one.
+    Packet(Vec<u8>),
+    /// Send packet and close connection.
+    Detach(Vec<u8>),
+    /// Close connection without reply.
+    Kill,
+}
+
+impl Reply {
+    fn ok() -> Self {
+        Reply::Packet(b"OK".to_vec())
+    }
+    fn error() -> Self {
+        Reply::Packet(b"E01".to_vec())
+    }
+    fn unsupported() -> Self {
+        Reply::Packet(Vec::new())
+    }
+}
+
+/// GDB server operating memory of one FEL device.
+pub struct GdbServer<'a, T> {
+    fel: &'a Fel<T>,
+    /// GDB register number of program counter, if chip is known.
+    pc_regnum: Option<usize>,
+    /// Size of each register in bytes.
+    reg_size: usize,
+    /// Program counter written by GDB, e.g. entry point after `load`.
+    pc: Option<u32>,
+}
+
+impl<'a, T: FelTransport> GdbServer<'a, T> {
+    /// Create server for given FEL device.
+    pub fn new(fel: &'a Fel<T>) -> crate::Result<Self> {
+        let chip = fel.chip()?;
+        let pc_regnum = chip.map(|chip| match chip.info().arch {
+            Arch::Arm => 15,
+            Arch::RiscV => 32,
+        });
+        let reg_size = chip.map_or(4, |chip| chip.info().xlen as usize / 8);
+        Ok(Self {
+            fel,
+            pc_regnum,
+            reg_size,
+            pc: None,
+        })
+    }
+
+    /// Serve one GDB connection until it detaches, kills or disconnects.
+    pub fn serve<S: Read + Write>(&mut self, stream: S) -> io::Result<()> {
+        let mut conn = Connection::new(stream);
+        while let Some(byte) = conn.read_byte()? {
+            match byte {
+                b'$' => {
+                    let Some(packet) = conn.read_packet()? else {
+                        conn.stream.write_all(b"-")?;
+                        continue;
+                    };
+                    conn.stream.write_all(b"+")?;
+                    debug!("gdb <- {}", String::from_utf8_lossy(&packet));
+                    match self.handle(&packet) {
+                        Reply::Packet(reply) => conn.write_packet(&reply)?,
+                        Reply::Detach(reply) => return conn.write_packet(&reply),
+                        Reply::Kill => return Ok(()),
+                    }
+                }
+                // interrupt request; code runs to completion, so target is already stopped.
+                0x03 => conn.write_packet(b"S02")?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn handle(&mut self, packet: &[u8]) -> Reply {
+        let (&command, args) = match packet.split_first() {
+            Some(split) => split,
+            None => return Reply::unsupported(),
+        };
+        match command {
+            b'?' => Reply::Packet(STOPPED.to_vec()),
+            b'q' if args.starts_with(b"Supported") => {
+                Reply::Packet(format!("PacketSize={:x}", PACKET_SIZE).into_bytes())
+            }
+            b'q' if args == b"Attached" => Reply::Packet(b"1".to_vec()),
+            b'q' if args.starts_with(b"Rcmd,") => self.monitor(&args[5..]),
+            b'H' => Reply::ok(),
+            b'g' => Reply::Packet(vec![b'x'; self.reg_size * 2]),
+            b'p' => match parse_hex(args) {
+                Some(regnum) if Some(regnum) == self.pc_regnum && self.pc.is_some() => {
+                    let mut buf = vec![0u8; self.reg_size];
+                    buf[..4].copy_from_slice(&self.pc.unwrap().to_le_bytes());
+                    Reply::Packet(hex(&buf))
+                }
+                Some(_) => Reply::Packet(vec![b'x'; self.reg_size * 2]),
+                None => Reply::error(),
+            },
+            b'P' => {
+                let Some((regnum, value)) = split_once(args, b'=') else {
+                    return Reply::error();
+                };
+                let (Some(regnum), Some(value)) = (parse_hex::<usize>(regnum), unhex(value)) else {
+                    return Reply::error();
+                };
+                if Some(regnum) == self.pc_regnum {
+                    let mut buf = [0u8; 4];
+                    let len = value.len().min(4);
+                    buf[..len].copy_from_slice(&value[..len]);
+                    self.pc = Some(u32::from_le_bytes(buf));
+                }
+                Reply::ok()
+            }
+            b'm' => {
+                let Some((address, length)) = parse_range(args) else {
+                    return Reply::error();
+                };
+                let mut buf = vec![0u8; length.min(PACKET_SIZE / 2)];
+                match self.fel.read_address(address, &mut buf) {
+                    Ok(_) => Reply::Packet(hex(&buf)),
+                    Err(e) => {
+                        debug!("gdb memory read failed: {}", e);
+                        Reply::error()
+                    }
+                }
+            }
+            b'M' | b'X' => {
+                let Some((range, data)) = split_once(args, b':') else {
+                    return Reply::error();
+                };
+                let Some((address, length)) = parse_range(range) else {
+                    return Reply::error();
+                };
+                let data = if command == b'M' {
+                    unhex(data)
+                } else {
+                    Some(unescape(data))
+                };
+                let Some(data) = data.filter(|data| data.len() == length) else {
+                    return Reply::error();
+                };
+                match self.fel.write_address(address, &data) {
+                    Ok(_) => Reply::ok(),
+                    Err(e) => {
+                        debug!("gdb memory write failed: {}", e);
+                        Reply::error()
+                    }
+                }
+            }
+            b'c' => {
+                let address = if args.is_empty() {
+                    self.pc
+                } else {
+                    parse_hex(args)
+                };
+                match address {
+                    Some(address) => self.exec(address),
+                    None => Reply::error(),
+                }
+            }
+            b'D' => Reply::Detach(b"OK".to_vec()),
+            b'k' => Reply::Kill,
+            _ => Reply::unsupported(),
+        }
+    }
+
+    fn exec(&self, address: u32) -> Reply {
+        match self.fel.exec(address) {
+            Ok(()) => Reply::Packet(STOPPED.to_vec()),
+            Err(e) => {
+                debug!("gdb exec failed: {}", e);
+                Reply::error()
+            }
+        }
+    }
+
+    fn monitor(&self, command: &[u8]) -> Reply {
+        let Some(command) = unhex(command) else {
+            return Reply::error();
+        };
+        let command = String::from_utf8_lossy(&command);
+        let mut words = command.split_whitespace();
+        match (words.next(), words.next()) {
+            (Some("exec"), Some(address)) => {
+                let address = address.trim_start_matches("0x");
+                match u32::from_str_radix(address, 16) {
+                    Ok(address) => match self.exec(address) {
+                        Reply::Packet(reply) if reply == STOPPED => Reply::ok(),
+                        reply => reply,
+                    },
+                    Err(_) => Reply::error(),
+                }
+            }
+            (Some("reset"), None) => match self.fel.reset() {
+                Ok(true) => Reply::ok(),
+                Ok(false) | Err(_) => Reply::error(),
+            },
+            _ => Reply::Packet(hex(b"commands: exec <address>, reset\n")),
+        }
+    }
+}
+
+/// Byte stream to GDB with read buffering.
+struct Connection<S> {
+    stream: S,
+    buf: Box<[u8]>,
+    start: usize,
+    end: usize,
+}
+
+impl<S: Read + Write> Connection<S> {
+    fn new(stream: S) -> Self {
+        Self {
+            stream,
+            buf: vec![0u8; 4096].into_boxed_slice(),
+            start: 0,
+            end: 0,
+        }
+    }
+
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        if self.start == self.end {
+            self.start = 0;
+            self.end = self.stream.read(&mut self.buf)?;
+            if self.end == 0 {
+                return Ok(None);
+            }
+        }
+        self.start += 1;
+        Ok(Some(self.buf[self.start - 1]))
+    }
+
+    /// Read packet body after `$`, returning `None` if checksum mismatches.
+    fn read_packet(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut packet = Vec::new();
+        let mut sum = 0u8;
+        loop {
+            let byte = self.read_byte()?.ok_or(io::ErrorKind::UnexpectedEof)?;
+            if byte == b'#' {
+                break;
+            }
+            sum = sum.wrapping_add(byte);
+            packet.push(byte);
+        }
+        let mut checksum = [0u8; 2];
+        for byte in &mut checksum {
+            *byte = self.read_byte()?.ok_or(io::ErrorKind::UnexpectedEof)?;
+        }
+        Ok((unhex(&checksum) == Some(vec![sum])).then_some(packet))
+    }
+
+    fn write_packet(&mut self, data: &[u8]) -> io::Result<()> {
+        debug!("gdb -> {}", String::from_utf8_lossy(data));
+        let sum = data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+        let mut packet = Vec::with_capacity(data.len() + 4);
+        packet.push(b'$');
+        packet.extend_from_slice(data);
+        packet.push(b'#');
+        packet.extend_from_slice(&hex(&[sum]));
+        self.stream.write_all(&packet)?;
+        self.stream.flush()
+    }
+}
+
+fn hex(data: &[u8]) -> Vec<u8> {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+    data.iter()
+        .flat_map(|&b| [DIGITS[(b >> 4) as usize], DIGITS[(b & 0xf) as usize]])
+        .collect()
+}
+
+fn unhex(data: &[u8]) -> Option<Vec<u8>> {
+    if !data.len().is_multiple_of(2) {
+        return None;
+    }
+    data.chunks(2)
+        .map(|pair| u8::from_str_radix(core::str::from_utf8(pair).ok()?, 16).ok())
+        .collect()
+}
+
+/// Decode binary data of `X` packet, where `}` escapes next byte XORed with 0x20.
+fn unescape(data: &[u8]) -> Vec<u8> {
+    let mut ans = Vec::with_capacity(data.len());
+    let mut iter = data.iter();
+    while let Some(&byte) = iter.next() {
+        match byte {
+            b'}' => ans.extend(iter.next().map(|b| b ^ 0x20)),
+            _ => ans.push(byte),
+        }
+    }
+    ans
+}
+
+fn parse_hex<N: num_traits::Num>(data: &[u8]) -> Option<N> {
+    N::from_str_radix(core::str::from_utf8(data).ok()?, 16).ok()
+}
+
+/// Parse `address,length` in hexadecimal.
+fn parse_range(data: &[u8]) -> Option<(u32, usize)> {
+    let (address, length) = split_once(data, b',')?;
+    Some((parse_hex(address)?, parse_hex(length)?))
+}
+
+fn split_once(data: &[u8], delimiter: u8) -> Option<(&[u8], &[u8])> {
+    let index = data.iter().position(|&b| b == delimiter)?;
+    Some((&data[..index], &data[index + 1..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GdbServer, Reply, hex};
+    use crate::{Fel, mock::MockDevice};
+    use std::{
+        cell::Cell,
+        io::{self, Read, Write},
+        rc::Rc,
+    };
+
+    struct Stream {
+        input: io::Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for Stream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for Stream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn packet(data: &str) -> String {
+        let sum = data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+        format!("${}#{:02x}", data, sum)
+    }
+
+    #[test]
+    fn gdb_memory_and_exec() {
+        let fel = Fel::new(MockDevice::new(0x00185900, 0x7e00));
+        fel.transport()
+            .write_memory(0x4000_0000, &[0x12, 0x34, 0x56]);
+        let executed = Rc::new(Cell::new(None));
+        let executed_1 = executed.clone();
+        fel.transport().set_exec_handler(move |address, _| {
+            executed_1.set(Some(address));
+            Ok(())
+        });
+        let mut server = GdbServer::new(&fel).unwrap();
+        assert_eq!(
+            server.handle(b"m40000000,3"),
+            Reply::Packet(b"123456".to_vec())
+        );
+        assert_eq!(server.handle(b"M40000001,2:abcd"), Reply::ok());
+        assert_eq!(server.handle(b"X40000010,2:}\x03}]"), Reply::ok());
+        let mut buf = [0u8; 3];
+        fel.transport().read_memory(0x4000_0000, &mut buf);
+        assert_eq!(buf, [0x12, 0xab, 0xcd]);
+        let mut buf = [0u8; 2];
+        fel.transport().read_memory(0x4000_0010, &mut buf);
+        assert_eq!(buf, [0x23, 0x7d]);
+        assert_eq!(server.handle(b"M40000000,2:ab"), Reply::error());
+        // D1 is RISC-V, whose program counter is register 32.
+        assert_eq!(server.handle(b"P20=0000024000000000"), Reply::ok());
+        assert_eq!(
+            server.handle(b"p20"),
+            Reply::Packet(b"0000024000000000".to_vec())
+        );
+        // D1 is RV64, so other registers are unavailable 8-byte values.
+        assert_eq!(
+            server.handle(b"p1"),
+            Reply::Packet(b"xxxxxxxxxxxxxxxx".to_vec())
+        );
+        assert_eq!(server.handle(b"c"), Reply::Packet(b"S05".to_vec()));
+        assert_eq!(executed.get(), Some(0x4002_0000));
+        let monitor = format!("qRcmd,{}", String::from_utf8(hex(b"exec 0x20000")).unwrap());
+        assert_eq!(server.handle(monitor.as_bytes()), Reply::ok());
+        assert_eq!(executed.get(), Some(0x2_0000));
+    }
+
+    #[test]
+    fn gdb_serve_packets() {
+        let fel = Fel::new(MockDevice::new(0x00185900, 0x7e00));
+        fel.transport().write_memory(0x2_0000, &[0xde, 0xad]);
+        let input = format!(
+            "+{}$m0,1#00{}{}",
+            packet("qSupported:multiprocess+"),
+            packet("m20000,2"),
+            packet("D")
+        );
+        let mut stream = Stream {
+            input: io::Cursor::new(input.into_bytes()),
+            output: Vec::new(),
+        };
+        GdbServer::new(&fel).unwrap().serve(&mut stream).unwrap();
+        let expected = format!(
+            "+{}-+{}+{}",
+            packet("PacketSize=4000"),
+            packet("dead"),
+            packet("OK")
+        );
+        assert_eq!(String::from_utf8(stream.output).unwrap(), expected);
+    }
+}
@@ -1,131 +1,554 @@
 use core::fmt;
-use futures::executor::block_on;
-use log::{debug, error, trace};
-use nusb::transfer::EndpointType;
-
-pub struct Fel<'a> {
-    iface: &'a mut nusb::Interface,
-    endpoint_in: u8,
-    endpoint_out: u8,
+use log::trace;
+use std::time::Duration;
+
+mod chip;
+pub mod crc32;
+pub mod ddr;
+pub mod device;
+mod egon;
+mod error;
+pub mod gdb;
+pub mod mmc;
+pub mod mock;
+pub mod payload;
+pub mod regs;
+pub mod script;
+mod sid;
+pub mod spi;
+pub mod spinand;
+pub mod spinor;
+pub mod toc;
+mod transport;
+pub use chip::{Arch, Chip, ChipInfo, Region};
+pub use egon::{EgonError, EgonHead};
+pub use error::{Error, Result};
+pub use sid::Sid;
+use transport::block_on;
+pub use transport::{FelTransport, Transfer, UsbTransport};
+
+/// FEL connection whose operations are `async`, for use from async executors.
+pub struct AsyncFel<T> {
+    transport: T,
     version: Option<Version>,
+    chunk_size: usize,
+}
+
+/// Blocking FEL connection, running each [`AsyncFel`] operation to completion.
+pub struct Fel<T> {
+    inner: AsyncFel<T>,
 }
 
-const CHUNK_SIZE: usize = 65536;
+/// Default bytes carried by each FEL read or write request.
+const DEFAULT_CHUNK_SIZE: usize = 65536;
+/// Bytes processed by each payload run, keeping runs well within USB timeout.
+const RUN_SIZE: usize = 1 << 20;
 
-impl<'a> Fel<'a> {
+impl<'a> AsyncFel<UsbTransport<'a>> {
     #[inline]
-    pub fn open_interface(iface: &'a mut nusb::Interface) -> Result<Self, ()> {
-        let mut endpoint_in = None;
-        let mut endpoint_out = None;
-        for descriptor in iface.descriptors() {
-            for endpoint in descriptor.endpoints() {
-                if endpoint.transfer_type() != EndpointType::Bulk {
-                    continue;
-                }
-                match endpoint.direction() {
-                    nusb::transfer::Direction::In => endpoint_in = Some(endpoint.address()),
-                    nusb::transfer::Direction::Out => endpoint_out = Some(endpoint.address()),
-                }
-            }
-        }
-        let (Some(endpoint_in), Some(endpoint_out)) = (endpoint_in, endpoint_out) else {
-            error!(
-                "Malformed device. Allwinner USB FEL device should include exactly one bulk in and one bulk out endpoint."
-            );
-            return Err(());
-        };
-        debug!(
-            "Endpoint in ID 0x{:x}, out ID 0x{:x}",
-            endpoint_in, endpoint_out
-        );
-        Ok(Self {
-            iface,
-            endpoint_in,
-            endpoint_out,
+    pub fn open_interface(iface: &'a mut nusb::Interface) -> Result<Self> {
+        UsbTransport::open_interface(iface).map(Self::new)
+    }
+
+    /// Set timeout of each USB transfer.
+    #[inline]
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.transport.set_timeout(timeout);
+    }
+}
+
+impl<T: FelTransport> AsyncFel<T> {
+    /// Create an FEL connection over given transport.
+    #[inline]
+    pub const fn new(transport: T) -> Self {
+        Self {
+            transport,
             version: None,
-        })
+            chunk_size: DEFAULT_CHUNK_SIZE,
+        }
     }
 
-    pub fn get_version(&self) -> Version {
-        self.version.unwrap_or_else(|| {
-            let mut buf = [0u8; 32];
-            self.send_fel_request(FelRequest::get_version());
-            self.usb_read(&mut buf);
-            self.read_fel_status();
-            buf.into()
-        })
+    /// Set bytes carried by each FEL read or write request, 64 KiB by default.
+    ///
+    /// Larger chunks take fewer request handshakes per transfer.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is zero.
+    #[inline]
+    pub fn set_chunk_size(&mut self, size: usize) {
+        assert!(size > 0, "chunk size should not be zero");
+        self.chunk_size = size;
+    }
+
+    /// Get bytes carried by each FEL read or write request.
+    #[inline]
+    pub const fn chunk_size(&self) -> usize {
+        self.chunk_size
     }
 
-    pub fn read_address(&self, address: u32, buf: &mut [u8]) -> usize {
+    /// Get reference to underlying transport.
+    #[inline]
+    pub const fn transport(&self) -> &T {
+        &self.transport
+    }
+
+    pub async fn get_version(&self) -> Result<Version> {
+        if let Some(version) = self.version {
+            return Ok(version);
+        }
+        let mut buf = [0u8; 32];
+        self.send_fel_request(FelRequest::get_version()).await?;
+        self.usb_read(&mut buf).await?;
+        self.read_fel_status().await?;
+        Ok(buf.into())
+    }
+
+    /// Read chip memory into `buf`, keeping requests of all chunks pipelined.
+    pub async fn read_address(&self, address: u32, buf: &mut [u8]) -> Result<usize> {
         trace!("read_address");
-        for chunk in buf.chunks_mut(CHUNK_SIZE) {
-            self.send_fel_request(FelRequest::read_raw(address, chunk.len() as u32));
-            self.usb_read(chunk);
-            self.read_fel_status();
+        check_range(address, buf.len())?;
+        let chunk_size = self.chunk_size;
+        let length = buf.len();
+        let transfers = (0..length).step_by(chunk_size).flat_map(move |offset| {
+            let len = (length - offset).min(chunk_size);
+            let request: [u8; 16] =
+                FelRequest::read_raw(address + offset as u32, len as u32).into();
+            usb_write_transfers(request.to_vec())
+                .into_iter()
+                .chain(usb_read_transfers(len))
+                .chain(usb_read_transfers(8))
+        });
+        let received = self.transport.pipeline(transfers).await?;
+        // per chunk: request response, data, data response, status, status response
+        for (chunk, received) in buf.chunks_mut(chunk_size).zip(received.chunks(5)) {
+            check_usb_response(&received[0])?;
+            check_length(&received[1], chunk.len())?;
+            check_usb_response(&received[2])?;
+            check_fel_status(&received[3])?;
+            check_usb_response(&received[4])?;
+            chunk.copy_from_slice(&received[1]);
         }
-        buf.len()
+        Ok(buf.len())
     }
 
-    pub fn write_address(&self, address: u32, buf: &[u8]) -> usize {
+    /// Write `buf` into chip memory, keeping requests of all chunks pipelined.
+    pub async fn write_address(&self, address: u32, buf: &[u8]) -> Result<usize> {
         trace!("write_address");
-        for chunk in buf.chunks(CHUNK_SIZE) {
-            self.send_fel_request(FelRequest::write_raw(address, chunk.len() as u32));
-            self.usb_write(chunk);
-            self.read_fel_status();
+        check_range(address, buf.len())?;
+        let chunk_size = self.chunk_size;
+        // chunks are sliced by offset, as futures holding closures over borrowed
+        // chunk arguments do not prove to be `Send`.
+        let transfers = (0..buf.len()).step_by(chunk_size).flat_map(move |offset| {
+            let chunk = &buf[offset..buf.len().min(offset + chunk_size)];
+            let request: [u8; 16] =
+                FelRequest::write_raw(address + offset as u32, chunk.len() as u32).into();
+            usb_write_transfers(request.to_vec())
+                .into_iter()
+                .chain(usb_write_transfers(chunk.to_vec()))
+                .chain(usb_read_transfers(8))
+        });
+        let received = self.transport.pipeline(transfers).await?;
+        // per chunk: request response, data response, status, status response
+        for received in received.chunks(4) {
+            check_usb_response(&received[0])?;
+            check_usb_response(&received[1])?;
+            check_fel_status(&received[2])?;
+            check_usb_response(&received[3])?;
         }
-        buf.len()
+        Ok(buf.len())
     }
 
-    fn send_fel_request(&self, request: FelRequest) {
+    pub async fn exec(&self, address: u32) -> Result<()> {
+        trace!("exec");
+        self.send_fel_request(FelRequest::exec(address)).await?;
+        self.read_fel_status().await
+    }
+
+    async fn send_fel_request(&self, request: FelRequest) -> Result<()> {
         trace!("send_fel_request");
         let buf: [u8; 16] = request.into();
-        self.usb_write(&buf);
+        self.usb_write(&buf).await
     }
 
-    fn read_fel_status(&self) {
+    async fn read_fel_status(&self) -> Result<()> {
         trace!("read_fel_status");
         let mut buf = [0u8; 8];
-        self.usb_read(&mut buf);
+        self.usb_read(&mut buf).await?;
+        check_fel_status(&buf)
     }
 
-    fn usb_read(&self, buf: &mut [u8]) {
+    async fn usb_read(&self, buf: &mut [u8]) -> Result<()> {
         trace!("usb_read");
-        let buf_1: [u8; 36] = UsbRequest::usb_read(buf.len() as u32).into();
-        block_on(self.iface.bulk_out(self.endpoint_out, buf_1.to_vec()))
-            .status
-            .expect("send_usb_request on usb_read transfer");
-        let buf_2 = nusb::transfer::RequestBuffer::new(buf.len());
-        let ans = block_on(self.iface.bulk_in(self.endpoint_in, buf_2));
-        ans.status.expect("usb bulk out on usb_read transfer");
-        let buf_3 = nusb::transfer::RequestBuffer::new(13);
-        let ans_1 = block_on(self.iface.bulk_in(self.endpoint_in, buf_3));
-        ans_1
-            .status
-            .expect("read_usb_response on usb_read transfer");
-        if ans_1.data != *b"AWUS\0\0\0\0\0\0\0\0\0" {
-            panic!("invalid data received from read_usb_response")
-        }
-        buf.copy_from_slice(&ans.data);
+        let received = self
+            .transport
+            .pipeline(usb_read_transfers(buf.len()))
+            .await?;
+        check_usb_response(&received[1])?;
+        check_length(&received[0], buf.len())?;
+        buf.copy_from_slice(&received[0]);
+        Ok(())
     }
 
-    fn usb_write(&self, buf: &[u8]) {
+    async fn usb_write(&self, buf: &[u8]) -> Result<()> {
         trace!("usb_write");
-        let buf_1: [u8; 36] = UsbRequest::usb_write(buf.len() as u32).into();
-        block_on(self.iface.bulk_out(self.endpoint_out, buf_1.to_vec()))
-            .status
-            .expect("send_usb_request on usb_write transfer");
-        block_on(self.iface.bulk_out(self.endpoint_out, buf.to_vec()))
-            .status
-            .expect("usb bulk out on usb_write transfer");
-        let buf_3 = nusb::transfer::RequestBuffer::new(13);
-        let ans_1 = block_on(self.iface.bulk_in(self.endpoint_in, buf_3));
-        ans_1
-            .status
-            .expect("read_usb_response on usb_write transfer");
-        if ans_1.data != *b"AWUS\0\0\0\0\0\0\0\0\0" {
-            panic!("invalid data received from read_usb_response")
+        let received = self
+            .transport
+            .pipeline(usb_write_transfers(buf.to_vec()))
+            .await?;
+        check_usb_response(&received[0])
+    }
+}
+
+impl<'a> Fel<UsbTransport<'a>> {
+    #[inline]
+    pub fn open_interface(iface: &'a mut nusb::Interface) -> Result<Self> {
+        AsyncFel::open_interface(iface).map(Self::from)
+    }
+
+    /// Set timeout of each USB transfer.
+    #[inline]
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.inner.set_timeout(timeout);
+    }
+}
+
+impl<T> From<AsyncFel<T>> for Fel<T> {
+    #[inline]
+    fn from(inner: AsyncFel<T>) -> Self {
+        Fel { inner }
+    }
+}
+
+impl<T: FelTransport> Fel<T> {
+    /// Create an FEL connection over given transport.
+    #[inline]
+    pub const fn new(transport: T) -> Self {
+        Fel {
+            inner: AsyncFel::new(transport),
         }
     }
+
+    /// Set bytes carried by each FEL read or write request, 64 KiB by default.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is zero.
+    #[inline]
+    pub fn set_chunk_size(&mut self, size: usize) {
+        self.inner.set_chunk_size(size);
+    }
+
+    /// Get bytes carried by each FEL read or write request.
+    #[inline]
+    pub const fn chunk_size(&self) -> usize {
+        self.inner.chunk_size()
+    }
+
+    /// Get reference to underlying transport.
+    #[inline]
+    pub const fn transport(&self) -> &T {
+        self.inner.transport()
+    }
+
+    /// Get reference to async connection this one wraps.
+    #[inline]
+    pub const fn as_async(&self) -> &AsyncFel<T> {
+        &self.inner
+    }
+
+    pub fn get_version(&self) -> Result<Version> {
+        block_on(self.inner.get_version())
+    }
+
+    /// Detect connected chip.
+    ///
+    /// D1 and T113 share one chip ID; they are told apart by instruction set
+    /// of the BROM, whose first word is an ARM branch on T113.
+    pub fn chip(&self) -> Result<Option<Chip>> {
+        let chip = self.get_version()?.chip();
+        if chip != Some(Chip::D1) {
+            return Ok(chip);
+        }
+        let mut buf = [0u8; 4];
+        self.read_address(0, &mut buf)?;
+        if u32::from_le_bytes(buf) >> 24 == 0xea {
+            Ok(Some(Chip::T113))
+        } else {
+            Ok(Some(Chip::D1))
+        }
+    }
+
+    /// Read security ID of connected chip.
+    ///
+    /// Returns `None` if chip is not supported.
+    pub fn read_sid(&self) -> Result<Option<Sid>> {
+        let Some(chip) = self.chip()? else {
+            return Ok(None);
+        };
+        let mut buf = [0u8; Sid::READ_SIZE];
+        self.read_address(chip.info().sid, &mut buf)?;
+        Ok(Some(Sid::parse(chip, &buf)))
+    }
+
+    /// Reset connected chip through its watchdog.
+    ///
+    /// Returns `false` if chip is not supported. Watchdog fires after its
+    /// shortest interval, so the device detaches shortly after this returns.
+    pub fn reset(&self) -> Result<bool> {
+        let Some(chip) = self.chip()? else {
+            return Ok(false);
+        };
+        for &(address, value) in chip.info().watchdog_reset {
+            self.write_address(address, &value.to_le_bytes())?;
+        }
+        Ok(true)
+    }
+
+    /// Read chip memory into `buf`, keeping requests of all chunks pipelined.
+    pub fn read_address(&self, address: u32, buf: &mut [u8]) -> Result<usize> {
+        block_on(self.inner.read_address(address, buf))
+    }
+
+    /// Write `buf` into chip memory, keeping requests of all chunks pipelined.
+    pub fn write_address(&self, address: u32, buf: &[u8]) -> Result<usize> {
+        block_on(self.inner.write_address(address, buf))
+    }
+
+    pub fn exec(&self, address: u32) -> Result<()> {
+        block_on(self.inner.exec(address))
+    }
+
+    /// Calculate CRC-32 of chip memory region.
+    ///
+    /// On known chips a payload calculates it on chip in the scratch region,
+    /// avoiding read back of the whole region; other chips fall back to reading
+    /// memory back to host.
+    pub fn crc32(&self, address: u32, length: usize) -> Result<u32> {
+        check_range(address, length)?;
+        let mut state = crc32::INIT;
+        let Some(base) = self.load_payload(&payload::CRC32_ARM, &payload::CRC32_RISCV)? else {
+            let mut buf = vec![0u8; self.chunk_size()];
+            for offset in (0..length).step_by(self.chunk_size()) {
+                let chunk = &mut buf[..(length - offset).min(self.chunk_size())];
+                self.read_address(address + offset as u32, chunk)?;
+                state = crc32::update(state, chunk);
+            }
+            return Ok(!state);
+        };
+        let table = payload::to_bytes(&crc32::TABLE);
+        self.write_address(base + payload::CRC32_TABLE_OFFSET, &table)?;
+        let params = base + payload::PARAMS_OFFSET;
+        for offset in (0..length).step_by(RUN_SIZE) {
+            let words = [
+                address + offset as u32,
+                (length - offset).min(RUN_SIZE) as u32,
+                state,
+                base + payload::CRC32_TABLE_OFFSET,
+                0x00ff_ffff,
+            ];
+            self.write_address(params, &payload::to_bytes(&words))?;
+            self.exec(base)?;
+            let mut buf = [0u8; 4];
+            self.read_address(params + 8, &mut buf)?;
+            state = u32::from_le_bytes(buf);
+        }
+        Ok(!state)
+    }
+
+    /// Fill chip memory region with a 32-bit little endian pattern.
+    ///
+    /// Pattern bytes repeat from `address` on. On known chips a payload fills
+    /// the word aligned body on chip; other chips fall back to writing whole
+    /// region over USB.
+    pub fn fill(&self, address: u32, length: usize, pattern: u32) -> Result<()> {
+        check_range(address, length)?;
+        let bytes = pattern.to_le_bytes();
+        let pattern_at = |offset: usize| -> Vec<u8> {
+            (offset..offset + self.chunk_size().min(length - offset))
+                .map(|i| bytes[i % 4])
+                .collect()
+        };
+        let Some(base) = self.load_payload(&payload::FILL_ARM, &payload::FILL_RISCV)? else {
+            for offset in (0..length).step_by(self.chunk_size()) {
+                self.write_address(address + offset as u32, &pattern_at(offset))?;
+            }
+            return Ok(());
+        };
+        let head = ((4 - address % 4) % 4).min(length as u32) as usize;
+        let body = (length - head) & !3;
+        if head > 0 {
+            self.write_address(address, &pattern_at(0)[..head])?;
+        }
+        if head + body < length {
+            let tail = &pattern_at(head + body)[..length - head - body];
+            self.write_address(address + (head + body) as u32, tail)?;
+        }
+        // pattern rotated so that its first byte lands on aligned body start.
+        let pattern = pattern.rotate_right(8 * head as u32);
+        for offset in (0..body).step_by(RUN_SIZE) {
+            let words = [
+                address + (head + offset) as u32,
+                (body - offset).min(RUN_SIZE) as u32,
+                pattern,
+            ];
+            self.write_address(base + payload::PARAMS_OFFSET, &payload::to_bytes(&words))?;
+            self.exec(base)?;
+        }
+        Ok(())
+    }
+
+    /// Copy chip memory region to another, possibly overlapping, address.
+    ///
+    /// On known chips a payload copies on chip; other chips fall back to
+    /// reading region back to host and writing it again.
+    pub fn copy(&self, source: u32, destination: u32, length: usize) -> Result<()> {
+        check_range(source, length)?;
+        check_range(destination, length)?;
+        // overlapping regions copy from end when destination is above source.
+        let backwards = destination > source;
+        let runs = |size: usize| {
+            let mut offsets: Vec<_> = (0..length).step_by(size).collect();
+            if backwards {
+                offsets.reverse();
+            }
+            offsets
+                .into_iter()
+                .map(move |offset| (offset as u32, (length - offset).min(size)))
+        };
+        let Some(base) = self.load_payload(&payload::COPY_ARM, &payload::COPY_RISCV)? else {
+            let mut buf = vec![0u8; self.chunk_size()];
+            for (offset, len) in runs(self.chunk_size()) {
+                self.read_address(source + offset, &mut buf[..len])?;
+                self.write_address(destination + offset, &buf[..len])?;
+            }
+            return Ok(());
+        };
+        for (offset, len) in runs(RUN_SIZE) {
+            let words = [source + offset, destination + offset, len as u32];
+            self.write_address(base + payload::PARAMS_OFFSET, &payload::to_bytes(&words))?;
+            self.exec(base)?;
+        }
+        Ok(())
+    }
+
+    /// Compare two chip memory regions.
+    ///
+    /// Returns offset of first differing byte, or `None` if regions are equal.
+    /// On known chips a payload compares on chip; other chips fall back to
+    /// reading both regions back to host.
+    pub fn compare(&self, first: u32, second: u32, length: usize) -> Result<Option<u32>> {
+        check_range(first, length)?;
+        check_range(second, length)?;
+        let Some(base) = self.load_payload(&payload::COMPARE_ARM, &payload::COMPARE_RISCV)? else {
+            let mut a = vec![0u8; self.chunk_size()];
+            let mut b = vec![0u8; self.chunk_size()];
+            for offset in (0..length).step_by(self.chunk_size()) {
+                let len = (length - offset).min(self.chunk_size());
+                self.read_address(first + offset as u32, &mut a[..len])?;
+                self.read_address(second + offset as u32, &mut b[..len])?;
+                if let Some(i) = a[..len].iter().zip(&b[..len]).position(|(x, y)| x != y) {
+                    return Ok(Some((offset + i) as u32));
+                }
+            }
+            return Ok(None);
+        };
+        let params = base + payload::PARAMS_OFFSET;
+        for offset in (0..length).step_by(RUN_SIZE) {
+            let len = (length - offset).min(RUN_SIZE) as u32;
+            let words = [first + offset as u32, second + offset as u32, len, 0];
+            self.write_address(params, &payload::to_bytes(&words))?;
+            self.exec(base)?;
+            let mut buf = [0u8; 4];
+            self.read_address(params + 12, &mut buf)?;
+            let found = u32::from_le_bytes(buf);
+            if found < len {
+                return Ok(Some(offset as u32 + found));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Load payload for instruction set of connected chip at start of its
+    /// scratch region.
+    ///
+    /// Returns payload address, or `None` if chip is not supported.
+    pub(crate) fn load_payload(&self, arm: &[u32], riscv: &[u32]) -> Result<Option<u32>> {
+        let Some(chip) = self.chip()? else {
+            return Ok(None);
+        };
+        let base = chip.info().scratch.address;
+        let code = match chip.info().arch {
+            Arch::Arm => payload::to_bytes(arm),
+            Arch::RiscV => payload::to_bytes(riscv),
+        };
+        self.write_address(base, &code)?;
+        Ok(Some(base))
+    }
+}
+
+/// Transfers of one USB write request sending `data`.
+/// Check that `length` bytes from `address` on stay within 32-bit address space.
+pub fn check_range(address: u32, length: usize) -> Result<()> {
+    match u32::try_from(length)
+        .ok()
+        .and_then(|len| address.checked_add(len))
+    {
+        Some(_) => Ok(()),
+        None => Err(Error::AddressOverflow { address, length }),
+    }
+}
+
+fn usb_write_transfers(data: Vec<u8>) -> [Transfer; 3] {
+    let request: [u8; 36] = UsbRequest::usb_write(data.len() as u32).into();
+    [
+        Transfer::Out(request.to_vec()),
+        Transfer::Out(data),
+        Transfer::In(13),
+    ]
+}
+
+/// Transfers of one USB read request receiving `length` bytes.
+fn usb_read_transfers(length: usize) -> [Transfer; 3] {
+    let request: [u8; 36] = UsbRequest::usb_read(length as u32).into();
+    [
+        Transfer::Out(request.to_vec()),
+        Transfer::In(length),
+        Transfer::In(13),
+    ]
+}
+
+fn check_length(data: &[u8], expected: usize) -> Result<()> {
+    if data.len() != expected {
+        return Err(Error::ShortRead {
+            expected,
+            actual: data.len(),
+        });
+    }
+    Ok(())
+}
+
+fn check_usb_response(data: &[u8]) -> Result<()> {
+    // struct { magic: [u8; 4], tag: u32, residue: u32, status: u8 }
+    if data.len() != 13 || data[..4] != *b"AWUS" {
+        return Err(Error::InvalidMagic {
+            expected: b"AWUS",
+            found: data.to_vec(),
+        });
+    }
+    match data[12] {
+        0 => Ok(()),
+        status => Err(Error::UsbStatus(status)),
+    }
+}
+
+fn check_fel_status(data: &[u8]) -> Result<()> {
+    // struct { mark: u16, tag: u16, state: u8, pad: [u8; 3] }
+    check_length(data, 8)?;
+    match data[4] {
+        0 => Ok(()),
+        state => Err(Error::FelStatus(state)),
+    }
 }
 
 /// USB request.
@@ -191,6 +614,15 @@ impl FelRequest {
         }
     }
     #[inline]
+    pub const fn exec(address: u32) -> Self {
+        FelRequest {
+            request: 0x102,
+            address,
+            length: 0,
+            pad: 0,
+        }
+    }
+    #[inline]
     pub const fn read_raw(address: u32, length: u32) -> Self {
         FelRequest {
             request: 0x103,
@@ -231,12 +663,37 @@ pub struct Version {
 }
 
 impl Version {
+    /// Get magic string of this version, `AWUSBFEX` on FEL devices.
+    #[inline]
+    pub const fn magic(self) -> [u8; 8] {
+        self.magic
+    }
+    /// Get chip ID of this version.
+    #[inline]
+    pub const fn id(self) -> u32 {
+        self.id
+    }
+    /// Get BROM firmware version.
+    #[inline]
+    pub const fn firmware(self) -> u32 {
+        self.firmware
+    }
+    /// Get FEL protocol version.
+    #[inline]
+    pub const fn protocol(self) -> u16 {
+        self.protocol
+    }
+    /// Get address of BROM scratchpad memory.
+    #[inline]
+    pub const fn scratchpad(self) -> u32 {
+        self.scratchpad
+    }
     /// Get chip from version.
+    ///
+    /// Chips sharing one chip ID are not distinguished; use `Fel::chip` instead
+    /// on a connected device.
     pub fn chip(self) -> Option<Chip> {
-        match self.id {
-            0x00185900 => Some(Chip::D1),
-            _ => None,
-        }
+        Chip::from_id(self.id)
     }
 }
 
@@ -248,13 +705,22 @@ impl fmt::Debug for Version {
             Some(chip) => map.entry(&"chip", &chip),
             None => map.entry(&"id", &self.id),
         };
-        map.entry(&"dflag", &self.dflag)
+        map.entry(&"firmware", &self.firmware)
+            .entry(&"protocol", &self.protocol)
+            .entry(&"dflag", &self.dflag)
             .entry(&"dlength", &self.dlength)
             .entry(&"scratchpad", &self.scratchpad)
             .finish()
     }
 }
 
+impl From<Version> for [u8; 32] {
+    #[inline]
+    fn from(value: Version) -> Self {
+        unsafe { core::mem::transmute(value) }
+    }
+}
+
 impl From<[u8; 32]> for Version {
     #[inline]
     fn from(value: [u8; 32]) -> Self {
@@ -262,9 +728,246 @@ impl From<[u8; 32]> for Version {
     }
 }
 
-#[derive(Debug)]
-#[repr(u32)]
-pub enum Chip {
-    /// D1-H, D1s or F133 chip.
-    D1 = 0x00185900,
+#[cfg(test)]
+mod tests {
+    use crate::{
+        AsyncFel, Chip, Error, Fel, UsbTransport, crc32, mock::MockDevice, payload,
+        transport::block_on,
+    };
+
+    #[test]
+    fn fel_get_version() {
+        let fel = Fel::new(MockDevice::new(0x00185900, 0x7e00));
+        let version = fel.get_version().unwrap();
+        assert_eq!(version.chip(), Some(Chip::D1));
+        assert_eq!(&version.magic(), b"AWUSBFEX");
+        assert_eq!(version.firmware(), 1);
+        assert_eq!(version.protocol(), 1);
+        assert_eq!(version.scratchpad(), 0x7e00);
+        assert_eq!(fel.chip().unwrap(), Some(Chip::D1));
+        let fel = Fel::new(MockDevice::new(0x00185900, 0x7e00));
+        // ARM branch instruction as the first word of BROM.
+        fel.transport()
+            .write_memory(0, &0xea00_0019u32.to_le_bytes());
+        assert_eq!(fel.chip().unwrap(), Some(Chip::T113));
+    }
+
+    #[test]
+    fn fel_read_write_chunks() {
+        let fel = Fel::new(MockDevice::new(0x00185900, 0x7e00));
+        let data: Vec<u8> = (0..200_000u32).map(|i| (i * 7) as u8).collect();
+        assert_eq!(fel.write_address(0x4000_0000, &data).unwrap(), data.len());
+        let mut buf = vec![0u8; data.len()];
+        assert_eq!(fel.read_address(0x4000_0000, &mut buf).unwrap(), data.len());
+        assert_eq!(buf, data);
+        let requests = fel.transport().requests();
+        let writes: Vec<_> = requests
+            .iter()
+            .filter(|r| r.request == 0x101)
+            .map(|r| (r.address, r.length))
+            .collect();
+        assert_eq!(
+            writes,
+            [
+                (0x4000_0000, 0x10000),
+                (0x4001_0000, 0x10000),
+                (0x4002_0000, 0x10000),
+                (0x4003_0000, 200_000 - 0x30000),
+            ]
+        );
+        assert_eq!(requests.iter().filter(|r| r.request == 0x103).count(), 4);
+
+        let mut fel = Fel::new(MockDevice::new(0x00185900, 0x7e00));
+        fel.set_chunk_size(0x30000);
+        fel.write_address(0x4000_0000, &data).unwrap();
+        fel.read_address(0x4000_0000, &mut buf).unwrap();
+        assert_eq!(buf, data);
+        let reads: Vec<_> = fel
+            .transport()
+            .requests()
+            .iter()
+            .filter(|r| r.request == 0x103)
+            .map(|r| (r.address, r.length))
+            .collect();
+        assert_eq!(
+            reads,
+            [(0x4000_0000, 0x30000), (0x4003_0000, 200_000 - 0x30000)]
+        );
+    }
+
+    #[test]
+    fn fel_address_overflow() {
+        let fel = Fel::new(MockDevice::new(0x00185900, 0x7e00));
+        let overflow = |result: crate::Result<_>| {
+            matches!(
+                result,
+                Err(Error::AddressOverflow {
+                    address: 0xffff_fff0,
+                    length: 0x20
+                })
+            )
+        };
+        assert!(overflow(
+            fel.read_address(0xffff_fff0, &mut [0; 0x20]).map(|_| ())
+        ));
+        assert!(overflow(
+            fel.write_address(0xffff_fff0, &[0; 0x20]).map(|_| ())
+        ));
+        assert!(overflow(fel.crc32(0xffff_fff0, 0x20).map(|_| ())));
+        assert!(overflow(fel.fill(0xffff_fff0, 0x20, 0)));
+        assert!(overflow(fel.copy(0x4000_0000, 0xffff_fff0, 0x20)));
+        assert!(overflow(
+            fel.compare(0xffff_fff0, 0x4000_0000, 0x20).map(|_| ())
+        ));
+        assert!(fel.transport().requests().is_empty());
+    }
+
+    #[test]
+    fn fel_async() {
+        let fel = AsyncFel::new(MockDevice::new(0x00185900, 0x7e00));
+        block_on(async {
+            assert_eq!(fel.get_version().await.unwrap().id(), 0x00185900);
+            fel.write_address(0x4000_0000, b"async").await.unwrap();
+            let mut buf = [0u8; 5];
+            fel.read_address(0x4000_0000, &mut buf).await.unwrap();
+            assert_eq!(&buf, b"async");
+        });
+        // USB operations can be spawned onto multithreaded executors.
+        fn assert_send<F: Send>(_: F) {}
+        fn usb(fel: &AsyncFel<UsbTransport>, buf: &mut [u8]) {
+            assert_send(fel.get_version());
+            assert_send(fel.read_address(0, buf));
+            assert_send(fel.write_address(0, &[]));
+            assert_send(fel.exec(0));
+        }
+        let _ = usb;
+    }
+
+    #[test]
+    fn fel_exec() {
+        let device = MockDevice::new(0x00185900, 0x7e00);
+        device.set_exec_handler(|address, memory| {
+            memory.write_u32(0x2_0000, address);
+            Ok(())
+        });
+        let fel = Fel::new(device);
+        fel.exec(0x4000_0000).unwrap();
+        let mut buf = [0u8; 4];
+        fel.read_address(0x2_0000, &mut buf).unwrap();
+        assert_eq!(u32::from_le_bytes(buf), 0x4000_0000);
+        fel.transport()
+            .set_exec_handler(|_, _| Err(Error::FelStatus(1)));
+        assert!(matches!(fel.exec(0x4000_0000), Err(Error::FelStatus(1))));
+        // device stays usable after failed request.
+        assert!(fel.get_version().is_ok());
+    }
+
+    #[test]
+    fn fel_crc32() {
+        let fel = Fel::new(MockDevice::new(0x00185900, 0x7e00));
+        let data: Vec<u8> = (0..2_200_000u32).map(|i| (i * 13 + i / 7) as u8).collect();
+        fel.transport().write_memory(0x4000_0000, &data);
+        // simulate payload by following its parameter block and lookup table.
+        fel.transport().set_exec_handler(|address, memory| {
+            let mut code = [0u8; 4];
+            memory.read(address, &mut code);
+            assert_eq!(u32::from_le_bytes(code), payload::CRC32_RISCV[0]);
+            let params = address + payload::PARAMS_OFFSET;
+            let (start, length) = (memory.read_u32(params), memory.read_u32(params + 4));
+            let (mut state, table) = (memory.read_u32(params + 8), memory.read_u32(params + 12));
+            for address in start..start + length {
+                let mut byte = [0u8];
+                memory.read(address, &mut byte);
+                let index = (state ^ byte[0] as u32) & 0xff;
+                state = (state >> 8) ^ memory.read_u32(table + index * 4);
+            }
+            memory.write_u32(params + 8, state);
+            Ok(())
+        });
+        assert_eq!(
+            fel.crc32(0x4000_0000, data.len()).unwrap(),
+            crc32::crc32(&data)
+        );
+        let execs = fel
+            .transport()
+            .requests()
+            .iter()
+            .filter(|request| request.request == 0x102)
+            .count();
+        assert_eq!(execs, 3);
+
+        let fel = Fel::new(MockDevice::new(0x1234_5678, 0x7e00));
+        fel.transport().write_memory(0x4000_0000, &data[..100_000]);
+        assert_eq!(
+            fel.crc32(0x4000_0000, 100_000).unwrap(),
+            crc32::crc32(&data[..100_000])
+        );
+    }
+
+    #[test]
+    fn fel_fill_copy_compare() {
+        let fel = Fel::new(MockDevice::new(0x00185900, 0x7e00));
+        // simulate payloads, told apart by their loaded code.
+        fel.transport().set_exec_handler(|address, memory| {
+            let loaded = |code: &[u32]| {
+                let mut buf = vec![0u8; code.len() * 4];
+                memory.read(address, &mut buf);
+                buf == payload::to_bytes(code)
+            };
+            let (fill, copy) = (loaded(&payload::FILL_RISCV), loaded(&payload::COPY_RISCV));
+            let params = address + payload::PARAMS_OFFSET;
+            let [a, b, c] = [0, 4, 8].map(|offset| memory.read_u32(params + offset));
+            if fill {
+                assert_eq!((a % 4, b % 4), (0, 0));
+                for offset in (0..b).step_by(4) {
+                    memory.write_u32(a + offset, c);
+                }
+            } else if copy {
+                let mut buf = vec![0u8; c as usize];
+                memory.read(a, &mut buf);
+                memory.write(b, &buf);
+            } else {
+                assert!(loaded(&payload::COMPARE_RISCV));
+                let mut x = vec![0u8; c as usize];
+                let mut y = vec![0u8; c as usize];
+                memory.read(a, &mut x);
+                memory.read(b, &mut y);
+                let found = x.iter().zip(&y).position(|(x, y)| x != y);
+                memory.write_u32(params + 12, found.map_or(c, |i| i as u32));
+            }
+            Ok(())
+        });
+        let unknown = Fel::new(MockDevice::new(0x1234_5678, 0x7e00));
+        for fel in [&fel, &unknown] {
+            fel.fill(0x4000_0001, 0x10_0006, 0x4433_2211).unwrap();
+            let mut buf = vec![0u8; 0x10_0008];
+            fel.transport().read_memory(0x4000_0000, &mut buf);
+            assert_eq!(buf[..6], [0, 0x11, 0x22, 0x33, 0x44, 0x11]);
+            assert_eq!(buf[0x10_0002..], [0x22, 0x33, 0x44, 0x11, 0x22, 0]);
+            assert_eq!(buf[0x8_0001..0x8_0005], [0x11, 0x22, 0x33, 0x44]);
+
+            let data: Vec<u8> = (0..0x18_0000u32).map(|i| (i * 7 + i / 251) as u8).collect();
+            fel.transport().write_memory(0x4100_0000, &data);
+            // overlapping copy upwards must not read bytes it already wrote.
+            fel.copy(0x4100_0000, 0x4100_0100, data.len()).unwrap();
+            let mut buf = vec![0u8; data.len()];
+            fel.transport().read_memory(0x4100_0100, &mut buf);
+            assert_eq!(buf, data);
+            fel.copy(0x4100_0100, 0x4100_0000, data.len()).unwrap();
+            fel.transport().read_memory(0x4100_0000, &mut buf);
+            assert_eq!(buf, data);
+
+            fel.transport().write_memory(0x4200_0000, &data);
+            assert_eq!(
+                fel.compare(0x4100_0000, 0x4200_0000, data.len()).unwrap(),
+                None
+            );
+            fel.transport()
+                .write_memory(0x4211_2345, &[!data[0x11_2345]]);
+            assert_eq!(
+                fel.compare(0x4100_0000, 0x4200_0000, data.len()).unwrap(),
+                Some(0x11_2345)
+            );
+        }
+    }
 }
@@ -1,7 +1,37 @@
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use clap_verbosity_flag::Verbosity;
-use log::{debug, error};
-use rfel::Fel;
+use indicatif::{ProgressBar, ProgressStyle};
+use log::debug;
+use nusb::DeviceInfo;
+use rfel::{
+    Chip, EgonHead, Fel, FelTransport, Region, Sid, Version,
+    ddr::{self, DramParameters},
+    device::{self, DeviceEvent, DeviceSelector},
+    gdb::GdbServer,
+    mmc::{self, Mmc},
+    regs::{self, ResolvedRegister},
+    script::{Script, Step},
+    spi::{Spi, SpiBus},
+    spinand::SpiNand,
+    spinor::SpiNor,
+    toc::{Toc0, Toc0Item, Toc1, Toc1Item},
+};
+use serde_json::json;
+use std::{
+    cell::RefCell,
+    error::Error,
+    net::TcpListener,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Print a line of command output like `println!`, tagged by [`print_line`].
+macro_rules! outln {
+    ($($arg:tt)*) => {
+        print_line(format_args!($($arg)*))
+    };
+}
 
 #[derive(Parser)]
 #[clap(name = "rfel")]
@@ -9,14 +39,64 @@ use rfel::Fel;
 struct Cli {
     #[clap(flatten)]
     verbose: Verbosity,
+    /// Select device by bus and address like 1:5, port path like 1-2.3, or serial number
+    #[clap(short, long, global = true)]
+    device: Option<DeviceSelector>,
+    /// Run the command on every connected device in parallel, tagging output with device port path
+    #[clap(long, global = true, conflicts_with = "device")]
+    all: bool,
+    /// Output format of version, read32, sid, list, wait, watch, hexdump and crc32
+    #[clap(long, global = true, value_enum, default_value_t = Format::Text)]
+    format: Format,
+    /// Bytes carried by each FEL read or write request, like 0x10000 or 1048576
+    #[clap(long, global = true, default_value = "0x10000", value_parser = parse_chunk_size)]
+    chunk_size: usize,
     #[clap(subcommand)]
     command: Commands,
 }
 
-#[derive(Debug, Subcommand)]
+/// Parse nonzero chunk size in hexadecimal or decimal.
+fn parse_chunk_size(value: &str) -> Result<usize, String> {
+    match parse_value(value.trim()) {
+        Some(0) | None => Err("should be a nonzero number like 0x10000 or 65536".to_string()),
+        Some(size) => Ok(size),
+    }
+}
+
+/// Output format of command results.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum Format {
+    /// Human readable text
+    Text,
+    /// One JSON object per result, with stable field names
+    Json,
+}
+
+#[derive(Clone, Debug, Subcommand)]
 enum Commands {
+    /// List connected FEL devices
+    List,
+    /// Wait until a FEL device is connected, selected by --device if given
+    Wait {
+        /// Seconds to wait, forever if absent
+        #[clap(long)]
+        timeout: Option<u64>,
+    },
+    /// Print FEL devices attaching and detaching until interrupted
+    Watch,
     /// Show chip version
     Version,
+    /// Show chip security ID
+    Sid,
+    /// Reset chip through its watchdog
+    Reset {
+        /// Wait until the device re-enumerates in FEL mode
+        #[clap(long)]
+        wait: bool,
+        /// Seconds to wait for the device
+        #[clap(long, default_value_t = 10, requires = "wait")]
+        timeout: u64,
+    },
     /// Dumps memory region in hexadecimal format
     Hexdump {
         /// The address to be dumped
@@ -36,128 +116,1752 @@ enum Commands {
         /// The 32-bit value to be written
         value: String,
     },
+    /// Write file content into chip memory
+    Write {
+        /// The address to be written
+        address: String,
+        /// Path to the file to be sent
+        file: PathBuf,
+    },
+    /// Read chip memory into a file
+    Read {
+        /// The address to be read
+        address: String,
+        /// Length of memory to be read
+        length: String,
+        /// Path to the file to be saved
+        file: PathBuf,
+    },
+    /// Compare chip memory against file content
+    Verify {
+        /// The address to be compared
+        address: String,
+        /// Path to the file holding expected content
+        file: PathBuf,
+    },
+    /// Calculate CRC-32 of chip memory region
+    Crc32 {
+        /// The address to be calculated
+        address: String,
+        /// Length of memory region
+        length: String,
+    },
+    /// Fill chip memory region with a byte or 32-bit word, on chip
+    Fill {
+        /// The address to be filled
+        address: String,
+        /// Length of memory region
+        length: String,
+        /// Byte like 0xff, repeated, or 32-bit word like 0x12345678 stored little endian
+        pattern: String,
+    },
+    /// Copy chip memory region to another address, on chip
+    Copy {
+        /// The address to copy from
+        source: String,
+        /// The address to copy to; regions may overlap
+        destination: String,
+        /// Length of memory region
+        length: String,
+    },
+    /// Compare two chip memory regions, on chip
+    Compare {
+        /// The address of first region
+        first: String,
+        /// The address of second region
+        second: String,
+        /// Length of memory regions
+        length: String,
+    },
+    /// Call function address
+    Exec {
+        /// The address to be executed
+        address: String,
+    },
+    /// Serve GDB remote protocol for chip memory access and code execution
+    Gdbserver {
+        /// TCP port to listen on
+        #[clap(long, default_value_t = 3333)]
+        port: u16,
+    },
+    /// Run steps listed in a TOML script in one session
+    Run {
+        /// Path to the script
+        script: PathBuf,
+    },
+    /// Load and run an eGON.BT0 SPL image, then return to FEL mode
+    Spl {
+        /// Path to the eGON.BT0 image
+        file: PathBuf,
+    },
+    /// Initialize DRAM by an allwinner-rt stub image and report its size, only on D1 chips
+    Ddr {
+        /// Board profile (nezha or lichee) or path to a TOML file of DRAM parameters
+        profile: String,
+        /// Path to the eGON.BT0 image of allwinner-rt `dram-stub` example
+        #[clap(long)]
+        stub: PathBuf,
+    },
+    /// Build an eGON.BT0 image from an ELF file or raw binary, or verify an existing image
+    Mkimage {
+        /// Path to the ELF file or raw binary starting with eGON.BT0 head, or the image to verify
+        input: PathBuf,
+        /// Path to the eGON.BT0 image to be saved
+        #[clap(required_unless_present = "verify")]
+        output: Option<PathBuf>,
+        /// Verify input as an eGON.BT0 image instead of building one
+        #[clap(long, conflicts_with = "output")]
+        verify: bool,
+        /// Pad image to a multiple of this many bytes
+        #[clap(long, default_value_t = 0x2000)]
+        align: usize,
+    },
+    /// Inspect or create TOC0 and TOC1 secure boot packages
+    Toc {
+        #[clap(subcommand)]
+        command: TocCommands,
+    },
+    /// Access peripheral registers by name
+    Reg {
+        #[clap(subcommand)]
+        command: RegCommands,
+    },
+    /// Operate SPI NOR flash on SPI0
+    Spinor {
+        #[clap(subcommand)]
+        command: SpinorCommands,
+    },
+    /// Operate SPI NAND flash on SPI0
+    Spinand {
+        #[clap(subcommand)]
+        command: SpinandCommands,
+    },
+    /// Operate SD card or eMMC through an SMHC helper image
+    Mmc {
+        /// Card to operate
+        #[clap(long, value_enum, default_value_t = Card::Sd)]
+        card: Card,
+        /// Path to the eGON.BT0 image of `mmc-helper` example
+        #[clap(long)]
+        helper: PathBuf,
+        /// Address of block buffer in DRAM, which should be initialized first, e.g. by `ddr`
+        #[clap(long, default_value = "0x40000000")]
+        buffer: String,
+        #[clap(subcommand)]
+        command: MmcCommands,
+    },
+}
+
+#[derive(Clone, Debug, Subcommand)]
+enum TocCommands {
+    /// Show head and items of a TOC0 or TOC1 package
+    Dump {
+        /// Path to the package
+        file: PathBuf,
+    },
+    /// Create a TOC0 package from boot0 firmware
+    Toc0 {
+        /// Path to the package to be saved
+        output: PathBuf,
+        /// Path to the firmware binary
+        #[clap(long)]
+        firmware: PathBuf,
+        /// Address where BROM loads the firmware
+        #[clap(long)]
+        load_address: String,
+        /// Path to the DER encoded certificate, zero-filled placeholder if absent
+        #[clap(long)]
+        certificate: Option<PathBuf>,
+        /// Path to the key item
+        #[clap(long)]
+        key: Option<PathBuf>,
+        /// Pad package to a multiple of this many bytes
+        #[clap(long, default_value_t = 0x2000)]
+        align: usize,
+    },
+    /// Create a TOC1 package from items
+    Toc1 {
+        /// Path to the package to be saved
+        output: PathBuf,
+        /// Item as NAME@ADDRESS=FILE, like opensbi@0x40000000=fw_jump.bin
+        #[clap(long = "item", required = true)]
+        items: Vec<String>,
+        /// Pad package to a multiple of this many bytes
+        #[clap(long, default_value_t = 0x4000)]
+        align: usize,
+    },
+}
+
+#[derive(Clone, Debug, Subcommand)]
+enum SpinandCommands {
+    /// Detect flash, show its parameters and scan bad blocks
+    Detect,
+    /// Erase flash blocks, skipping bad blocks
+    Erase {
+        /// Flash offset to be erased, aligned to block size
+        address: String,
+        /// Length of region to be erased
+        length: String,
+    },
+    /// Write file content into good blocks, skipping bad blocks
+    Write {
+        /// Flash offset to be written, aligned to block size
+        address: String,
+        /// Path to the file to be written
+        file: PathBuf,
+    },
+    /// Read content written by `spinand write` into a file
+    Read {
+        /// Flash offset to be read, aligned to block size
+        address: String,
+        /// Length of content to be read
+        length: String,
+        /// Path to the file to be saved
+        file: PathBuf,
+    },
+    /// Write copies of an eGON.BT0 image into the first good blocks for BROM to boot
+    Boot0 {
+        /// Path to the eGON.BT0 image
+        file: PathBuf,
+        /// Bytes of image stored in each page, 1024 for chips whose BROM reads half pages
+        #[clap(long)]
+        split: Option<u32>,
+        /// Number of copies to write
+        #[clap(long, default_value_t = 4)]
+        copies: u32,
+    },
 }
 
-/// USB vendor ID 0x1f3a: Allwinner Technology Co., Ltd.
-const VENDOR_ALLWINNER: u16 = 0x1f3a;
-/// Product 0xefe8: sunxi SoC OTG connector in FEL/flashing mode.
-const PRODUCT_FEL: u16 = 0xefe8;
+#[derive(Clone, Debug, Subcommand)]
+enum RegCommands {
+    /// Read one register and decode its fields
+    Read {
+        /// Register name like ccu.pll_cpu_control or ccu.smhc_clk[1]
+        name: String,
+    },
+    /// Read and decode all registers of a peripheral
+    Dump {
+        /// Peripheral name like uart0
+        peripheral: String,
+    },
+}
+
+#[derive(Clone, Debug, Subcommand)]
+enum SpinorCommands {
+    /// Detect flash and show its parameters
+    Detect,
+    /// Erase flash region
+    Erase {
+        /// Flash offset to be erased, aligned to smallest erase size
+        address: String,
+        /// Length of region to be erased
+        length: String,
+    },
+    /// Erase flash region and write file content into it
+    Write {
+        /// Flash offset to be written, aligned to smallest erase size
+        address: String,
+        /// Path to the file to be written
+        file: PathBuf,
+    },
+    /// Read flash content into a file
+    Read {
+        /// Flash offset to be read
+        address: String,
+        /// Length of flash content to be read
+        length: String,
+        /// Path to the file to be saved
+        file: PathBuf,
+    },
+}
+
+/// Card attached to an SMHC.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum Card {
+    /// SD card on SMHC0
+    Sd,
+    /// eMMC on SMHC2
+    Emmc,
+}
+
+impl Card {
+    fn smhc(self) -> u32 {
+        match self {
+            Card::Sd => 0,
+            Card::Emmc => 2,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Subcommand)]
+enum MmcCommands {
+    /// Initialize card and show its size
+    Detect,
+    /// Write file content onto card
+    Write {
+        /// Card offset to be written, a multiple of 512
+        offset: String,
+        /// Path to the file to be written
+        file: PathBuf,
+    },
+    /// Read card content into a file
+    Read {
+        /// Card offset to be read, a multiple of 512
+        offset: String,
+        /// Length of card content to be read
+        length: String,
+        /// Path to the file to be saved
+        file: PathBuf,
+    },
+}
+
+/// Size of each chunk when transferring large memory regions.
+const CHUNK_SIZE: usize = 65536;
+/// Bytes passed to each `read_address` or `write_address` call when streaming
+/// files, so that requests stay pipelined between progress updates.
+const STREAM_SIZE: usize = 1 << 20;
+/// Size of block buffer of SMHC helper; helper moves this much in each run.
+const MMC_BUFFER_SIZE: u32 = 4 << 20;
 
 fn main() {
     let cli = Cli::parse();
     env_logger::Builder::new()
         .filter_level(cli.verbose.log_level_filter())
         .init();
-    let devices: Vec<_> = nusb::list_devices()
-        .expect("list devices")
-        .filter(|dev| dev.vendor_id() == VENDOR_ALLWINNER && dev.product_id() == PRODUCT_FEL)
+    let format = cli.format;
+    let chunk_size = cli.chunk_size;
+    // host-only commands run without USB access.
+    if let Some(ans) = run_host(&cli.command) {
+        if let Err(e) = ans {
+            print_error(format, e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    let devices = match device::list_devices() {
+        Ok(devices) => devices,
+        Err(e) => {
+            print_error(format, format_args!("cannot list USB devices: {}", e));
+            std::process::exit(1);
+        }
+    };
+    let devices: Vec<_> = devices
+        .into_iter()
+        .filter(|dev| {
+            cli.device
+                .as_ref()
+                .is_none_or(|selector| selector.matches(dev))
+        })
         .inspect(|dev| debug!("Allwinner FEL device {:?}", dev))
         .collect();
-    if devices.len() == 0 {
-        error!("Cannot find any Allwinner FEL device connected.");
+    match cli.command {
+        Commands::List => {
+            list(&devices, format);
+            return;
+        }
+        Commands::Wait { timeout } => {
+            let timeout = timeout.map(Duration::from_secs);
+            match device::wait_for_device(cli.device.as_ref(), timeout) {
+                Ok(Some(info)) => match format {
+                    Format::Text => println!(
+                        "device attached at {}:{} ({})",
+                        info.bus_number(),
+                        info.device_address(),
+                        device::port_path(&info)
+                    ),
+                    Format::Json => println!("{}", device_json(&info)),
+                },
+                Ok(None) => {
+                    print_error(format, "timed out waiting for FEL device");
+                    std::process::exit(1);
+                }
+                Err(e) => {
+                    print_error(format, e);
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        Commands::Watch => {
+            if let Err(e) = watch(cli.device.as_ref(), format) {
+                print_error(format, e);
+                std::process::exit(1);
+            }
+            return;
+        }
+        _ => {}
+    }
+    if devices.is_empty() {
+        match &cli.device {
+            Some(selector) => print_error(
+                format,
+                format_args!("cannot find Allwinner FEL device {}", selector),
+            ),
+            None => print_error(format, "cannot find any Allwinner FEL device connected"),
+        }
+        std::process::exit(1);
+    }
+    if cli.all {
+        let handles: Vec<_> = devices
+            .into_iter()
+            .map(|info| {
+                let command = cli.command.clone();
+                thread::spawn(move || {
+                    DEVICE_TAG.set(Some(device::port_path(&info)));
+                    let ans = open_and_run(&info, command, format, chunk_size);
+                    if let Err(e) = &ans {
+                        print_error(format, e);
+                    }
+                    ans.is_ok()
+                })
+            })
+            .collect();
+        let mut failed = false;
+        for handle in handles {
+            failed |= !handle.join().expect("join device thread");
+        }
+        if failed {
+            std::process::exit(1);
+        }
         return;
     }
     if devices.len() > 1 {
-        error!("TODO: rfel does not support connecting to multiple Allwinner FEL devices by now.");
+        print_error(
+            format,
+            "multiple Allwinner FEL devices connected, select one with --device or use --all",
+        );
+        list(&devices, format);
+        std::process::exit(1);
+    }
+    if let Err(e) = open_and_run(&devices[0], cli.command, format, chunk_size) {
+        print_error(format, e);
+        std::process::exit(1);
+    }
+}
+
+/// Print error message, as an object with `error` field in JSON format.
+fn print_error(format: Format, message: impl core::fmt::Display) {
+    match format {
+        Format::Text => outln!("error: {}", message),
+        Format::Json => print_json(error_json(message)),
+    }
+}
+
+thread_local! {
+    /// Port path of device whose command runs on this thread, set with `--all`.
+    static DEVICE_TAG: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+#[cfg(test)]
+thread_local! {
+    /// Lines of command output printed on this thread, for tests to check.
+    static OUTPUT: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Print a line of command output, prefixed by device port path with `--all`.
+fn print_line(line: core::fmt::Arguments) {
+    DEVICE_TAG.with_borrow(|tag| match tag {
+        Some(tag) => emit(format!("[{}] {}", tag, line)),
+        None => emit(line.to_string()),
+    })
+}
+
+/// Print JSON object of command output, tagged by [`tag_json`].
+fn print_json(value: serde_json::Value) {
+    emit(tag_json(value).to_string())
+}
+
+fn emit(line: String) {
+    #[cfg(test)]
+    OUTPUT.with_borrow_mut(|output| output.push(line.clone()));
+    println!("{}", line)
+}
+
+/// Add `device` field of device port path to JSON object with `--all`.
+fn tag_json(mut value: serde_json::Value) -> serde_json::Value {
+    DEVICE_TAG.with_borrow(|tag| {
+        if let (Some(tag), Some(object)) = (tag, value.as_object_mut()) {
+            object.insert("device".to_string(), json!(tag));
+        }
+    });
+    value
+}
+
+fn error_json(message: impl core::fmt::Display) -> serde_json::Value {
+    json!({ "error": message.to_string() })
+}
+
+fn open_and_run(
+    info: &DeviceInfo,
+    command: Commands,
+    format: Format,
+    chunk_size: usize,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    if let Commands::Run { script } = &command {
+        return run_script(info, script, chunk_size);
+    }
+    let wait = match command {
+        Commands::Reset {
+            wait: true,
+            timeout,
+        } => Some(Duration::from_secs(timeout)),
+        _ => None,
+    };
+    {
+        let device = info.open()?;
+        let mut interface = device.claim_interface(0)?;
+        let mut fel = Fel::open_interface(&mut interface)?;
+        fel.set_chunk_size(chunk_size);
+        run(&fel, command, format)?;
+    }
+    if let Some(timeout) = wait {
+        match device::wait_for_reenumeration(info, timeout)? {
+            Some(info) => outln!(
+                "device re-enumerated at {}:{}",
+                info.bus_number(),
+                info.device_address()
+            ),
+            None => return Err(rfel::Error::Timeout.into()),
+        }
+    }
+    Ok(())
+}
+
+fn probe_chip(info: &DeviceInfo) -> Result<(Version, Option<Chip>), Box<dyn Error + Send + Sync>> {
+    let device = info.open()?;
+    let mut interface = device.claim_interface(0)?;
+    let fel = Fel::open_interface(&mut interface)?;
+    Ok((fel.get_version()?, fel.chip()?))
+}
+
+/// Describe device and its chip as JSON object.
+fn device_json(info: &DeviceInfo) -> serde_json::Value {
+    let (id, chip, error) = match probe_chip(info) {
+        Ok((version, chip)) => (Some(version.id()), chip.map(|chip| chip.info().name), None),
+        Err(e) => (None, None, Some(e.to_string())),
+    };
+    json!({
+        "bus": info.bus_number(),
+        "address": info.device_address(),
+        "port": device::port_path(info),
+        "id": id,
+        "chip": chip,
+        "error": error,
+    })
+}
+
+/// Name chip of device, or its ID if chip is unknown.
+fn chip_name(info: &DeviceInfo) -> String {
+    match probe_chip(info) {
+        Ok((_, Some(chip))) => chip.info().name.to_string(),
+        Ok((version, None)) => format!("0x{:08x}", version.id()),
+        Err(e) => format!("unknown ({})", e),
+    }
+}
+
+fn list(devices: &[DeviceInfo], format: Format) {
+    if format == Format::Json {
+        let devices: Vec<_> = devices.iter().map(device_json).collect();
+        println!("{}", json!({ "devices": devices }));
         return;
     }
-    let device = devices[0].open().expect("open USB device");
-    let mut interface = device.claim_interface(0).expect("open USB interface 0");
-    let fel = Fel::open_interface(&mut interface).expect("open usb interface as an FEL device");
-    match cli.command {
+    println!("{:<9} {:<12} CHIP", "BUS:ADDR", "PORT");
+    for info in devices {
+        println!(
+            "{:<9} {:<12} {}",
+            format!("{}:{}", info.bus_number(), info.device_address()),
+            device::port_path(info),
+            chip_name(info)
+        );
+    }
+}
+
+/// Print attach and detach events of selected devices, starting with those
+/// already attached.
+fn watch(selector: Option<&DeviceSelector>, format: Format) -> std::io::Result<()> {
+    let selected = |info: &DeviceInfo| selector.is_none_or(|selector| selector.matches(info));
+    let (mut watch, devices) = device::DeviceWatch::new()?;
+    if format == Format::Text {
+        println!("{:<9} {:<9} {:<12} CHIP", "EVENT", "BUS:ADDR", "PORT");
+    }
+    // detached devices cannot be asked for their chips any more.
+    let print = |event: &str, info: &DeviceInfo| {
+        let attached = event != "detached";
+        match format {
+            Format::Text => println!(
+                "{:<9} {:<9} {:<12} {}",
+                event,
+                format!("{}:{}", info.bus_number(), info.device_address()),
+                device::port_path(info),
+                if attached {
+                    chip_name(info)
+                } else {
+                    String::new()
+                }
+            ),
+            Format::Json => {
+                let mut object = match attached {
+                    true => device_json(info),
+                    false => json!({
+                        "bus": info.bus_number(),
+                        "address": info.device_address(),
+                        "port": device::port_path(info),
+                    }),
+                };
+                object["event"] = json!(event);
+                println!("{}", object);
+            }
+        }
+    };
+    for info in devices.iter().filter(|info| selected(info)) {
+        print("present", info);
+    }
+    while let Some(event) = watch.next(None)? {
+        match event {
+            DeviceEvent::Attached(info) if selected(&info) => print("attached", &info),
+            DeviceEvent::Detached(info) if selected(&info) => print("detached", &info),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn run<T: FelTransport>(
+    fel: &Fel<T>,
+    command: Commands,
+    format: Format,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    match command {
+        Commands::List | Commands::Wait { .. } | Commands::Watch => {
+            unreachable!("devices are listed and watched before opening")
+        }
+        Commands::Mkimage { .. } | Commands::Toc { .. } => {
+            unreachable!("images are built without device")
+        }
+        Commands::Run { .. } => unreachable!("scripts reopen device on their own"),
         Commands::Version => {
-            let version = fel.get_version();
-            println!("{:x?}", version);
+            let version = fel.get_version()?;
+            match format {
+                Format::Text => outln!("{:x?}", version),
+                Format::Json => print_json(version_json(version, fel.chip()?)),
+            }
         }
-        Commands::Hexdump { address, length } => {
-            let address: usize = match parse_value(address.trim()) {
-                Some(address) => address,
-                None => {
-                    println!(
-                        "error: invalid address, shoule be hexadecimal like 0x40000000, or decimal like 1073741824"
-                    );
-                    return;
-                }
-            };
-            let length: usize = match parse_value(length.trim()) {
-                Some(address) => address,
-                None => {
-                    println!(
-                        "error: invalid data, shoule be hexadecimal like 0x40000000, or decimal like 1073741824"
-                    );
-                    return;
-                }
+        Commands::Sid => {
+            let Some(sid) = fel.read_sid()? else {
+                return Err("unsupported chip, cannot locate SID".into());
             };
-            const CHUNK_SIZE: usize = 65536;
-            let mut buf = Vec::new();
-            buf.resize(CHUNK_SIZE, 0);
+            if format == Format::Json {
+                print_json(sid_json(&sid));
+                return Ok(());
+            }
+            outln!("{}", sid);
+            outln!(
+                "chip_id: {:08x} {:08x} {:08x} {:08x}",
+                sid.chip_id[0],
+                sid.chip_id[1],
+                sid.chip_id[2],
+                sid.chip_id[3]
+            );
+            if let Some(ddr_efuse_type) = sid.ddr_efuse_type {
+                outln!("ddr_efuse_type: 0x{:x}", ddr_efuse_type);
+            }
+        }
+        Commands::Reset { .. } => {
+            if !fel.reset()? {
+                return Err("unsupported chip, cannot locate watchdog".into());
+            }
+        }
+        Commands::Hexdump { address, length } => {
+            let address: u32 = parse_arg("address", &address)?;
+            let length: usize = parse_arg("length", &length)?;
+            rfel::check_range(address, length)?;
+            if format == Format::Json {
+                let mut buf = vec![0u8; length];
+                fel.read_address(address, &mut buf)?;
+                let data: String = buf.iter().map(|byte| format!("{:02x}", byte)).collect();
+                let ans = json!({ "address": address, "length": length, "data": data });
+                print_json(ans);
+                return Ok(());
+            }
+            let mut buf = vec![0u8; CHUNK_SIZE];
             for offset in (0..length).step_by(CHUNK_SIZE) {
                 let chunk_len = (length - offset).min(CHUNK_SIZE);
-                fel.read_address((address + offset) as u32, &mut buf[..chunk_len]);
-                hexdump(&buf[..chunk_len], (address + offset) as u32);
+                fel.read_address(address + offset as u32, &mut buf[..chunk_len])?;
+                hexdump(&buf[..chunk_len], address + offset as u32);
             }
         }
         Commands::Read32 { address } => {
-            let address: u32 = match parse_value(address.trim()) {
-                Some(address) => address,
-                None => {
-                    println!(
-                        "error: invalid address, shoule be hexadecimal like 0x40000000, or decimal like 1073741824"
-                    );
-                    return;
-                }
-            };
+            let address: u32 = parse_arg("address", &address)?;
             let mut buf = [0u8; 4];
-            fel.read_address(address, &mut buf);
+            fel.read_address(address, &mut buf)?;
             let ans = u32::from_le_bytes(buf);
-            println!("0x{:08x}", ans);
+            match format {
+                Format::Text => outln!("0x{:08x}", ans),
+                Format::Json => print_json(json!({ "address": address, "value": ans })),
+            }
         }
         Commands::Write32 { address, value } => {
-            let address: u32 = match parse_value(address.trim()) {
-                Some(address) => address,
-                None => {
+            let address: u32 = parse_arg("address", &address)?;
+            let value: u32 = parse_arg("value", &value)?;
+            fel.write_address(address, &value.to_le_bytes())?;
+        }
+        Commands::Write { address, file } => {
+            let address: u32 = parse_arg("address", &address)?;
+            let buf = read_file(&file)?;
+            rfel::check_range(address, buf.len())?;
+            let progress = progress_bar("Writing", buf.len());
+            let start = Instant::now();
+            for (index, chunk) in buf.chunks(STREAM_SIZE).enumerate() {
+                fel.write_address(address + (index * STREAM_SIZE) as u32, chunk)?;
+                progress.inc(chunk.len() as u64);
+            }
+            progress.finish();
+            print_throughput("written", buf.len(), start);
+        }
+        Commands::Read {
+            address,
+            length,
+            file,
+        } => {
+            let address: u32 = parse_arg("address", &address)?;
+            let length: usize = parse_arg("length", &length)?;
+            rfel::check_range(address, length)?;
+            let mut buf = vec![0u8; length];
+            let progress = progress_bar("Reading", length);
+            let start = Instant::now();
+            for (index, chunk) in buf.chunks_mut(STREAM_SIZE).enumerate() {
+                fel.read_address(address + (index * STREAM_SIZE) as u32, chunk)?;
+                progress.inc(chunk.len() as u64);
+            }
+            progress.finish();
+            print_throughput("read", length, start);
+            write_file(&file, &buf)?;
+        }
+        Commands::Verify { address, file } => {
+            /// Most mismatching offsets listed.
+            const MAX_LISTED: usize = 16;
+            let address: u32 = parse_arg("address", &address)?;
+            let expected = read_file(&file)?;
+            rfel::check_range(address, expected.len())?;
+            let progress = progress_bar("Verifying", expected.len());
+            let mut buf = vec![0u8; STREAM_SIZE];
+            let mut mismatches = 0;
+            let mut first_mismatch = None;
+            for (index, chunk) in expected.chunks(STREAM_SIZE).enumerate() {
+                let offset = index * STREAM_SIZE;
+                let actual = &mut buf[..chunk.len()];
+                fel.read_address(address + offset as u32, actual)?;
+                for (i, (a, b)) in actual.iter().zip(chunk).enumerate() {
+                    if a == b {
+                        continue;
+                    }
+                    first_mismatch.get_or_insert((address as usize + offset + i) as u32);
+                    if mismatches < MAX_LISTED {
+                        progress.suspend(|| {
+                            outln!(
+                                "0x{:08x} (offset 0x{:x}): expected 0x{:02x}, found 0x{:02x}",
+                                address as usize + offset + i,
+                                offset + i,
+                                b,
+                                a
+                            )
+                        });
+                    }
+                    mismatches += 1;
+                }
+                progress.inc(chunk.len() as u64);
+            }
+            progress.finish();
+            if let Some(address) = first_mismatch {
+                outln!(
+                    "{} of {} bytes differ from file",
+                    mismatches,
+                    expected.len()
+                );
+                return Err(rfel::Error::VerifyMismatch { address }.into());
+            }
+            outln!("{} bytes verified, memory matches file", expected.len());
+        }
+        Commands::Crc32 { address, length } => {
+            let address: u32 = parse_arg("address", &address)?;
+            let length: usize = parse_arg("length", &length)?;
+            let ans = fel.crc32(address, length)?;
+            match format {
+                Format::Text => outln!("0x{:08x}", ans),
+                Format::Json => {
+                    print_json(json!({ "address": address, "length": length, "crc32": ans }))
+                }
+            }
+        }
+        Commands::Fill {
+            address,
+            length,
+            pattern,
+        } => {
+            let address: u32 = parse_arg("address", &address)?;
+            let length: usize = parse_arg("length", &length)?;
+            let Some(pattern) = parse_pattern(pattern.trim()) else {
+                return Err(
+                    "invalid pattern, should be a byte like 0xff or a word like 0x12345678".into(),
+                );
+            };
+            let start = Instant::now();
+            fel.fill(address, length, pattern)?;
+            print_throughput("filled", length, start);
+        }
+        Commands::Copy {
+            source,
+            destination,
+            length,
+        } => {
+            let source: u32 = parse_arg("source address", &source)?;
+            let destination: u32 = parse_arg("destination address", &destination)?;
+            let length: usize = parse_arg("length", &length)?;
+            let start = Instant::now();
+            fel.copy(source, destination, length)?;
+            print_throughput("copied", length, start);
+        }
+        Commands::Compare {
+            first,
+            second,
+            length,
+        } => {
+            let first: u32 = parse_arg("first address", &first)?;
+            let second: u32 = parse_arg("second address", &second)?;
+            let length: usize = parse_arg("length", &length)?;
+            match fel.compare(first, second, length)? {
+                None => outln!("{} bytes compared, regions are equal", length),
+                Some(offset) => {
+                    return Err(format!(
+                        "regions differ at offset 0x{:x}, 0x{:08x} and 0x{:08x}",
+                        offset,
+                        first + offset,
+                        second + offset
+                    )
+                    .into());
+                }
+            }
+        }
+        Commands::Exec { address } => {
+            let address: u32 = parse_arg("address", &address)?;
+            fel.exec(address)?;
+        }
+        Commands::Spl { file } => {
+            let image = read_egon(&file)?;
+            run_spl(fel, &image)?;
+        }
+        Commands::Ddr { profile, stub } => {
+            let Some(chip) = fel.chip()? else {
+                return Err("unsupported chip, cannot initialize DRAM".into());
+            };
+            let Some(profiles) = chip.info().dram_profiles else {
+                return Err(format!(
+                    "DRAM initialization is not supported on chip {}",
+                    chip.info().name
+                )
+                .into());
+            };
+            let builtin = profiles.iter().find(|(name, _)| *name == profile);
+            let params = match builtin.map(|(_, params)| *params) {
+                Some(params) => params,
+                None => match std::fs::read_to_string(&profile) {
+                    Ok(text) => match DramParameters::parse(&text) {
+                        Ok(params) => params,
+                        Err(e) => {
+                            return Err(format!("{}: {}", profile, e).into());
+                        }
+                    },
+                    Err(e) => {
+                        return Err(format!(
+                            "{:?} is neither a built-in profile nor a readable file: {}",
+                            profile, e
+                        )
+                        .into());
+                    }
+                },
+            };
+            let mut image = read_egon(&stub)?;
+            if ddr::patch(&mut image, &params).is_none() {
+                return Err(
+                    format!("{} is too short to hold DRAM parameters", stub.display()).into(),
+                );
+            }
+            let spl = run_spl(fel, &image)?;
+            let mut buf = [0u8; 4];
+            fel.read_address(spl.address + ddr::SIZE_OFFSET as u32, &mut buf)?;
+            match u32::from_le_bytes(buf) {
+                0 => return Err("DRAM initialization failed".into()),
+                size => outln!("DRAM initialized, {} MiB", size),
+            }
+        }
+        Commands::Gdbserver { port } => {
+            let listener = match TcpListener::bind(("127.0.0.1", port)) {
+                Ok(listener) => listener,
+                Err(e) => {
+                    return Err(format!("cannot listen on port {}: {}", port, e).into());
+                }
+            };
+            let mut server = GdbServer::new(fel)?;
+            outln!("listening for GDB on 127.0.0.1:{}", port);
+            for stream in listener.incoming() {
+                let ans = stream.and_then(|stream| {
+                    if let Ok(address) = stream.peer_addr() {
+                        outln!("GDB connected from {}", address);
+                    }
+                    stream.set_nodelay(true)?;
+                    server.serve(stream)
+                });
+                match ans {
+                    Ok(()) => outln!("GDB disconnected"),
+                    Err(e) => print_error(format, format_args!("GDB connection: {}", e)),
+                }
+            }
+        }
+        Commands::Spinor { command } => {
+            let spi = open_spi0(fel)?;
+            run_spinor(&SpiNor::detect(spi)?, command)?;
+        }
+        Commands::Spinand { command } => {
+            let spi = open_spi0(fel)?;
+            run_spinand(&SpiNand::detect(spi)?, command)?;
+        }
+        Commands::Mmc {
+            card,
+            helper,
+            buffer,
+            command,
+        } => {
+            let buffer: u32 = parse_arg("buffer address", &buffer)?;
+            let image = read_egon(&helper)?;
+            if fel.chip()? != Some(Chip::D1) {
+                return Err("SMHC helper only supports D1 chips".into());
+            }
+            let spl = Chip::D1.info().spl;
+            if image.len() > spl.size as usize {
+                return Err(format!(
+                    "eGON.BT0 image length {} exceeds SPL region size {}",
+                    image.len(),
+                    spl.size
+                )
+                .into());
+            }
+            let buffer = Region {
+                address: buffer,
+                size: MMC_BUFFER_SIZE,
+            };
+            let mmc = Mmc::load(fel, spl.address, &image, card.smhc(), buffer)?;
+            run_mmc(&mmc, command)?;
+        }
+        Commands::Reg { command } => {
+            let Some(chip) = fel.chip()? else {
+                return Err("unsupported chip, no register table".into());
+            };
+            let peripherals = chip.info().peripherals;
+            let names: Vec<_> = peripherals.iter().map(|p| p.name).collect();
+            match command {
+                RegCommands::Read { name } => {
+                    let Some(register) = regs::resolve(peripherals, name.trim()) else {
+                        return Err(format!(
+                            "unknown register {}, peripherals of {} are: {}",
+                            name,
+                            chip.info().name,
+                            names.join(", ")
+                        )
+                        .into());
+                    };
+                    print_register(fel, &register)?;
+                }
+                RegCommands::Dump { peripheral } => {
+                    let Some(peripheral) = regs::find(peripherals, peripheral.trim()) else {
+                        return Err(format!(
+                            "unknown peripheral {}, peripherals of {} are: {}",
+                            peripheral,
+                            chip.info().name,
+                            names.join(", ")
+                        )
+                        .into());
+                    };
+                    for register in peripheral.registers() {
+                        print_register(fel, &register)?;
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn version_json(version: Version, chip: Option<Chip>) -> serde_json::Value {
+    json!({
+        "magic": String::from_utf8_lossy(&version.magic()),
+        "id": version.id(),
+        "chip": chip.map(|chip| chip.info().name),
+        "firmware": version.firmware(),
+        "protocol": version.protocol(),
+        "scratchpad": version.scratchpad(),
+    })
+}
+
+fn sid_json(sid: &Sid) -> serde_json::Value {
+    json!({
+        "sid": sid.to_string(),
+        "chip_id": sid.chip_id,
+        "ddr_efuse_type": sid.ddr_efuse_type,
+    })
+}
+
+fn print_register<T: FelTransport>(
+    fel: &Fel<T>,
+    register: &ResolvedRegister,
+) -> Result<(), rfel::Error> {
+    let mut buf = [0u8; 4];
+    fel.read_address(register.address, &mut buf)?;
+    let value = u32::from_le_bytes(buf);
+    outln!(
+        "{:<32} 0x{:08x}: 0x{:08x}",
+        register.name,
+        register.address,
+        value
+    );
+    if let Some(decode) = register.decode {
+        for (field, field_value) in decode(value) {
+            outln!("    {}: {}", field, field_value);
+        }
+    }
+    Ok(())
+}
+
+/// Open SPI0 controller of connected chip.
+fn open_spi0<T: FelTransport>(fel: &Fel<T>) -> Result<Spi<'_, T>, Box<dyn Error + Send + Sync>> {
+    let Some(chip) = fel.chip()? else {
+        return Err("unsupported chip, cannot locate SPI0 controller".into());
+    };
+    let Some(spi0) = &chip.info().spi0 else {
+        return Err(format!("SPI flash is not supported on chip {}", chip.info().name).into());
+    };
+    Ok(Spi::open(fel, spi0)?)
+}
+
+fn run_mmc<T: FelTransport>(
+    mmc: &Mmc<T>,
+    command: MmcCommands,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let card_blocks = mmc.card_blocks()?;
+    let card_size = card_blocks as usize * mmc::BLOCK_SIZE;
+    match command {
+        MmcCommands::Detect => {
+            outln!("{} blocks, {} MiB", card_blocks, card_size >> 20);
+        }
+        MmcCommands::Write { offset, file } => {
+            let offset: usize = parse_arg("offset", &offset)?;
+            let buf = read_file(&file)?;
+            check_card_range(card_size, offset, buf.len())?;
+            let block = (offset / mmc::BLOCK_SIZE) as u32;
+            let piece = mmc.buffer_blocks() * mmc::BLOCK_SIZE;
+            let progress = progress_bar("Writing", buf.len());
+            let start = Instant::now();
+            for (index, chunk) in buf.chunks(piece).enumerate() {
+                mmc.write(block + (index * mmc.buffer_blocks()) as u32, chunk)?;
+                progress.inc(chunk.len() as u64);
+            }
+            progress.finish();
+            print_throughput("written", buf.len(), start);
+        }
+        MmcCommands::Read {
+            offset,
+            length,
+            file,
+        } => {
+            let offset: usize = parse_arg("offset", &offset)?;
+            let length: usize = parse_arg("length", &length)?;
+            check_card_range(card_size, offset, length)?;
+            let block = (offset / mmc::BLOCK_SIZE) as u32;
+            let piece = mmc.buffer_blocks() * mmc::BLOCK_SIZE;
+            let mut buf = vec![0u8; length];
+            let progress = progress_bar("Reading", length);
+            let start = Instant::now();
+            for (index, chunk) in buf.chunks_mut(piece).enumerate() {
+                mmc.read(block + (index * mmc.buffer_blocks()) as u32, chunk)?;
+                progress.inc(chunk.len() as u64);
+            }
+            progress.finish();
+            print_throughput("read", length, start);
+            write_file(&file, &buf)?;
+        }
+    }
+    Ok(())
+}
+
+/// Check a card region lies inside card and starts at a block.
+fn check_card_range(
+    card_size: usize,
+    offset: usize,
+    length: usize,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    if !offset.is_multiple_of(mmc::BLOCK_SIZE) {
+        return Err(format!(
+            "card offset 0x{:x} is not a multiple of block size {}",
+            offset,
+            mmc::BLOCK_SIZE
+        )
+        .into());
+    }
+    if offset.checked_add(length).is_none_or(|end| end > card_size) {
+        return Err(format!(
+            "region 0x{:x} with length 0x{:x} exceeds card size 0x{:x}",
+            offset, length, card_size
+        )
+        .into());
+    }
+    Ok(())
+}
+
+fn run_spinor<B: SpiBus>(
+    nor: &SpiNor<B>,
+    command: SpinorCommands,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let info = nor.info();
+    let min_erase = info.erase[0].size;
+    match command {
+        SpinorCommands::Detect => {
+            let [a, b, c] = info.id;
+            outln!(
+                "{} (JEDEC ID {:02x}{:02x}{:02x}), {} KiB",
+                info.name,
+                a,
+                b,
+                c,
+                info.size / 1024
+            );
+            for erase in &info.erase {
+                outln!(
+                    "erase {} KiB with opcode 0x{:02x}",
+                    erase.size / 1024,
+                    erase.opcode
+                );
+            }
+        }
+        SpinorCommands::Erase { address, length } => {
+            let address: u32 = parse_arg("address", &address)?;
+            let length: u32 = parse_arg("length", &length)?;
+            check_flash_range(info.size, min_erase, address, length as usize)?;
+            let progress = progress_bar("Erasing", length as usize);
+            for offset in (0..length).step_by(CHUNK_SIZE) {
+                let len = (length - offset).min(CHUNK_SIZE as u32);
+                nor.erase(address + offset, len)?;
+                progress.inc(len as u64);
+            }
+            progress.finish();
+        }
+        SpinorCommands::Write { address, file } => {
+            let address: u32 = parse_arg("address", &address)?;
+            let buf = read_file(&file)?;
+            check_flash_range(info.size, min_erase, address, buf.len())?;
+            let progress = progress_bar("Erasing", buf.len());
+            for (index, chunk) in buf.chunks(CHUNK_SIZE).enumerate() {
+                nor.erase(address + (index * CHUNK_SIZE) as u32, chunk.len() as u32)?;
+                progress.inc(chunk.len() as u64);
+            }
+            progress.finish();
+            let progress = progress_bar("Writing", buf.len());
+            let start = Instant::now();
+            for (index, chunk) in buf.chunks(CHUNK_SIZE).enumerate() {
+                let chunk_address = address + (index * CHUNK_SIZE) as u32;
+                nor.program(chunk_address, chunk)?;
+                nor.verify(chunk_address, chunk)?;
+                progress.inc(chunk.len() as u64);
+            }
+            progress.finish();
+            print_throughput("written and verified", buf.len(), start);
+        }
+        SpinorCommands::Read {
+            address,
+            length,
+            file,
+        } => {
+            let address: u32 = parse_arg("address", &address)?;
+            let length: usize = parse_arg("length", &length)?;
+            check_flash_range(info.size, 1, address, length)?;
+            let mut buf = vec![0u8; length];
+            let progress = progress_bar("Reading", length);
+            let start = Instant::now();
+            for (index, chunk) in buf.chunks_mut(CHUNK_SIZE).enumerate() {
+                nor.read(address + (index * CHUNK_SIZE) as u32, chunk)?;
+                progress.inc(chunk.len() as u64);
+            }
+            progress.finish();
+            print_throughput("read", length, start);
+            write_file(&file, &buf)?;
+        }
+    }
+    Ok(())
+}
+
+fn run_spinand<B: SpiBus>(
+    nand: &SpiNand<B>,
+    command: SpinandCommands,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let info = nand.info();
+    let block_size = info.block_size();
+    match command {
+        SpinandCommands::Detect => {
+            let [a, b, c] = info.id;
+            outln!(
+                "{} (ID {:02x}{:02x}{:02x}), {} MiB, {}+{} bytes per page, {} pages per block",
+                info.name,
+                a,
+                b,
+                c,
+                info.size() >> 20,
+                info.page_size,
+                info.spare_size,
+                info.pages_per_block
+            );
+            let mut bad_blocks = Vec::new();
+            for block in 0..info.blocks {
+                if nand.is_bad_block(block)? {
+                    bad_blocks.push(block);
+                }
+            }
+            outln!("{} bad blocks {:?}", bad_blocks.len(), bad_blocks);
+        }
+        SpinandCommands::Erase { address, length } => {
+            let address: u32 = parse_arg("address", &address)?;
+            let length: u32 = parse_arg("length", &length)?;
+            check_flash_range(info.size(), block_size, address, length as usize)?;
+            let progress = progress_bar(
+                "Erasing",
+                length.div_ceil(block_size) as usize * block_size as usize,
+            );
+            let skipped = nand.erase(address, length, |len| progress.inc(len as u64))?;
+            progress.finish();
+            outln!("{} bad blocks skipped", skipped);
+        }
+        SpinandCommands::Write { address, file } => {
+            let address: u32 = parse_arg("address", &address)?;
+            let buf = read_file(&file)?;
+            check_flash_range(info.size(), block_size, address, buf.len())?;
+            let progress = progress_bar("Writing", buf.len());
+            let start = Instant::now();
+            nand.write(address, &buf, |len| progress.inc(len as u64))?;
+            progress.finish();
+            print_throughput("written and verified", buf.len(), start);
+        }
+        SpinandCommands::Read {
+            address,
+            length,
+            file,
+        } => {
+            let address: u32 = parse_arg("address", &address)?;
+            let length: usize = parse_arg("length", &length)?;
+            check_flash_range(info.size(), block_size, address, length)?;
+            let mut buf = vec![0u8; length];
+            let progress = progress_bar("Reading", length);
+            let start = Instant::now();
+            nand.read(address, &mut buf, |len| progress.inc(len as u64))?;
+            progress.finish();
+            print_throughput("read", length, start);
+            write_file(&file, &buf)?;
+        }
+        SpinandCommands::Boot0 {
+            file,
+            split,
+            copies,
+        } => {
+            let image = read_file(&file)?;
+            if EgonHead::parse(&image).is_none() {
+                return Err(format!("{} is not an eGON.BT0 image", file.display()).into());
+            }
+            let split = split.unwrap_or(info.page_size);
+            if split == 0 || split > info.page_size {
+                return Err(format!(
+                    "split size {} should be between 1 and page size {}",
+                    split, info.page_size
+                )
+                .into());
+            }
+            let capacity = nand.boot0_capacity(split);
+            if image.len() > capacity as usize {
+                return Err(format!(
+                    "boot0 image length {} exceeds {} bytes of one block with split size {}",
+                    image.len(),
+                    capacity,
+                    split
+                )
+                .into());
+            }
+            nand.write_boot0(&image, split, copies)?;
+            outln!("{} copies of boot0 written", copies);
+        }
+    }
+    Ok(())
+}
+
+/// Read an eGON.BT0 image and cut it to the length in its head.
+fn read_egon(file: &Path) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+    let mut image = read_file(file)?;
+    match EgonHead::verify(&image) {
+        Ok(head) => {
+            image.truncate(head.length as usize);
+            Ok(image)
+        }
+        Err(e) => Err(format!("{}: {}", file.display(), e).into()),
+    }
+}
+
+/// Load eGON.BT0 image into SPL region, run it and wait for it to return to FEL.
+///
+/// Returns SPL region.
+fn run_spl<T: FelTransport>(
+    fel: &Fel<T>,
+    image: &[u8],
+) -> Result<Region, Box<dyn Error + Send + Sync>> {
+    let Some(chip) = fel.chip()? else {
+        return Err("unsupported chip, cannot locate SRAM to load SPL".into());
+    };
+    let spl = chip.info().spl;
+    if image.len() > spl.size as usize {
+        return Err(format!(
+            "eGON.BT0 image length {} exceeds SPL region size {} of chip {}",
+            image.len(),
+            spl.size,
+            chip.info().name
+        )
+        .into());
+    }
+    fel.write_address(spl.address, image)?;
+    fel.exec(spl.address)?;
+    // ROM resumes FEL mode once SPL returns; request version to wait for it.
+    let version = fel.get_version()?;
+    debug!("SPL returned to FEL, {:x?}", version);
+    Ok(spl)
+}
+
+fn run_script(
+    info: &DeviceInfo,
+    path: &Path,
+    chunk_size: usize,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("cannot read file {}: {}", path.display(), e))?;
+    let base = path.parent().unwrap_or(Path::new(""));
+    let script = Script::parse(&text, base)?;
+    let mut info = info.clone();
+    let mut steps = &script.steps[..];
+    loop {
+        let next = {
+            let device = info.open()?;
+            let mut interface = device.claim_interface(0)?;
+            let mut fel = Fel::open_interface(&mut interface)?;
+            fel.set_chunk_size(chunk_size);
+            run_steps(&fel, steps)?
+        };
+        let Some(index) = next else {
+            return Ok(());
+        };
+        let Step::WaitForReenumerate { timeout } = steps[index] else {
+            unreachable!("steps stop only to wait for device")
+        };
+        let timeout = Duration::from_secs(timeout.unwrap_or(10));
+        info = match device::wait_for_reenumeration(&info, timeout)? {
+            Some(info) => info,
+            None => return Err(rfel::Error::Timeout.into()),
+        };
+        debug!(
+            "device re-enumerated at {}:{}",
+            info.bus_number(),
+            info.device_address()
+        );
+        steps = &steps[index + 1..];
+    }
+}
+
+/// Run script steps until one waits for device re-enumeration.
+///
+/// Returns index of the waiting step, or `None` if all steps are done.
+fn run_steps<T: FelTransport>(
+    fel: &Fel<T>,
+    steps: &[Step],
+) -> Result<Option<usize>, Box<dyn Error + Send + Sync>> {
+    for (index, step) in steps.iter().enumerate() {
+        debug!("script step {:?}", step);
+        match step {
+            Step::Write { address, file } => {
+                let address: u32 = script_value(address)?;
+                fel.write_address(address, &read_file(file)?)?;
+            }
+            Step::Write32 { address, value } => {
+                let address: u32 = script_value(address)?;
+                let value: u32 = script_value(value)?;
+                fel.write_address(address, &value.to_le_bytes())?;
+            }
+            Step::Read {
+                address,
+                length,
+                file,
+            } => {
+                let address: u32 = script_value(address)?;
+                let length: usize = script_value(length)?;
+                let mut buf = vec![0u8; length];
+                fel.read_address(address, &mut buf)?;
+                write_file(file, &buf)?;
+            }
+            Step::Verify { address, file } => {
+                let address: u32 = script_value(address)?;
+                let expected = read_file(file)?;
+                rfel::check_range(address, expected.len())?;
+                let mut buf = vec![0u8; STREAM_SIZE];
+                for (index, chunk) in expected.chunks(STREAM_SIZE).enumerate() {
+                    let offset = (index * STREAM_SIZE) as u32;
+                    let actual = &mut buf[..chunk.len()];
+                    fel.read_address(address + offset, actual)?;
+                    if let Some(i) = actual.iter().zip(chunk).position(|(a, b)| a != b) {
+                        let address = address + offset + i as u32;
+                        return Err(rfel::Error::VerifyMismatch { address }.into());
+                    }
+                }
+            }
+            Step::Exec { address } => fel.exec(script_value(address)?)?,
+            Step::Reset => {
+                if !fel.reset()? {
+                    return Err("unsupported chip, cannot locate watchdog".into());
+                }
+            }
+            Step::WaitForReenumerate { .. } => return Ok(Some(index)),
+            Step::Sleep { ms } => thread::sleep(Duration::from_millis(*ms)),
+        }
+    }
+    Ok(None)
+}
+
+fn script_value<T: core::str::FromStr + num_traits::Num>(
+    value: &str,
+) -> Result<T, Box<dyn Error + Send + Sync>> {
+    parse_arg("number", value)
+}
+
+/// Parse numeric argument, failing with a message naming what it is.
+fn parse_arg<T: core::str::FromStr + num_traits::Num>(
+    name: &str,
+    value: &str,
+) -> Result<T, Box<dyn Error + Send + Sync>> {
+    match parse_value(value.trim()) {
+        Some(value) => Ok(value),
+        None => Err(format!(
+            "invalid {} {:?}, should be hexadecimal like 0x40000000, or decimal like 1073741824",
+            name, value
+        )
+        .into()),
+    }
+}
+
+/// Check a flash region lies inside flash and starts at `align`.
+fn check_flash_range(
+    size: u32,
+    align: u32,
+    address: u32,
+    length: usize,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    if !address.is_multiple_of(align) {
+        return Err(format!(
+            "flash address 0x{:x} is not aligned to erase size 0x{:x}",
+            address, align
+        )
+        .into());
+    }
+    if address as u64 + length as u64 > size as u64 {
+        return Err(format!(
+            "region 0x{:x} with length 0x{:x} exceeds flash size 0x{:x}",
+            address, length, size
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Run commands working on files only, returning `None` for commands that need a device.
+fn run_host(command: &Commands) -> Option<Result<(), Box<dyn Error + Send + Sync>>> {
+    match command {
+        Commands::Mkimage {
+            input,
+            output,
+            verify,
+            align,
+        } => Some(mkimage(input, output.as_deref(), *verify, *align)),
+        Commands::Toc { command } => Some(toc(command)),
+        _ => None,
+    }
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+    std::fs::read(path).map_err(|e| format!("cannot read file {}: {}", path.display(), e).into())
+}
+
+fn write_file(path: &Path, data: &[u8]) -> Result<(), Box<dyn Error + Send + Sync>> {
+    std::fs::write(path, data)
+        .map_err(|e| format!("cannot write file {}: {}", path.display(), e).into())
+}
+
+fn toc(command: &TocCommands) -> Result<(), Box<dyn Error + Send + Sync>> {
+    match command {
+        TocCommands::Dump { file } => {
+            let image = read_file(file)?;
+            if image.starts_with(&Toc0::NAME) {
+                let toc0 = Toc0::parse(&image)?;
+                println!(
+                    "TOC0 package, {} items, platform {:02x?}, variant {:02x?}",
+                    toc0.items.len(),
+                    toc0.platform,
+                    toc0.variant
+                );
+                for (index, item) in toc0.items.iter().enumerate() {
                     println!(
-                        "error: invalid address, shoule be hexadecimal like 0x40000000, or decimal like 1073741824"
+                        "item {}: {} (0x{:08x}), {} bytes, type {}, load address 0x{:08x}",
+                        index,
+                        Toc0::item_name(item.name),
+                        item.name,
+                        item.data.len(),
+                        item.kind,
+                        item.load_address
                     );
-                    return;
                 }
-            };
-            let value: u32 = match parse_value(value.trim()) {
-                Some(value) => value,
-                None => {
+            } else {
+                let toc1 = Toc1::parse(&image)?;
+                println!(
+                    "TOC1 package {:?}, {} items, version {}.{}",
+                    toc1.name,
+                    toc1.items.len(),
+                    toc1.version_main,
+                    toc1.version_sub
+                );
+                for (index, item) in toc1.items.iter().enumerate() {
                     println!(
-                        "error: invalid address, shoule be hexadecimal like 0x40000000, or decimal like 1073741824"
+                        "item {}: {:?}, {} bytes, type {}, run address 0x{:08x}",
+                        index,
+                        item.name,
+                        item.data.len(),
+                        item.kind,
+                        item.run_address
                     );
-                    return;
                 }
+            }
+        }
+        TocCommands::Toc0 {
+            output,
+            firmware,
+            load_address,
+            certificate,
+            key,
+            align,
+        } => {
+            let load_address = parse_value(load_address.trim())
+                .ok_or_else(|| format!("invalid load address {:?}", load_address))?;
+            let item = |name, data, load_address| Toc0Item {
+                name,
+                status: 0,
+                kind: 0,
+                load_address,
+                data,
+            };
+            let certificate = match certificate {
+                Some(path) => read_file(path)?,
+                None => vec![0; Toc0::CERTIFICATE_PLACEHOLDER_SIZE],
+            };
+            let mut items = vec![
+                item(Toc0::ITEM_CERTIFICATE, certificate, 0),
+                item(Toc0::ITEM_FIRMWARE, read_file(firmware)?, load_address),
+            ];
+            if let Some(key) = key {
+                items.push(item(Toc0::ITEM_KEY, read_file(key)?, 0));
+            }
+            let toc0 = Toc0 {
+                serial: 0,
+                status: 0,
+                platform: [0; 4],
+                variant: [0; 4],
+                items,
+            };
+            let image = toc0.build(*align);
+            write_file(output, &image)?;
+            println!(
+                "{}: TOC0 package of {} bytes",
+                output.display(),
+                image.len()
+            );
+        }
+        TocCommands::Toc1 {
+            output,
+            items,
+            align,
+        } => {
+            let mut toc1 = Toc1 {
+                name: Toc1::DEFAULT_NAME.to_string(),
+                serial: 0,
+                status: 0,
+                version_main: 1,
+                version_sub: 0,
+                items: Vec::new(),
             };
-            fel.write_address(address, &value.to_le_bytes());
+            for spec in items {
+                let invalid = || format!("invalid item {:?}, should be NAME@ADDRESS=FILE", spec);
+                let (name, rest) = spec.split_once('@').ok_or_else(invalid)?;
+                let (address, file) = rest.split_once('=').ok_or_else(invalid)?;
+                let run_address = parse_value(address.trim()).ok_or_else(invalid)?;
+                toc1.items.push(Toc1Item {
+                    name: name.to_string(),
+                    encrypt: 0,
+                    kind: 0,
+                    run_address,
+                    index: 0,
+                    data: read_file(Path::new(file))?,
+                });
+            }
+            let image = toc1.build(*align);
+            write_file(output, &image)?;
+            println!(
+                "{}: TOC1 package of {} bytes",
+                output.display(),
+                image.len()
+            );
         }
     }
+    Ok(())
+}
+
+/// Build eGON.BT0 image from `input` into `output`, or verify `input` as an image.
+fn mkimage(
+    input: &Path,
+    output: Option<&Path>,
+    verify: bool,
+    align: usize,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let data =
+        std::fs::read(input).map_err(|e| format!("cannot read file {}: {}", input.display(), e))?;
+    if verify {
+        let head = EgonHead::verify(&data).map_err(|e| format!("{}: {}", input.display(), e))?;
+        println!(
+            "{}: valid eGON.BT0 image, length {}, checksum 0x{:08x}",
+            input.display(),
+            head.length,
+            head.checksum
+        );
+        return Ok(());
+    }
+    let mut image = if data.starts_with(b"\x7fELF") {
+        elf_to_binary(&data)?
+    } else {
+        data
+    };
+    let Some(head) = EgonHead::finalize(&mut image, align) else {
+        return Err(format!("{} does not start with an eGON.BT0 head", input.display()).into());
+    };
+    let output = output.expect("output is required unless verifying");
+    write_file(output, &image)?;
+    println!(
+        "{}: length {}, checksum 0x{:08x}",
+        output.display(),
+        head.length,
+        head.checksum
+    );
+    Ok(())
+}
+
+/// Flatten loadable segments of an ELF file into a binary starting at the lowest physical address.
+fn elf_to_binary(data: &[u8]) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+    use elf::{ElfBytes, abi::PT_LOAD, endian::AnyEndian};
+    /// Largest binary accepted, far above any on-chip SRAM.
+    const MAX_SIZE: u64 = 16 << 20;
+    let file = ElfBytes::<AnyEndian>::minimal_parse(data)?;
+    let segments: Vec<_> = file
+        .segments()
+        .ok_or("ELF file has no program headers")?
+        .iter()
+        .filter(|phdr| phdr.p_type == PT_LOAD && phdr.p_filesz > 0)
+        .collect();
+    let start = segments
+        .iter()
+        .map(|phdr| phdr.p_paddr)
+        .min()
+        .ok_or("ELF file has no loadable segment")?;
+    let end = segments
+        .iter()
+        .map(|phdr| phdr.p_paddr + phdr.p_filesz)
+        .max()
+        .unwrap();
+    if end - start > MAX_SIZE {
+        return Err(format!("ELF loadable segments span {} bytes", end - start).into());
+    }
+    let mut binary = vec![0u8; (end - start) as usize];
+    for phdr in segments {
+        let offset = (phdr.p_paddr - start) as usize;
+        let data = file.segment_data(&phdr)?;
+        binary[offset..offset + data.len()].copy_from_slice(data);
+    }
+    Ok(binary)
+}
+
+fn progress_bar(message: &'static str, length: usize) -> ProgressBar {
+    let progress = ProgressBar::new(length as u64);
+    progress.set_style(
+        ProgressStyle::with_template(
+            "{msg} [{wide_bar}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta})",
+        )
+        .unwrap()
+        .progress_chars("=> "),
+    );
+    progress.set_message(message);
+    progress
+}
+
+fn print_throughput(action: &str, length: usize, start: Instant) {
+    let elapsed = start.elapsed();
+    let speed = length as f64 / elapsed.as_secs_f64() / 1e6;
+    outln!(
+        "{} bytes {} in {:.2?}, {:.2} MB/s",
+        length,
+        action,
+        elapsed,
+        speed
+    );
 }
 
 fn hexdump(buf: &[u8], base_address: u32) {
+    use core::fmt::Write;
     for i in (0..buf.len()).step_by(16) {
-        print!("{:08x}: ", base_address as usize + i);
+        let mut line = format!("{:08x}: ", base_address as usize + i);
         let chunk_len = 16.min(buf.len() - i);
         for j in 0..chunk_len {
-            print!("{:02x} ", buf[i + j]);
+            write!(line, "{:02x} ", buf[i + j]).unwrap();
         }
-        print!(" ");
+        line.push(' ');
         for _ in chunk_len..16 {
-            print!("   ");
+            line.push_str("   ");
         }
         for byte in &buf[i..(i + chunk_len)] {
             if byte.is_ascii_graphic() || *byte == b' ' {
-                print!("{}", *byte as char);
+                line.push(*byte as char);
             } else {
-                print!(".");
+                line.push('.');
             }
         }
-        println!()
+        outln!("{}", line)
     }
 }
 
+/// Parse fill pattern, repeating values written as one byte into a word.
+fn parse_pattern(value: &str) -> Option<u32> {
+    let pattern: u32 = parse_value(value)?;
+    let is_byte = match value.strip_prefix("0x") {
+        Some(digits) => digits.len() <= 2,
+        None => pattern <= 0xff,
+    };
+    Some(if is_byte {
+        pattern * 0x0101_0101
+    } else {
+        pattern
+    })
+}
+
 fn parse_value<T: core::str::FromStr + num_traits::Num>(value: &str) -> Option<T> {
     if value.starts_with("0x") {
         T::from_str_radix(value.strip_prefix("0x").unwrap(), 16).ok()
@@ -165,3 +1869,567 @@ fn parse_value<T: core::str::FromStr + num_traits::Num>(value: &str) -> Option<T
         value.parse::<T>().ok()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Cli, DEVICE_TAG, OUTPUT, error_json, mkimage, parse_pattern, run, run_host, run_steps,
+        sid_json, tag_json, version_json,
+    };
+    use clap::Parser;
+    use rfel::{
+        EgonHead, Fel,
+        mock::MockDevice,
+        script::{Script, Step},
+        toc::{Toc0, Toc1},
+    };
+    use std::{cell::Cell, error::Error, path::PathBuf, rc::Rc};
+
+    fn d1() -> Fel<MockDevice> {
+        Fel::new(MockDevice::new(0x00185900, 0x7e00))
+    }
+
+    fn run_command<'a>(
+        fel: &Fel<MockDevice>,
+        args: impl IntoIterator<Item = &'a str>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let cli = Cli::try_parse_from(core::iter::once("rfel").chain(args)).unwrap();
+        run(fel, cli.command, cli.format)
+    }
+
+    /// Take lines of command output printed so far.
+    fn output() -> Vec<String> {
+        OUTPUT.take()
+    }
+
+    fn temp_file(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("rfel-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn command_read32_write32() {
+        let fel = d1();
+        run_command(&fel, ["write32", "0x40000000", "0x12345678"]).unwrap();
+        let mut buf = [0u8; 4];
+        fel.transport().read_memory(0x4000_0000, &mut buf);
+        assert_eq!(u32::from_le_bytes(buf), 0x12345678);
+        run_command(&fel, ["read32", "0x40000000"]).unwrap();
+        assert_eq!(output(), ["0x12345678"]);
+        run_command(&fel, ["hexdump", "0x40000000", "32"]).unwrap();
+        run_command(&fel, ["version"]).unwrap();
+    }
+
+    #[test]
+    fn command_address_overflow() {
+        let fel = d1();
+        let err = run_command(&fel, ["hexdump", "0xfffffff0", "0x20"]).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<rfel::Error>(),
+            Some(rfel::Error::AddressOverflow { .. })
+        ));
+        assert!(run_command(&fel, ["hexdump", "0x100000000", "0x10"]).is_err());
+        assert!(run_command(&fel, ["crc32", "0xfffffff0", "0x20"]).is_err());
+    }
+
+    #[test]
+    fn command_sid() {
+        let fel = d1();
+        fel.transport()
+            .write_memory(0x0300_6200, &0x9340_4800u32.to_le_bytes());
+        run_command(&fel, ["sid"]).unwrap();
+        assert_eq!(
+            output(),
+            [
+                "93404800000000000000000000000000",
+                "chip_id: 93404800 00000000 00000000 00000000",
+                "ddr_efuse_type: 0x0",
+            ]
+        );
+        let sid = fel.read_sid().unwrap().unwrap();
+        assert_eq!(sid.chip_id, [0x9340_4800, 0, 0, 0]);
+    }
+
+    #[test]
+    fn command_json_output() {
+        let fel = d1();
+        let version = fel.get_version().unwrap();
+        assert_eq!(
+            version_json(version, fel.chip().unwrap()),
+            serde_json::json!({
+                "magic": "AWUSBFEX",
+                "id": 0x0018_5900,
+                "chip": "D1",
+                "firmware": 1,
+                "protocol": 1,
+                "scratchpad": 0x7e00,
+            })
+        );
+        fel.transport()
+            .write_memory(0x0300_6200, &0x9340_4800u32.to_le_bytes());
+        let sid = fel.read_sid().unwrap().unwrap();
+        assert_eq!(
+            sid_json(&sid),
+            serde_json::json!({
+                "sid": "93404800000000000000000000000000",
+                "chip_id": [0x9340_4800u32, 0, 0, 0],
+                "ddr_efuse_type": 0,
+            })
+        );
+        for args in [
+            ["--format", "json", "version"].as_slice(),
+            &["--format", "json", "sid"],
+            &["--format", "json", "read32", "0x03006200"],
+            &["--format", "json", "hexdump", "0x03006200", "0x8"],
+        ] {
+            run_command(&fel, args.iter().copied()).unwrap();
+        }
+        let objects: Vec<serde_json::Value> = output()
+            .iter()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(objects.len(), 4);
+        assert_eq!(objects[0]["chip"], "D1");
+        assert_eq!(objects[1]["sid"], "93404800000000000000000000000000");
+        assert_eq!(
+            objects[2],
+            serde_json::json!({ "address": 0x0300_6200, "value": 0x9340_4800u32 })
+        );
+        assert_eq!(
+            objects[3],
+            serde_json::json!({ "address": 0x0300_6200, "length": 8, "data": "0048409300000000" })
+        );
+        // failing commands report through error object.
+        let file = temp_file("missing.bin");
+        let e = run_command(
+            &fel,
+            [
+                "--format",
+                "json",
+                "write",
+                "0x40000000",
+                file.to_str().unwrap(),
+            ],
+        )
+        .unwrap_err();
+        let message = format!("cannot read file {}: ", file.display());
+        let json = error_json(e);
+        assert_eq!(json.as_object().unwrap().len(), 1);
+        assert!(json["error"].as_str().unwrap().starts_with(&message));
+        let e = run_command(&fel, ["--format", "json", "read32", "0xg"]).unwrap_err();
+        assert_eq!(
+            error_json(e),
+            serde_json::json!({
+                "error": "invalid address \"0xg\", should be hexadecimal like 0x40000000, or decimal like 1073741824"
+            })
+        );
+    }
+
+    #[test]
+    fn json_device_tag() {
+        let value = serde_json::json!({ "error": "timed out" });
+        assert_eq!(tag_json(value.clone()), value);
+        DEVICE_TAG.set(Some("1-2.3".to_string()));
+        assert_eq!(
+            tag_json(value),
+            serde_json::json!({ "error": "timed out", "device": "1-2.3" })
+        );
+    }
+
+    #[test]
+    fn command_fill_copy_compare() {
+        assert_eq!(parse_pattern("0xa5"), Some(0xa5a5_a5a5));
+        assert_eq!(parse_pattern("255"), Some(0xffff_ffff));
+        assert_eq!(parse_pattern("0x00a5"), Some(0xa5));
+        assert_eq!(parse_pattern("0x12345678"), Some(0x1234_5678));
+        let fel = Fel::new(MockDevice::new(0x1234_5678, 0x7e00));
+        run_command(&fel, ["fill", "0x40000002", "3", "0xa5"]).unwrap();
+        let mut buf = [0u8; 8];
+        fel.transport().read_memory(0x4000_0000, &mut buf);
+        assert_eq!(buf, [0, 0, 0xa5, 0xa5, 0xa5, 0, 0, 0]);
+        run_command(&fel, ["fill", "0x40000000", "6", "0x12345678"]).unwrap();
+        fel.transport().read_memory(0x4000_0000, &mut buf);
+        assert_eq!(buf, [0x78, 0x56, 0x34, 0x12, 0x78, 0x56, 0, 0]);
+        run_command(&fel, ["copy", "0x40000000", "0x40001000", "6"]).unwrap();
+        fel.transport().read_memory(0x4000_1000, &mut buf);
+        assert_eq!(buf, [0x78, 0x56, 0x34, 0x12, 0x78, 0x56, 0, 0]);
+        let lines = output();
+        assert!(lines[0].starts_with("3 bytes filled in "));
+        assert!(lines[1].starts_with("6 bytes filled in "));
+        assert!(lines[2].starts_with("6 bytes copied in "));
+        run_command(&fel, ["compare", "0x40000000", "0x40001000", "6"]).unwrap();
+        assert_eq!(output(), ["6 bytes compared, regions are equal"]);
+
+        fel.transport().write_memory(0x4000_1004, &[0x00]);
+        let e = run_command(&fel, ["compare", "0x40000000", "0x40001000", "6"]).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "regions differ at offset 0x4, 0x40000004 and 0x40001004"
+        );
+    }
+
+    #[test]
+    fn command_reset() {
+        let fel = d1();
+        run_command(&fel, ["reset"]).unwrap();
+        let mut buf = [0u8; 4];
+        fel.transport().read_memory(0x0205_00b8, &mut buf);
+        assert_eq!(u32::from_le_bytes(buf), 0x16aa_0001);
+    }
+
+    #[test]
+    fn command_write_read() {
+        let fel = d1();
+        let input = temp_file("write.bin");
+        let output = temp_file("read.bin");
+        let data: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+        std::fs::write(&input, &data).unwrap();
+        run_command(&fel, ["write", "0x40000000", input.to_str().unwrap()]).unwrap();
+        let mut buf = vec![0u8; data.len()];
+        fel.transport().read_memory(0x4000_0000, &mut buf);
+        assert_eq!(buf, data);
+        run_command(
+            &fel,
+            ["read", "0x40000000", "100000", output.to_str().unwrap()],
+        )
+        .unwrap();
+        assert_eq!(std::fs::read(&output).unwrap(), data);
+        std::fs::remove_file(input).unwrap();
+        std::fs::remove_file(output).unwrap();
+    }
+
+    #[test]
+    fn command_exec() {
+        let fel = d1();
+        let executed = Rc::new(Cell::new(None));
+        let executed_1 = executed.clone();
+        fel.transport().set_exec_handler(move |address, _| {
+            executed_1.set(Some(address));
+            Ok(())
+        });
+        run_command(&fel, ["exec", "0x40000000"]).unwrap();
+        assert_eq!(executed.get(), Some(0x4000_0000));
+    }
+
+    #[test]
+    fn command_spl() {
+        let fel = d1();
+        let mut image = vec![0u8; 0x1000];
+        image[4..12].copy_from_slice(&EgonHead::MAGIC);
+        image[16..20].copy_from_slice(&0x800u32.to_le_bytes());
+        let checksum = EgonHead::checksum(&image[..0x800]);
+        image[12..16].copy_from_slice(&checksum.to_le_bytes());
+        let file = temp_file("spl.bin");
+        std::fs::write(&file, &image).unwrap();
+        let executed = Rc::new(Cell::new(None));
+        let executed_1 = executed.clone();
+        fel.transport().set_exec_handler(move |address, _| {
+            executed_1.set(Some(address));
+            Ok(())
+        });
+        run_command(&fel, ["spl", file.to_str().unwrap()]).unwrap();
+        assert_eq!(executed.get(), Some(0x2_0000));
+        let mut buf = [0u8; 8];
+        fel.transport().read_memory(0x2_0004, &mut buf);
+        assert_eq!(buf, EgonHead::MAGIC);
+        std::fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn command_ddr() {
+        let fel = d1();
+        let mut image = vec![0u8; 0x800];
+        image[4..12].copy_from_slice(&EgonHead::MAGIC);
+        image[16..20].copy_from_slice(&0x800u32.to_le_bytes());
+        let checksum = EgonHead::checksum(&image);
+        image[12..16].copy_from_slice(&checksum.to_le_bytes());
+        let stub = temp_file("dram-stub.bin");
+        std::fs::write(&stub, &image).unwrap();
+        let profile = temp_file("board.toml");
+        std::fs::write(&profile, "base = \"lichee\"\ndram_clk = 528\n").unwrap();
+        // Pretend 512 MiB DRAM runs only at 528 MHz.
+        fel.transport().set_exec_handler(|address, memory| {
+            if memory.read_u32(address + 0x30) == 528 {
+                memory.write_u32(address + 0x90, 512);
+            }
+            Ok(())
+        });
+        let stub_arg = stub.to_str().unwrap();
+        run_command(&fel, ["ddr", profile.to_str().unwrap(), "--stub", stub_arg]).unwrap();
+        let mut buf = [0u8; 4];
+        fel.transport().read_memory(0x2_0090, &mut buf);
+        assert_eq!(u32::from_le_bytes(buf), 512);
+        fel.transport().read_memory(0x2_008c, &mut buf);
+        assert_eq!(u32::from_le_bytes(buf), 0x3405_0100);
+        // Built-in profile runs at 792 MHz; size word is cleared before running.
+        let e = run_command(&fel, ["ddr", "nezha", "--stub", stub_arg]).unwrap_err();
+        assert_eq!(e.to_string(), "DRAM initialization failed");
+        fel.transport().read_memory(0x2_0090, &mut buf);
+        assert_eq!(u32::from_le_bytes(buf), 0);
+        // H6 has no DRAM stub.
+        let h6 = Fel::new(MockDevice::new(0x0017_2800, 0x7e00));
+        let e = run_command(&h6, ["ddr", "nezha", "--stub", stub_arg]).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "DRAM initialization is not supported on chip H6"
+        );
+        std::fs::remove_file(stub).unwrap();
+        std::fs::remove_file(profile).unwrap();
+    }
+
+    #[test]
+    fn command_mkimage_elf() {
+        // ELF32 with two loadable segments at 0x20000 and 0x20100.
+        let mut elf = vec![0u8; 0x200];
+        elf[..16].copy_from_slice(b"\x7fELF\x01\x01\x01\0\0\0\0\0\0\0\0\0");
+        for (offset, value) in [
+            (16, 2u16),
+            (18, 0xf3),
+            (40, 52),
+            (42, 32),
+            (44, 2),
+            (46, 40),
+        ] {
+            elf[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+        }
+        for (offset, value) in [(20, 1u32), (24, 0x20000), (28, 52)] {
+            elf[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+        }
+        for (index, (file_offset, address, size)) in
+            [(0x100u32, 0x20000u32, 0x40u32), (0x180, 0x20100, 0x10)]
+                .into_iter()
+                .enumerate()
+        {
+            let phdr = 52 + index * 32;
+            for (offset, value) in [(0, 1), (4, file_offset), (8, address), (12, address)] {
+                elf[phdr + offset..phdr + offset + 4].copy_from_slice(&value.to_le_bytes());
+            }
+            for (offset, value) in [(16, size), (20, size), (24, 5), (28, 4)] {
+                elf[phdr + offset..phdr + offset + 4].copy_from_slice(&value.to_le_bytes());
+            }
+        }
+        elf[0x104..0x10c].copy_from_slice(&EgonHead::MAGIC);
+        elf[0x180..0x190].fill(0x5a);
+        let input = temp_file("mkimage.elf");
+        let output = temp_file("mkimage.bin");
+        std::fs::write(&input, &elf).unwrap();
+        mkimage(&input, Some(&output), false, 0x400).unwrap();
+        let image = std::fs::read(&output).unwrap();
+        assert_eq!(image.len(), 0x400);
+        assert_eq!(image[0x100..0x110], [0x5a; 16]);
+        let head = EgonHead::verify(&image).unwrap();
+        assert_eq!(head.length, 0x400);
+        mkimage(&output, None, true, 0x400).unwrap();
+        assert!(mkimage(&input, None, true, 0x400).is_err());
+        std::fs::remove_file(input).unwrap();
+        std::fs::remove_file(output).unwrap();
+    }
+
+    #[test]
+    fn command_toc() {
+        let firmware = temp_file("toc-firmware.bin");
+        let opensbi = temp_file("toc-opensbi.bin");
+        let toc0 = temp_file("toc0.bin");
+        let toc1 = temp_file("toc1.bin");
+        std::fs::write(&firmware, [0x11; 100]).unwrap();
+        std::fs::write(&opensbi, [0x22; 200]).unwrap();
+        let args = [
+            "rfel",
+            "toc",
+            "toc0",
+            toc0.to_str().unwrap(),
+            "--firmware",
+            firmware.to_str().unwrap(),
+            "--load-address",
+            "0x20060",
+        ];
+        let cli = Cli::try_parse_from(args).unwrap();
+        run_host(&cli.command).unwrap().unwrap();
+        let item = format!("opensbi@0x40000000={}", opensbi.display());
+        let args = [
+            "rfel",
+            "toc",
+            "toc1",
+            toc1.to_str().unwrap(),
+            "--item",
+            &item,
+        ];
+        let cli = Cli::try_parse_from(args).unwrap();
+        run_host(&cli.command).unwrap().unwrap();
+
+        let parsed = Toc0::parse(&std::fs::read(&toc0).unwrap()).unwrap();
+        assert_eq!(parsed.items[1].load_address, 0x20060);
+        assert_eq!(parsed.items[1].data, [0x11; 100]);
+        let parsed = Toc1::parse(&std::fs::read(&toc1).unwrap()).unwrap();
+        assert_eq!(parsed.items[0].name, "opensbi");
+        assert_eq!(parsed.items[0].run_address, 0x4000_0000);
+        for file in [&toc0, &toc1] {
+            let cli = Cli::try_parse_from(["rfel", "toc", "dump", file.to_str().unwrap()]).unwrap();
+            run_host(&cli.command).unwrap().unwrap();
+        }
+        let cli = Cli::try_parse_from(["rfel", "toc", "dump", firmware.to_str().unwrap()]).unwrap();
+        assert!(run_host(&cli.command).unwrap().is_err());
+        for file in [firmware, opensbi, toc0, toc1] {
+            std::fs::remove_file(file).unwrap();
+        }
+    }
+
+    #[test]
+    fn command_verify_crc32() {
+        let fel = Fel::new(MockDevice::new(0x1234_5678, 0x7e00));
+        let data: Vec<u8> = (0..100_000u32).map(|i| (i * 7) as u8).collect();
+        fel.transport().write_memory(0x4000_0000, &data);
+        let file = temp_file("verify.bin");
+        std::fs::write(&file, &data).unwrap();
+        run_command(&fel, ["verify", "0x40000000", file.to_str().unwrap()]).unwrap();
+        assert_eq!(output(), ["100000 bytes verified, memory matches file"]);
+        fel.transport().write_memory(0x4000_1000, &[0xff]);
+        fel.transport().write_memory(0x4000_2000, &[0xff]);
+        let e = run_command(&fel, ["verify", "0x40000000", file.to_str().unwrap()]).unwrap_err();
+        assert!(matches!(
+            e.downcast_ref(),
+            Some(rfel::Error::VerifyMismatch {
+                address: 0x4000_1000
+            })
+        ));
+        assert_eq!(
+            output(),
+            [
+                "0x40001000 (offset 0x1000): expected 0x00, found 0xff",
+                "0x40002000 (offset 0x2000): expected 0x00, found 0xff",
+                "2 of 100000 bytes differ from file",
+            ]
+        );
+        std::fs::remove_file(file).unwrap();
+
+        // CRC-32 check value of ASCII "123456789".
+        fel.transport().write_memory(0x4000_0000, b"123456789");
+        run_command(&fel, ["crc32", "0x40000000", "9"]).unwrap();
+        assert_eq!(output(), ["0xcbf43926"]);
+        run_command(&fel, ["--format", "json", "crc32", "0x40000000", "9"]).unwrap();
+        assert_eq!(
+            output(),
+            [r#"{"address":1073741824,"crc32":3421780262,"length":9}"#]
+        );
+        let e = run_command(&fel, ["crc32", "0x4000000g", "9"]).unwrap_err();
+        assert!(e.to_string().starts_with("invalid address \"0x4000000g\""));
+        let e = run_command(&fel, ["crc32", "0x40000000", "nine"]).unwrap_err();
+        assert!(e.to_string().starts_with("invalid length \"nine\""));
+    }
+
+    #[test]
+    fn command_reg() {
+        let fel = d1();
+        fel.transport()
+            .write_memory(0x0200_1000, &0xa800_2301u32.to_le_bytes());
+        run_command(&fel, ["reg", "read", "ccu.pll_cpu_control"]).unwrap();
+        // PLL_CPU_CTRL 0xa8002301: enabled and locking with N = 35, M = 1.
+        assert_eq!(
+            output(),
+            [
+                "ccu.pll_cpu_control              0x02001000: 0xa8002301",
+                "    is_pll_enabled: true",
+                "    is_pll_ldo_enabled: false",
+                "    is_lock_enabled: true",
+                "    is_locked: false",
+                "    is_pll_output_unmasked: true",
+                "    pll_n: 35",
+                "    pll_m: 1",
+            ]
+        );
+        let e = run_command(&fel, ["reg", "read", "ccu.unknown"]).unwrap_err();
+        assert!(e.to_string().starts_with("unknown register ccu.unknown"));
+        run_command(&fel, ["reg", "dump", "spi0"]).unwrap();
+        let lines = output();
+        assert_eq!(
+            lines.len(),
+            16 + lines.iter().filter(|l| l.starts_with("    ")).count()
+        );
+        assert!(lines.contains(&"    is_software_reset_finished: false".to_string()));
+        let reads: Vec<_> = fel
+            .transport()
+            .requests()
+            .iter()
+            .filter(|request| request.request == 0x103 && request.address != 0)
+            .map(|request| request.address)
+            .collect();
+        assert_eq!(reads.len(), 1 + 16);
+        assert_eq!(reads[0], 0x0200_1000);
+        assert_eq!(reads[16], 0x0402_5000 + 0x88);
+    }
+
+    #[test]
+    fn command_run_steps() {
+        let fel = d1();
+        let executed = Rc::new(Cell::new(None));
+        let executed_1 = executed.clone();
+        fel.transport().set_exec_handler(move |address, memory| {
+            executed_1.set(Some(address));
+            memory.write_u32(0x4000_0100, 0xcafe_f00d);
+            Ok(())
+        });
+        let input = temp_file("script-in.bin");
+        let output = temp_file("script-out.bin");
+        std::fs::write(&input, [1, 2, 3, 4, 5]).unwrap();
+        let text = format!(
+            r#"
+            [vars]
+            load = 0x40000000
+            input = "{}"
+
+            [[steps]]
+            action = "write"
+            address = "${{load}}"
+            file = "${{input}}"
+
+            [[steps]]
+            action = "verify"
+            address = "${{load}}"
+            file = "${{input}}"
+
+            [[steps]]
+            action = "exec"
+            address = "${{load}}"
+
+            [[steps]]
+            action = "write32"
+            address = "0x40000104"
+            value = "0x12345678"
+
+            [[steps]]
+            action = "read"
+            address = "0x40000100"
+            length = "8"
+            file = "{}"
+
+            [[steps]]
+            action = "wait-for-reenumerate"
+
+            [[steps]]
+            action = "write32"
+            address = "0x40000200"
+            value = "0"
+            "#,
+            input.display(),
+            output.display()
+        );
+        let script = Script::parse(&text, std::path::Path::new("")).unwrap();
+        assert_eq!(run_steps(&fel, &script.steps).unwrap(), Some(5));
+        assert_eq!(script.steps[5], Step::WaitForReenumerate { timeout: None });
+        assert_eq!(executed.get(), Some(0x4000_0000));
+        assert_eq!(
+            std::fs::read(&output).unwrap(),
+            [0x0d, 0xf0, 0xfe, 0xca, 0x78, 0x56, 0x34, 0x12]
+        );
+        assert_eq!(run_steps(&fel, &script.steps[6..]).unwrap(), None);
+        fel.transport().write_memory(0x4000_0002, &[0xff]);
+        let err = run_steps(&fel, &script.steps[1..2]).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<rfel::Error>(),
+            Some(rfel::Error::VerifyMismatch {
+                address: 0x4000_0002
+            })
+        ));
+        std::fs::remove_file(input).unwrap();
+        std::fs::remove_file(output).unwrap();
+    }
+}
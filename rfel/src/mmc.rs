@@ -0,0 +1,241 @@
+//! SD card and eMMC access through an SMHC helper image run over FEL.
+//!
+//! The helper is an eGON.BT0 image built from `examples/mmc-helper`. Its head
+//! is followed by a mailbox of 32-bit words, which host fills in before each
+//! run and helper updates before returning to FEL:
+//!
+//! | Word | Field         | Meaning                                              |
+//! |------|---------------|------------------------------------------------------|
+//! | 0    | `smhc`        | SMHC index, 0 for SD card or 2 for eMMC              |
+//! | 1    | `operation`   | 1 to read blocks into buffer, 2 to write them out    |
+//! | 2    | `block`       | first card block                                     |
+//! | 3    | `count`       | number of 512-byte blocks                            |
+//! | 4    | `buffer`      | address of block buffer in chip memory               |
+//! | 5    | `status`      | 0 done, 1 invalid request, 2 init failed, 3 too far, |
+//! |      |               | 4 write failed                                       |
+//! | 6    | `card_blocks` | card size in blocks                                  |
+//!
+//! Helper initializes SD cards by SD protocol and eMMC by MMC protocol, both
+//! on a 1-bit bus. It initializes the card on every run, so each run moves a whole
+//! buffer of blocks.
+
+use crate::{Error, Fel, FelTransport, Region, Result, egon::EgonHead, payload};
+
+/// Offset of mailbox in helper image.
+pub const MAILBOX_OFFSET: usize = EgonHead::OFFSET + EgonHead::SIZE;
+/// Size of card blocks in bytes.
+pub const BLOCK_SIZE: usize = 512;
+
+const OPERATION_READ: u32 = 1;
+const OPERATION_WRITE: u32 = 2;
+
+const STATUS_DONE: u32 = 0;
+const STATUS_INIT_FAILED: u32 = 2;
+const STATUS_OUT_OF_RANGE: u32 = 3;
+const STATUS_WRITE_FAILED: u32 = 4;
+const STATUS_READ_FAILED: u32 = 5;
+/// Status written before each run; helper which did not run leaves it as is.
+const STATUS_PENDING: u32 = 0xffff_ffff;
+
+/// SD card or eMMC on an SMHC of connected chip, accessed by helper image.
+pub struct Mmc<'a, T> {
+    fel: &'a Fel<T>,
+    base: u32,
+    smhc: u32,
+    buffer: Region,
+}
+
+impl<'a, T: FelTransport> Mmc<'a, T> {
+    /// Load helper image at `base` to access card on SMHC `smhc`, moving
+    /// blocks through `buffer` in chip memory.
+    ///
+    /// # Panics
+    ///
+    /// Panics if buffer cannot hold one block.
+    pub fn load(
+        fel: &'a Fel<T>,
+        base: u32,
+        image: &[u8],
+        smhc: u32,
+        buffer: Region,
+    ) -> Result<Self> {
+        assert!(
+            buffer.size as usize >= BLOCK_SIZE,
+            "buffer should hold one block"
+        );
+        fel.write_address(base, image)?;
+        Ok(Mmc {
+            fel,
+            base,
+            smhc,
+            buffer,
+        })
+    }
+
+    /// Initialize card and get its size in blocks.
+    pub fn card_blocks(&self) -> Result<u32> {
+        self.run(OPERATION_READ, 0, 0)
+    }
+
+    /// Read card from `block` on into `buf`.
+    pub fn read(&self, block: u32, buf: &mut [u8]) -> Result<()> {
+        for (index, chunk) in buf.chunks_mut(self.buffer_size()).enumerate() {
+            let count = chunk.len().div_ceil(BLOCK_SIZE) as u32;
+            self.run(
+                OPERATION_READ,
+                block + (index * self.buffer_blocks()) as u32,
+                count,
+            )?;
+            self.fel.read_address(self.buffer.address, chunk)?;
+        }
+        Ok(())
+    }
+
+    /// Write `data` onto card from `block` on.
+    ///
+    /// Bytes of a partially written last block keep their previous content.
+    pub fn write(&self, block: u32, data: &[u8]) -> Result<()> {
+        for (index, chunk) in data.chunks(self.buffer_size()).enumerate() {
+            let first = block + (index * self.buffer_blocks()) as u32;
+            let count = chunk.len().div_ceil(BLOCK_SIZE) as u32;
+            let whole = chunk.len() / BLOCK_SIZE * BLOCK_SIZE;
+            if whole < chunk.len() {
+                let mut last = [0u8; BLOCK_SIZE];
+                self.read(first + count - 1, &mut last)?;
+                last[..chunk.len() - whole].copy_from_slice(&chunk[whole..]);
+                let address = self.buffer.address + whole as u32;
+                self.fel.write_address(address, &last)?;
+            }
+            self.fel
+                .write_address(self.buffer.address, &chunk[..whole])?;
+            self.run(OPERATION_WRITE, first, count)?;
+        }
+        Ok(())
+    }
+
+    /// Blocks moved by each helper run.
+    #[inline]
+    pub fn buffer_blocks(&self) -> usize {
+        self.buffer.size as usize / BLOCK_SIZE
+    }
+
+    fn buffer_size(&self) -> usize {
+        self.buffer_blocks() * BLOCK_SIZE
+    }
+
+    /// Run helper on blocks in buffer, returning card size in blocks.
+    fn run(&self, operation: u32, block: u32, count: u32) -> Result<u32> {
+        let mailbox = self.base + MAILBOX_OFFSET as u32;
+        let words = [
+            self.smhc,
+            operation,
+            block,
+            count,
+            self.buffer.address,
+            STATUS_PENDING,
+            0,
+        ];
+        self.fel
+            .write_address(mailbox, &payload::to_bytes(&words))?;
+        self.fel.exec(self.base)?;
+        let mut buf = [0u8; 8];
+        self.fel.read_address(mailbox + 20, &mut buf)?;
+        let status = u32::from_le_bytes(buf[..4].try_into().unwrap());
+        let blocks = u32::from_le_bytes(buf[4..].try_into().unwrap());
+        match status {
+            STATUS_DONE => Ok(blocks),
+            STATUS_INIT_FAILED => Err(Error::CardInitFailed),
+            STATUS_OUT_OF_RANGE => Err(Error::CardOutOfRange { blocks }),
+            STATUS_WRITE_FAILED => Err(Error::CardWriteFailed),
+            STATUS_READ_FAILED => Err(Error::CardReadFailed),
+            status => Err(Error::HelperStatus(status)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BLOCK_SIZE, MAILBOX_OFFSET, Mmc};
+    use crate::{Error, Fel, Region, mock::MockDevice};
+    use std::{cell::RefCell, rc::Rc};
+
+    #[test]
+    fn mmc_read_write() {
+        const CARD_BLOCKS: u32 = 64;
+        let card = Rc::new(RefCell::new(vec![
+            0x5au8;
+            CARD_BLOCKS as usize * BLOCK_SIZE
+        ]));
+        let card_1 = card.clone();
+        let device = MockDevice::new(0x00185900, 0x7e00);
+        // Simulate helper serving SD card on SMHC0.
+        device.set_exec_handler(move |address, memory| {
+            let mailbox = address + MAILBOX_OFFSET as u32;
+            let word = |i: u32| memory.read_u32(mailbox + 4 * i);
+            let (smhc, operation, block, count, buffer) =
+                (word(0), word(1), word(2), word(3), word(4));
+            let status = if smhc != 0 {
+                2
+            } else if block + count > CARD_BLOCKS {
+                3
+            } else if operation == 2 && block == 40 {
+                // Block 40 fails to program.
+                4
+            } else if operation == 1 && block == 41 {
+                // Block 41 fails to read.
+                5
+            } else {
+                let range = block as usize * BLOCK_SIZE..(block + count) as usize * BLOCK_SIZE;
+                let mut card = card_1.borrow_mut();
+                match operation {
+                    1 => memory.write(buffer, &card[range]),
+                    2 => memory.read(buffer, &mut card[range]),
+                    _ => {}
+                }
+                0
+            };
+            memory.write_u32(mailbox + 20, status);
+            memory.write_u32(mailbox + 24, CARD_BLOCKS);
+            Ok(())
+        });
+        let fel = Fel::new(device);
+        let buffer = Region {
+            address: 0x4000_0000,
+            size: 4 * BLOCK_SIZE as u32,
+        };
+        let mmc = Mmc::load(&fel, 0x2_0000, &[0; 0x100], 0, buffer).unwrap();
+        assert_eq!(mmc.card_blocks().unwrap(), CARD_BLOCKS);
+
+        // Ten blocks and a half from block 3 take three runs.
+        let data: Vec<u8> = (0..10 * BLOCK_SIZE + 100).map(|i| (i * 7) as u8).collect();
+        mmc.write(3, &data).unwrap();
+        {
+            let card = card.borrow();
+            assert_eq!(card[3 * BLOCK_SIZE..][..data.len()], data);
+            assert!(
+                card[3 * BLOCK_SIZE + data.len()..14 * BLOCK_SIZE]
+                    .iter()
+                    .all(|&b| b == 0x5a)
+            );
+            assert_eq!(card[3 * BLOCK_SIZE - 1], 0x5a);
+        }
+        let mut buf = vec![0u8; data.len()];
+        mmc.read(3, &mut buf).unwrap();
+        assert_eq!(buf, data);
+
+        assert!(matches!(
+            mmc.write(60, &data),
+            Err(Error::CardOutOfRange { blocks: 64 })
+        ));
+        assert!(matches!(
+            mmc.write(40, &[0; BLOCK_SIZE]),
+            Err(Error::CardWriteFailed)
+        ));
+        assert!(matches!(
+            mmc.read(41, &mut [0; BLOCK_SIZE]),
+            Err(Error::CardReadFailed)
+        ));
+        let emmc = Mmc::load(&fel, 0x2_0000, &[0; 0x100], 2, buffer).unwrap();
+        assert!(matches!(emmc.card_blocks(), Err(Error::CardInitFailed)));
+    }
+}
@@ -0,0 +1,262 @@
+//! Software simulated FEL device for tests without a board.
+//!
+//! [`MockDevice`] implements [`FelTransport`] by playing the BROM side of
+//! the AWUC/AWUS USB framing and FEL requests over a sparse memory model.
+
+use crate::{Error, FelTransport, Result, Version};
+use core::cell::RefCell;
+use nusb::transfer::TransferError;
+use std::collections::{BTreeMap, VecDeque};
+
+/// Byte-addressable memory of a simulated chip.
+///
+/// Memory is allocated in pages on first write; unwritten bytes read as zero.
+#[derive(Default)]
+pub struct Memory {
+    pages: BTreeMap<u32, Box<[u8; PAGE_SIZE]>>,
+}
+
+const PAGE_SIZE: usize = 4096;
+
+impl Memory {
+    /// Read memory starting from `address` into `buf`.
+    pub fn read(&self, address: u32, buf: &mut [u8]) {
+        let mut offset = 0;
+        while offset < buf.len() {
+            let address = address.wrapping_add(offset as u32);
+            let (page, start) = Self::locate(address);
+            let len = (PAGE_SIZE - start).min(buf.len() - offset);
+            let chunk = &mut buf[offset..offset + len];
+            match self.pages.get(&page) {
+                Some(page) => chunk.copy_from_slice(&page[start..start + len]),
+                None => chunk.fill(0),
+            }
+            offset += len;
+        }
+    }
+    /// Write `buf` into memory starting from `address`.
+    pub fn write(&mut self, address: u32, buf: &[u8]) {
+        let mut offset = 0;
+        while offset < buf.len() {
+            let address = address.wrapping_add(offset as u32);
+            let (page, start) = Self::locate(address);
+            let len = (PAGE_SIZE - start).min(buf.len() - offset);
+            let page = self
+                .pages
+                .entry(page)
+                .or_insert_with(|| Box::new([0; PAGE_SIZE]));
+            page[start..start + len].copy_from_slice(&buf[offset..offset + len]);
+            offset += len;
+        }
+    }
+    /// Split address into page address and offset within page.
+    #[inline]
+    fn locate(address: u32) -> (u32, usize) {
+        let page = address & !(PAGE_SIZE as u32 - 1);
+        (page, (address - page) as usize)
+    }
+    /// Read a little endian 32-bit word at `address`.
+    #[inline]
+    pub fn read_u32(&self, address: u32) -> u32 {
+        let mut buf = [0u8; 4];
+        self.read(address, &mut buf);
+        u32::from_le_bytes(buf)
+    }
+    /// Write a little endian 32-bit word at `address`.
+    #[inline]
+    pub fn write_u32(&mut self, address: u32, value: u32) {
+        self.write(address, &value.to_le_bytes());
+    }
+}
+
+/// FEL request received by simulated device.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Request {
+    pub request: u32,
+    pub address: u32,
+    pub length: u32,
+}
+
+/// Handler called when simulated device executes code at an address.
+pub type ExecHandler = Box<dyn FnMut(u32, &mut Memory) -> Result<()>>;
+
+/// Simulated Allwinner BROM in FEL mode.
+pub struct MockDevice {
+    state: RefCell<State>,
+}
+
+struct State {
+    version: Version,
+    memory: Memory,
+    on_exec: ExecHandler,
+    requests: Vec<Request>,
+    usb: UsbState,
+    fel: FelState,
+    outgoing: VecDeque<Vec<u8>>,
+}
+
+enum UsbState {
+    /// Waiting for AWUC request.
+    Idle,
+    /// Host is about to send data of given length.
+    Write(usize),
+    /// Host is about to receive data of given length.
+    Read(usize),
+    /// Host is about to receive AWUS response.
+    Response,
+}
+
+enum FelState {
+    /// Waiting for FEL request.
+    Request,
+    /// Waiting for data of write request.
+    WriteData { address: u32 },
+}
+
+impl MockDevice {
+    /// Create a simulated device with given chip ID and scratchpad address.
+    pub fn new(id: u32, scratchpad: u32) -> Self {
+        let version = Version {
+            magic: *b"AWUSBFEX",
+            id,
+            firmware: 1,
+            protocol: 1,
+            dflag: 0x44,
+            dlength: 0x08,
+            scratchpad,
+            pad: [0; 8],
+        };
+        Self {
+            state: RefCell::new(State {
+                version,
+                memory: Memory::default(),
+                on_exec: Box::new(|_, _| Ok(())),
+                requests: Vec::new(),
+                usb: UsbState::Idle,
+                fel: FelState::Request,
+                outgoing: VecDeque::new(),
+            }),
+        }
+    }
+    /// Set handler to simulate code executed by FEL exec requests.
+    ///
+    /// A handler returning error makes the device report non-zero FEL status.
+    pub fn set_exec_handler(&self, f: impl FnMut(u32, &mut Memory) -> Result<()> + 'static) {
+        self.state.borrow_mut().on_exec = Box::new(f);
+    }
+    /// Read simulated memory.
+    pub fn read_memory(&self, address: u32, buf: &mut [u8]) {
+        self.state.borrow().memory.read(address, buf)
+    }
+    /// Write simulated memory.
+    pub fn write_memory(&self, address: u32, buf: &[u8]) {
+        self.state.borrow_mut().memory.write(address, buf)
+    }
+    /// FEL requests received so far.
+    pub fn requests(&self) -> Vec<Request> {
+        self.state.borrow().requests.clone()
+    }
+}
+
+impl State {
+    fn handle_fel_data(&mut self, buf: &[u8]) -> Result<()> {
+        match self.fel {
+            FelState::Request => {
+                if buf.len() != 16 {
+                    return Err(Error::Usb(TransferError::Stall));
+                }
+                let word =
+                    |offset: usize| u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap());
+                let request = Request {
+                    request: word(0),
+                    address: word(4),
+                    length: word(8),
+                };
+                self.requests.push(request);
+                match request.request {
+                    0x001 => {
+                        let version: [u8; 32] = self.version.into();
+                        self.outgoing.push_back(version.to_vec());
+                        self.push_status(0);
+                    }
+                    0x101 => {
+                        self.fel = FelState::WriteData {
+                            address: request.address,
+                        };
+                    }
+                    0x102 => {
+                        let state = match (self.on_exec)(request.address, &mut self.memory) {
+                            Ok(()) => 0,
+                            Err(_) => 1,
+                        };
+                        self.push_status(state);
+                    }
+                    0x103 => {
+                        let mut data = vec![0u8; request.length as usize];
+                        self.memory.read(request.address, &mut data);
+                        self.outgoing.push_back(data);
+                        self.push_status(0);
+                    }
+                    _ => self.push_status(1),
+                }
+            }
+            FelState::WriteData { address } => {
+                self.memory.write(address, buf);
+                self.fel = FelState::Request;
+                self.push_status(0);
+            }
+        }
+        Ok(())
+    }
+
+    fn push_status(&mut self, state: u8) {
+        self.outgoing
+            .push_back(vec![0xff, 0xff, 0, 0, state, 0, 0, 0]);
+    }
+}
+
+impl FelTransport for MockDevice {
+    async fn bulk_in(&self, length: usize) -> Result<Vec<u8>> {
+        let mut state = self.state.borrow_mut();
+        match state.usb {
+            UsbState::Read(expected) => {
+                let mut data = state.outgoing.pop_front().unwrap_or_default();
+                data.truncate(length.min(expected));
+                state.usb = UsbState::Response;
+                Ok(data)
+            }
+            UsbState::Response => {
+                state.usb = UsbState::Idle;
+                let mut data = b"AWUS".to_vec();
+                data.resize(13, 0);
+                data.truncate(length);
+                Ok(data)
+            }
+            _ => Err(Error::Usb(TransferError::Stall)),
+        }
+    }
+
+    async fn bulk_out(&self, buf: &[u8]) -> Result<()> {
+        let mut state = self.state.borrow_mut();
+        match state.usb {
+            UsbState::Idle => {
+                // struct { magic: [u8; 8], length: u32, unknown1: u32, request: u16, .. }
+                if buf.len() != 36 || buf[..4] != *b"AWUC" {
+                    return Err(Error::Usb(TransferError::Stall));
+                }
+                let length = u32::from_le_bytes(buf[8..12].try_into().unwrap()) as usize;
+                state.usb = match u16::from_le_bytes([buf[16], buf[17]]) {
+                    0x11 => UsbState::Read(length),
+                    0x12 => UsbState::Write(length),
+                    _ => return Err(Error::Usb(TransferError::Stall)),
+                };
+                Ok(())
+            }
+            UsbState::Write(length) if length == buf.len() => {
+                state.usb = UsbState::Response;
+                state.handle_fel_data(buf)
+            }
+            _ => Err(Error::Usb(TransferError::Stall)),
+        }
+    }
+}
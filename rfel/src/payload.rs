@@ -0,0 +1,476 @@
+//! Machine code run on chip by FEL exec requests.
+//!
+//! Each payload is loaded at start of chip scratch region and reads its
+//! parameters at [`PARAMS_OFFSET`] from its own start, so it can run from any
+//! address. Payloads return to BROM, which then resumes FEL mode.
+
+/// Offset of parameter words from start of payload.
+pub const PARAMS_OFFSET: u32 = 0x80;
+/// Offset of CRC-32 lookup table from start of payload.
+pub const CRC32_TABLE_OFFSET: u32 = 0x100;
+/// Offset of parameter words from start of SPI payload.
+pub const SPI_PARAMS_OFFSET: u32 = 0x200;
+/// Offset of SPI command and receive buffers from start of SPI payload.
+pub const SPI_BUFFER_OFFSET: u32 = 0x400;
+
+/// SPI payload command ending command list.
+pub const SPI_END: u8 = 0;
+/// SPI payload command running one transaction: little endian 16-bit write
+/// length and read length, then bytes to write. Read bytes are appended to
+/// receive buffer.
+pub const SPI_TRANSFER: u8 = 1;
+/// SPI payload command repeating a one-byte status read transaction: opcode,
+/// then mask of busy bits. Gives up with status 1 after about a million reads.
+pub const SPI_WAIT: u8 = 2;
+
+// payloads end before parameters, and five parameter words end before table.
+const _: () = assert!(CRC32_RISCV.len() * 4 <= PARAMS_OFFSET as usize);
+const _: () = assert!(CRC32_ARM.len() * 4 <= PARAMS_OFFSET as usize);
+const _: () = assert!(PARAMS_OFFSET + 5 * 4 <= CRC32_TABLE_OFFSET);
+const _: () = assert!(FILL_RISCV.len() * 4 <= PARAMS_OFFSET as usize);
+const _: () = assert!(FILL_ARM.len() * 4 <= PARAMS_OFFSET as usize);
+const _: () = assert!(COPY_RISCV.len() * 4 <= PARAMS_OFFSET as usize);
+const _: () = assert!(COPY_ARM.len() * 4 <= PARAMS_OFFSET as usize);
+const _: () = assert!(COMPARE_RISCV.len() * 4 <= PARAMS_OFFSET as usize);
+const _: () = assert!(COMPARE_ARM.len() * 4 <= PARAMS_OFFSET as usize);
+// SPI payload is larger, and its buffers follow its four parameter words.
+const _: () = assert!(SPI_RISCV.len() * 4 <= SPI_PARAMS_OFFSET as usize);
+const _: () = assert!(SPI_ARM.len() * 4 <= SPI_PARAMS_OFFSET as usize);
+const _: () = assert!(SPI_PARAMS_OFFSET + 5 * 4 <= SPI_BUFFER_OFFSET);
+
+/// Update CRC-32 state over memory region, RV32I or RV64I.
+///
+/// Parameter words: address, length, state (read and written back), lookup
+/// table address and `0x00ff_ffff` mask clearing bits shifted in on RV64.
+pub const CRC32_RISCV: [u32; 24] = [
+    0x0000_0e97, // auipc t4, 0
+    0x080e_d503, // lhu a0, 128(t4)
+    0x082e_de03, // lhu t3, 130(t4)
+    0x010e_1e13, // slli t3, t3, 16
+    0x01c5_6533, // or a0, a0, t3
+    0x084e_a583, // lw a1, 132(t4)
+    0x088e_a603, // lw a2, 136(t4)
+    0x08ce_af03, // lw t5, 140(t4)
+    0x090e_a303, // lw t1, 144(t4)
+    0x0205_8a63, // 1: beqz a1, 2f
+    0x0005_4e03, // lbu t3, 0(a0)
+    0x00ce_4e33, // xor t3, t3, a2
+    0x0ffe_7e13, // andi t3, t3, 255
+    0x002e_1e13, // slli t3, t3, 2
+    0x01ee_0e33, // add t3, t3, t5
+    0x000e_2e03, // lw t3, 0(t3)
+    0x0086_5613, // srli a2, a2, 8
+    0x0066_7633, // and a2, a2, t1
+    0x01c6_4633, // xor a2, a2, t3
+    0x0015_0513, // addi a0, a0, 1
+    0xfff5_8593, // addi a1, a1, -1
+    0xfd1f_f06f, // j 1b
+    0x08ce_a423, // 2: sw a2, 136(t4)
+    0x0000_8067, // ret
+];
+
+/// Update CRC-32 state over memory region, AArch32 ARM mode.
+///
+/// Parameter words: address, length, state (read and written back) and lookup
+/// table address.
+pub const CRC32_ARM: [u32; 17] = [
+    0xe92d_4010, // push {r4, lr}
+    0xe28f_c074, // add r12, pc, #116
+    0xe59c_0000, // ldr r0, [r12]
+    0xe59c_1004, // ldr r1, [r12, #4]
+    0xe59c_2008, // ldr r2, [r12, #8]
+    0xe59c_300c, // ldr r3, [r12, #12]
+    0xe351_0000, // 1: cmp r1, #0
+    0x0a00_0006, // beq 2f
+    0xe4d0_4001, // ldrb r4, [r0], #1
+    0xe024_4002, // eor r4, r4, r2
+    0xe204_40ff, // and r4, r4, #255
+    0xe793_4104, // ldr r4, [r3, r4, lsl #2]
+    0xe024_2422, // eor r2, r4, r2, lsr #8
+    0xe241_1001, // sub r1, r1, #1
+    0xeaff_fff6, // b 1b
+    0xe58c_2008, // 2: str r2, [r12, #8]
+    0xe8bd_8010, // pop {r4, pc}
+];
+
+/// Fill memory region with a 32-bit pattern, RV32I or RV64I.
+///
+/// Parameter words: word aligned address, length in bytes as a multiple of 4
+/// and pattern.
+pub const FILL_RISCV: [u32; 13] = [
+    0x0000_0e97, // auipc t4, 0
+    0x080e_d503, // lhu a0, 128(t4)
+    0x082e_de03, // lhu t3, 130(t4)
+    0x010e_1e13, // slli t3, t3, 16
+    0x01c5_6533, // or a0, a0, t3
+    0x084e_a583, // lw a1, 132(t4)
+    0x088e_a603, // lw a2, 136(t4)
+    0x0005_8a63, // 1: beqz a1, 2f
+    0x00c5_2023, // sw a2, 0(a0)
+    0x0045_0513, // addi a0, a0, 4
+    0xffc5_8593, // addi a1, a1, -4
+    0xff1f_f06f, // j 1b
+    0x0000_8067, // 2: ret
+];
+
+/// Fill memory region with a 32-bit pattern, AArch32 ARM mode.
+///
+/// Parameter words: word aligned address, length in bytes as a multiple of 4
+/// and pattern.
+pub const FILL_ARM: [u32; 10] = [
+    0xe28f_c078, // add r12, pc, #120
+    0xe59c_0000, // ldr r0, [r12]
+    0xe59c_1004, // ldr r1, [r12, #4]
+    0xe59c_2008, // ldr r2, [r12, #8]
+    0xe351_0000, // 1: cmp r1, #0
+    0x0a00_0002, // beq 2f
+    0xe480_2004, // str r2, [r0], #4
+    0xe241_1004, // sub r1, r1, #4
+    0xeaff_fffa, // b 1b
+    0xe12f_ff1e, // 2: bx lr
+];
+
+/// Copy bytes between possibly overlapping memory regions, RV32I or RV64I.
+///
+/// Parameter words: source address, destination address and length. Copies
+/// backwards unless destination is below source.
+pub const COPY_RISCV: [u32; 28] = [
+    0x0000_0e97, // auipc t4, 0
+    0x080e_d503, // lhu a0, 128(t4)
+    0x082e_de03, // lhu t3, 130(t4)
+    0x010e_1e13, // slli t3, t3, 16
+    0x01c5_6533, // or a0, a0, t3
+    0x084e_d583, // lhu a1, 132(t4)
+    0x086e_de03, // lhu t3, 134(t4)
+    0x010e_1e13, // slli t3, t3, 16
+    0x01c5_e5b3, // or a1, a1, t3
+    0x088e_a603, // lw a2, 136(t4)
+    0x02a5_e463, // bltu a1, a0, 2f
+    0x00c5_0533, // add a0, a0, a2
+    0x00c5_85b3, // add a1, a1, a2
+    0x0206_0c63, // 1: beqz a2, 3f
+    0xfff5_0513, // addi a0, a0, -1
+    0xfff5_8593, // addi a1, a1, -1
+    0x0005_4e03, // lbu t3, 0(a0)
+    0x01c5_8023, // sb t3, 0(a1)
+    0xfff6_0613, // addi a2, a2, -1
+    0xfe9f_f06f, // j 1b
+    0x0006_0e63, // 2: beqz a2, 3f
+    0x0005_4e03, // lbu t3, 0(a0)
+    0x01c5_8023, // sb t3, 0(a1)
+    0x0015_0513, // addi a0, a0, 1
+    0x0015_8593, // addi a1, a1, 1
+    0xfff6_0613, // addi a2, a2, -1
+    0xfe9f_f06f, // j 2b
+    0x0000_8067, // 3: ret
+];
+
+/// Copy bytes between possibly overlapping memory regions, AArch32 ARM mode.
+///
+/// Parameter words: source address, destination address and length. Copies
+/// backwards unless destination is below source.
+pub const COPY_ARM: [u32; 21] = [
+    0xe28f_c078, // add r12, pc, #120
+    0xe59c_0000, // ldr r0, [r12]
+    0xe59c_1004, // ldr r1, [r12, #4]
+    0xe59c_2008, // ldr r2, [r12, #8]
+    0xe151_0000, // cmp r1, r0
+    0x3a00_0007, // blo 2f
+    0xe080_0002, // add r0, r0, r2
+    0xe081_1002, // add r1, r1, r2
+    0xe352_0000, // 1: cmp r2, #0
+    0x0a00_0009, // beq 3f
+    0xe570_3001, // ldrb r3, [r0, #-1]!
+    0xe561_3001, // strb r3, [r1, #-1]!
+    0xe242_2001, // sub r2, r2, #1
+    0xeaff_fff9, // b 1b
+    0xe352_0000, // 2: cmp r2, #0
+    0x0a00_0003, // beq 3f
+    0xe4d0_3001, // ldrb r3, [r0], #1
+    0xe4c1_3001, // strb r3, [r1], #1
+    0xe242_2001, // sub r2, r2, #1
+    0xeaff_fff9, // b 2b
+    0xe12f_ff1e, // 3: bx lr
+];
+
+/// Find first differing byte of two memory regions, RV32I or RV64I.
+///
+/// Parameter words: first address, second address, length and result, written
+/// with offset of first difference, or length if regions are equal.
+pub const COMPARE_RISCV: [u32; 21] = [
+    0x0000_0e97, // auipc t4, 0
+    0x080e_d503, // lhu a0, 128(t4)
+    0x082e_de03, // lhu t3, 130(t4)
+    0x010e_1e13, // slli t3, t3, 16
+    0x01c5_6533, // or a0, a0, t3
+    0x084e_d583, // lhu a1, 132(t4)
+    0x086e_de03, // lhu t3, 134(t4)
+    0x010e_1e13, // slli t3, t3, 16
+    0x01c5_e5b3, // or a1, a1, t3
+    0x088e_a603, // lw a2, 136(t4)
+    0x0000_0f13, // li t5, 0
+    0x02cf_0063, // 1: beq t5, a2, 2f
+    0x01e5_0e33, // add t3, a0, t5
+    0x000e_4e03, // lbu t3, 0(t3)
+    0x01e5_8fb3, // add t6, a1, t5
+    0x000f_cf83, // lbu t6, 0(t6)
+    0x01fe_1663, // bne t3, t6, 2f
+    0x001f_0f13, // addi t5, t5, 1
+    0xfe5f_f06f, // j 1b
+    0x09ee_a623, // 2: sw t5, 140(t4)
+    0x0000_8067, // ret
+];
+
+/// Find first differing byte of two memory regions, AArch32 ARM mode.
+///
+/// Parameter words: first address, second address, length and result, written
+/// with offset of first difference, or length if regions are equal.
+pub const COMPARE_ARM: [u32; 16] = [
+    0xe92d_4010, // push {r4, lr}
+    0xe28f_c074, // add r12, pc, #116
+    0xe59c_0000, // ldr r0, [r12]
+    0xe59c_1004, // ldr r1, [r12, #4]
+    0xe59c_2008, // ldr r2, [r12, #8]
+    0xe3a0_3000, // mov r3, #0
+    0xe153_0002, // 1: cmp r3, r2
+    0x0a00_0005, // beq 2f
+    0xe7d0_4003, // ldrb r4, [r0, r3]
+    0xe7d1_e003, // ldrb lr, [r1, r3]
+    0xe154_000e, // cmp r4, lr
+    0x1a00_0001, // bne 2f
+    0xe283_3001, // add r3, r3, #1
+    0xeaff_fff7, // b 1b
+    0xe58c_300c, // 2: str r3, [r12, #12]
+    0xe8bd_8010, // pop {r4, pc}
+];
+
+/// Run SPI commands on an SPI controller, RV32I or RV64I.
+///
+/// Parameter words: controller base address, command list address, receive
+/// buffer address and status, written with 0 when done or 1 if a wait gave up.
+/// One scratch byte follows parameters. Controller is laid out as
+/// `allwinner_hal::spi::RegisterBlock`, in master mode with software chip
+/// select; each burst moves at most 64 bytes through FIFO.
+pub const SPI_RISCV: [u32; 108] = [
+    0x0000_0e97, // auipc t4, 0
+    0x200e_d503, // lhu a0, 512(t4)
+    0x202e_de03, // lhu t3, 514(t4)
+    0x010e_1e13, // slli t3, t3, 16
+    0x01c5_6533, // or a0, a0, t3
+    0x204e_d583, // lhu a1, 516(t4)
+    0x206e_de03, // lhu t3, 518(t4)
+    0x010e_1e13, // slli t3, t3, 16
+    0x01c5_e5b3, // or a1, a1, t3
+    0x208e_d603, // lhu a2, 520(t4)
+    0x20ae_de03, // lhu t3, 522(t4)
+    0x010e_1e13, // slli t3, t3, 16
+    0x01c6_6633, // or a2, a2, t3
+    0x0000_0893, // li a7, 0
+    0x0005_c283, // next: lbu t0, 0(a1)
+    0x0015_8593, // addi a1, a1, 1
+    0x0802_8863, // beqz t0, done
+    0x0010_0313, // li t1, 1
+    0x0062_8863, // beq t0, t1, transfer
+    0x0020_0313, // li t1, 2
+    0x0462_8063, // beq t0, t1, wait
+    0x07c0_006f, // j done
+    0x0005_c783, // transfer: lbu a5, 0(a1)
+    0x0015_c283, // lbu t0, 1(a1)
+    0x0082_9293, // slli t0, t0, 8
+    0x0057_e7b3, // or a5, a5, t0
+    0x0025_c803, // lbu a6, 2(a1)
+    0x0035_c283, // lbu t0, 3(a1)
+    0x0082_9293, // slli t0, t0, 8
+    0x0058_6833, // or a6, a6, t0
+    0x0045_8713, // addi a4, a1, 4
+    0x0006_0693, // mv a3, a2
+    0x0580_0fef, // jal t6, xfer
+    0x0007_0593, // mv a1, a4
+    0x0006_8613, // mv a2, a3
+    0xfadf_f06f, // j next
+    0x0010_0f37, // wait: lui t5, 0x100
+    0x0005_8713, // 1: mv a4, a1
+    0x0010_0793, // li a5, 1
+    0x210e_8693, // addi a3, t4, 528
+    0x0010_0813, // li a6, 1
+    0x0340_0fef, // jal t6, xfer
+    0x210e_c283, // lbu t0, 528(t4)
+    0x0015_c303, // lbu t1, 1(a1)
+    0x0062_f2b3, // and t0, t0, t1
+    0x0002_8a63, // beqz t0, 2f
+    0xffff_0f13, // addi t5, t5, -1
+    0xfc0f_1ce3, // bnez t5, 1b
+    0x0010_0893, // li a7, 1
+    0x00c0_006f, // j done
+    0x0025_8593, // 2: addi a1, a1, 2
+    0xf6df_f06f, // j next
+    0x211e_a623, // done: sw a7, 524(t4)
+    0x0000_8067, // ret
+    0x0085_2283, // xfer: lw t0, 8(a0)
+    0xf7f2_f293, // andi t0, t0, -129
+    0x0055_2423, // sw t0, 8(a0)
+    0x0407_8a63, // 3: beqz a5, 5f
+    0x0400_0313, // li t1, 64
+    0x0067_f463, // bgeu a5, t1, 4f
+    0x0007_8313, // mv t1, a5
+    0x0265_2823, // 4: sw t1, 0x30(a0)
+    0x0265_2a23, // sw t1, 0x34(a0)
+    0x0265_2c23, // sw t1, 0x38(a0)
+    0x0003_0393, // mv t2, t1
+    0x0007_4283, // 6: lbu t0, 0(a4)
+    0x2055_0023, // sb t0, 0x200(a0)
+    0x0017_0713, // addi a4, a4, 1
+    0xfff3_8393, // addi t2, t2, -1
+    0xfe03_98e3, // bnez t2, 6b
+    0x0085_2283, // lw t0, 8(a0)
+    0x8000_03b7, // lui t2, 0x80000
+    0x0072_e2b3, // or t0, t0, t2
+    0x0055_2423, // sw t0, 8(a0)
+    0x0085_2283, // 7: lw t0, 8(a0)
+    0xfe02_cee3, // bltz t0, 7b
+    0x4067_87b3, // sub a5, a5, t1
+    0xfb1f_f06f, // j 3b
+    0x0000_83b7, // 5: lui t2, 0x8
+    0x0075_2c23, // sw t2, 0x18(a0)
+    0x0608_0063, // 8: beqz a6, 11f
+    0x0400_0313, // li t1, 64
+    0x0068_7463, // bgeu a6, t1, 9f
+    0x0008_0313, // mv t1, a6
+    0x0265_2823, // 9: sw t1, 0x30(a0)
+    0x0205_2a23, // sw zero, 0x34(a0)
+    0x0205_2c23, // sw zero, 0x38(a0)
+    0x0085_2283, // lw t0, 8(a0)
+    0x8000_03b7, // lui t2, 0x80000
+    0x0072_e2b3, // or t0, t0, t2
+    0x0055_2423, // sw t0, 8(a0)
+    0x0085_2283, // 10: lw t0, 8(a0)
+    0xfe02_cee3, // bltz t0, 10b
+    0x01c5_2283, // 12: lw t0, 0x1c(a0)
+    0x0ff2_f293, // andi t0, t0, 0xff
+    0xfe62_ece3, // bltu t0, t1, 12b
+    0x0003_0393, // mv t2, t1
+    0x3005_4283, // 13: lbu t0, 0x300(a0)
+    0x0056_8023, // sb t0, 0(a3)
+    0x0016_8693, // addi a3, a3, 1
+    0xfff3_8393, // addi t2, t2, -1
+    0xfe03_98e3, // bnez t2, 13b
+    0x4068_0833, // sub a6, a6, t1
+    0xfa5f_f06f, // j 8b
+    0x0085_2283, // 11: lw t0, 8(a0)
+    0x0802_e293, // ori t0, t0, 0x80
+    0x0055_2423, // sw t0, 8(a0)
+    0x000f_8067, // jr t6
+];
+
+/// Run SPI commands on an SPI controller, AArch32 ARM mode.
+///
+/// Parameters and commands are the same as [`SPI_RISCV`].
+pub const SPI_ARM: [u32; 102] = [
+    0xe92d_4ff0, // push {r4-r11, lr}
+    0xe28f_cf7d, // add r12, pc, #(0x200 - 12)
+    0xe59c_0000, // ldr r0, [r12]
+    0xe59c_1004, // ldr r1, [r12, #4]
+    0xe59c_2008, // ldr r2, [r12, #8]
+    0xe3a0_b000, // mov r11, #0
+    0xe4d1_3001, // next: ldrb r3, [r1], #1
+    0xe353_0000, // cmp r3, #0
+    0x0a00_0022, // beq done
+    0xe353_0001, // cmp r3, #1
+    0x0a00_0002, // beq transfer
+    0xe353_0002, // cmp r3, #2
+    0x0a00_000d, // beq wait
+    0xea00_001d, // b done
+    0xe5d1_5000, // transfer: ldrb r5, [r1]
+    0xe5d1_3001, // ldrb r3, [r1, #1]
+    0xe185_5403, // orr r5, r5, r3, lsl #8
+    0xe5d1_6002, // ldrb r6, [r1, #2]
+    0xe5d1_3003, // ldrb r3, [r1, #3]
+    0xe186_6403, // orr r6, r6, r3, lsl #8
+    0xe281_4004, // add r4, r1, #4
+    0xe1a0_7002, // mov r7, r2
+    0xe1a0_e00f, // mov lr, pc
+    0xea00_0015, // b xfer
+    0xe1a0_1004, // mov r1, r4
+    0xe1a0_2007, // mov r2, r7
+    0xeaff_ffea, // b next
+    0xe3a0_a601, // wait: mov r10, #0x100000
+    0xe1a0_4001, // 1: mov r4, r1
+    0xe3a0_5001, // mov r5, #1
+    0xe28c_7010, // add r7, r12, #16
+    0xe3a0_6001, // mov r6, #1
+    0xe1a0_e00f, // mov lr, pc
+    0xea00_000b, // b xfer
+    0xe5dc_3010, // ldrb r3, [r12, #16]
+    0xe5d1_8001, // ldrb r8, [r1, #1]
+    0xe113_0008, // tst r3, r8
+    0x0a00_0003, // beq 2f
+    0xe25a_a001, // subs r10, r10, #1
+    0x1aff_fff3, // bne 1b
+    0xe3a0_b001, // mov r11, #1
+    0xea00_0001, // b done
+    0xe281_1002, // 2: add r1, r1, #2
+    0xeaff_ffd9, // b next
+    0xe58c_b00c, // done: str r11, [r12, #12]
+    0xe8bd_8ff0, // pop {r4-r11, pc}
+    0xe590_3008, // xfer: ldr r3, [r0, #8]
+    0xe3c3_3080, // bic r3, r3, #0x80
+    0xe580_3008, // str r3, [r0, #8]
+    0xe355_0000, // 3: cmp r5, #0
+    0x0a00_0012, // beq 5f
+    0xe355_0040, // cmp r5, #64
+    0x23a0_8040, // movhs r8, #64
+    0x31a0_8005, // movlo r8, r5
+    0xe580_8030, // str r8, [r0, #0x30]
+    0xe580_8034, // str r8, [r0, #0x34]
+    0xe580_8038, // str r8, [r0, #0x38]
+    0xe1a0_9008, // mov r9, r8
+    0xe4d4_3001, // 6: ldrb r3, [r4], #1
+    0xe5c0_3200, // strb r3, [r0, #0x200]
+    0xe259_9001, // subs r9, r9, #1
+    0x1aff_fffb, // bne 6b
+    0xe590_3008, // ldr r3, [r0, #8]
+    0xe383_3102, // orr r3, r3, #0x80000000
+    0xe580_3008, // str r3, [r0, #8]
+    0xe590_3008, // 7: ldr r3, [r0, #8]
+    0xe313_0102, // tst r3, #0x80000000
+    0x1aff_fffc, // bne 7b
+    0xe045_5008, // sub r5, r5, r8
+    0xeaff_ffea, // b 3b
+    0xe3a0_3902, // 5: mov r3, #0x8000
+    0xe580_3018, // str r3, [r0, #0x18]
+    0xe356_0000, // 8: cmp r6, #0
+    0x0a00_0017, // beq 11f
+    0xe356_0040, // cmp r6, #64
+    0x23a0_8040, // movhs r8, #64
+    0x31a0_8006, // movlo r8, r6
+    0xe580_8030, // str r8, [r0, #0x30]
+    0xe3a0_3000, // mov r3, #0
+    0xe580_3034, // str r3, [r0, #0x34]
+    0xe580_3038, // str r3, [r0, #0x38]
+    0xe590_3008, // ldr r3, [r0, #8]
+    0xe383_3102, // orr r3, r3, #0x80000000
+    0xe580_3008, // str r3, [r0, #8]
+    0xe590_3008, // 10: ldr r3, [r0, #8]
+    0xe313_0102, // tst r3, #0x80000000
+    0x1aff_fffc, // bne 10b
+    0xe590_301c, // 12: ldr r3, [r0, #0x1c]
+    0xe203_30ff, // and r3, r3, #0xff
+    0xe153_0008, // cmp r3, r8
+    0x3aff_fffb, // blo 12b
+    0xe1a0_9008, // mov r9, r8
+    0xe5d0_3300, // 13: ldrb r3, [r0, #0x300]
+    0xe4c7_3001, // strb r3, [r7], #1
+    0xe259_9001, // subs r9, r9, #1
+    0x1aff_fffb, // bne 13b
+    0xe046_6008, // sub r6, r6, r8
+    0xeaff_ffe5, // b 8b
+    0xe590_3008, // 11: ldr r3, [r0, #8]
+    0xe383_3080, // orr r3, r3, #0x80
+    0xe580_3008, // str r3, [r0, #8]
+    0xe12f_ff1e, // bx lr
+];
+
+/// Encode payload words into little endian bytes.
+pub fn to_bytes(code: &[u32]) -> Vec<u8> {
+    code.iter().flat_map(|word| word.to_le_bytes()).collect()
+}
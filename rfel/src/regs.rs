@@ -0,0 +1,560 @@
+//! Peripheral register tables for symbolic register access.
+//!
+//! Register offsets come from `#[repr(C)]` register blocks of `allwinner-hal`,
+//! and base addresses follow `soc!` definitions of `allwinner-rt`. Registers
+//! typed by the HAL are decoded with its accessors.
+
+use allwinner_hal::{ccu, com, dma, gpio, phy, smhc, spi};
+use core::mem::{offset_of, size_of};
+
+/// Decode register value into named fields.
+pub type Decode = fn(u32) -> Vec<(&'static str, String)>;
+
+/// Peripheral instance with its registers.
+#[derive(Debug)]
+pub struct Peripheral {
+    /// Instance name, like `uart0`.
+    pub name: &'static str,
+    /// Base address.
+    pub base: u32,
+    /// Registers, shared by instances of the same peripheral.
+    pub registers: &'static [Register],
+}
+
+/// Register, or array of registers, within a peripheral.
+#[derive(Debug)]
+pub struct Register {
+    /// Field name in HAL register block.
+    pub name: &'static str,
+    /// Offset of first register from peripheral base.
+    pub offset: u32,
+    /// Number of registers in array, 1 for a single register.
+    pub count: u32,
+    /// Distance between array elements in bytes.
+    pub stride: u32,
+    /// Field decoder, if HAL types this register.
+    pub decode: Option<Decode>,
+}
+
+impl Register {
+    const fn new(name: &'static str, offset: usize) -> Self {
+        Register {
+            name,
+            offset: offset as u32,
+            count: 1,
+            stride: 4,
+            decode: None,
+        }
+    }
+    const fn array(self, count: u32, stride: usize) -> Self {
+        Register {
+            count,
+            stride: stride as u32,
+            ..self
+        }
+    }
+    const fn decode(self, decode: Decode) -> Self {
+        Register {
+            decode: Some(decode),
+            ..self
+        }
+    }
+}
+
+/// One register at a resolved address.
+#[derive(Debug)]
+pub struct ResolvedRegister {
+    /// Full name, like `ccu.smhc_clk[1]`.
+    pub name: String,
+    /// Absolute address.
+    pub address: u32,
+    /// Field decoder, if any.
+    pub decode: Option<Decode>,
+}
+
+impl Peripheral {
+    /// Registers of this peripheral with arrays expanded, in table order.
+    pub fn registers(&self) -> impl Iterator<Item = ResolvedRegister> + '_ {
+        self.registers.iter().flat_map(move |register| {
+            (0..register.count).map(move |index| ResolvedRegister {
+                name: if register.count == 1 {
+                    format!("{}.{}", self.name, register.name)
+                } else {
+                    format!("{}.{}[{}]", self.name, register.name, index)
+                },
+                address: self.base + register.offset + index * register.stride,
+                decode: register.decode,
+            })
+        })
+    }
+}
+
+/// Find peripheral by instance name.
+pub fn find<'a>(peripherals: &'a [Peripheral], name: &str) -> Option<&'a Peripheral> {
+    peripherals
+        .iter()
+        .find(|p| p.name.eq_ignore_ascii_case(name))
+}
+
+/// Resolve name like `ccu.pll_cpu_control` or `ccu.smhc_clk[1]` into a register.
+pub fn resolve(peripherals: &[Peripheral], name: &str) -> Option<ResolvedRegister> {
+    let (peripheral, register) = name.split_once('.')?;
+    let peripheral = find(peripherals, peripheral)?;
+    let (register, index) = match register.strip_suffix(']') {
+        Some(rest) => {
+            let (register, index) = rest.split_once('[')?;
+            (register, Some(index.trim().parse::<u32>().ok()?))
+        }
+        None => (register, None),
+    };
+    let found = peripheral
+        .registers
+        .iter()
+        .find(|r| r.name.eq_ignore_ascii_case(register))?;
+    let index = match (index, found.count) {
+        (None, 1) => 0,
+        (Some(index), count) if index < count && count > 1 => index,
+        _ => return None,
+    };
+    let name = if found.count == 1 {
+        format!("{}.{}", peripheral.name, found.name)
+    } else {
+        format!("{}.{}[{}]", peripheral.name, found.name, index)
+    };
+    Some(ResolvedRegister {
+        name,
+        address: peripheral.base + found.offset + index * found.stride,
+        decode: found.decode,
+    })
+}
+
+/// Register of HAL register block `$Block` named by its field.
+macro_rules! field {
+    ($Block:ty, $field:ident) => {
+        Register::new(stringify!($field), offset_of!($Block, $field))
+    };
+    ($Block:ty, $field:ident[$count:expr]) => {
+        Register::new(stringify!($field), offset_of!($Block, $field)).array($count, 4)
+    };
+}
+
+/// Decoder calling given accessors of HAL register type `$Ty`.
+macro_rules! decode {
+    ($Ty:ty { $($method:ident),+ $(,)? }) => {{
+        fn decode(value: u32) -> Vec<(&'static str, String)> {
+            // SAFETY: HAL register types are `#[repr(transparent)]` wrappers of `u32`.
+            let register = unsafe { core::mem::transmute::<u32, $Ty>(value) };
+            vec![$((stringify!($method), format!("{:?}", register.$method())),)+]
+        }
+        decode
+    }};
+}
+
+const CCU: &[Register] = &[
+    field!(ccu::RegisterBlock, pll_cpu_control).decode(decode!(ccu::PllCpuControl {
+        is_pll_enabled,
+        is_pll_ldo_enabled,
+        is_lock_enabled,
+        is_locked,
+        is_pll_output_unmasked,
+        pll_n,
+        pll_m,
+    })),
+    field!(ccu::RegisterBlock, pll_ddr_control).decode(decode!(ccu::PllDdrControl {
+        is_pll_enabled,
+        is_pll_ldo_enabled,
+        is_lock_enabled,
+        is_locked,
+        is_pll_output_unmasked,
+        pll_n,
+    })),
+    field!(ccu::RegisterBlock, pll_peri0_control).decode(decode!(ccu::PllPeri0Control {
+        is_pll_enabled,
+        is_pll_ldo_enabled,
+        is_lock_enabled,
+        is_locked,
+        is_pll_output_unmasked,
+        pll_n,
+        pll_m,
+    })),
+    field!(ccu::RegisterBlock, cpu_axi_config),
+    field!(ccu::RegisterBlock, mbus_clock).decode(decode!(ccu::MbusClock { is_reset_asserted })),
+    field!(ccu::RegisterBlock, dram_clock),
+    field!(ccu::RegisterBlock, dram_bgr),
+    field!(ccu::RegisterBlock, smhc_clk[3]),
+    field!(ccu::RegisterBlock, smhc_bgr),
+    field!(ccu::RegisterBlock, uart_bgr),
+    field!(ccu::RegisterBlock, spi_clk[2]),
+    field!(ccu::RegisterBlock, spi_bgr),
+];
+
+/// Registers of GPIO ports and their external interrupt groups, named `p<port>_<field>`.
+macro_rules! gpio_ports {
+    ($(($port:literal, $index:literal)),+ $(,)?) => {
+        &[$(
+            gpio_ports!(@port $port, $index, cfg, 4),
+            gpio_ports!(@port $port, $index, dat, 1),
+            gpio_ports!(@port $port, $index, drv, 4),
+            gpio_ports!(@port $port, $index, pull, 2),
+            gpio_ports!(@eint $port, $index, cfg, 4),
+            gpio_ports!(@eint $port, $index, ctl, 1),
+            gpio_ports!(@eint $port, $index, status, 1),
+            gpio_ports!(@eint $port, $index, deb, 1),
+        )+]
+    };
+    (@port $port:literal, $index:literal, $field:ident, $count:literal) => {
+        Register::new(
+            concat!("p", $port, "_", stringify!($field)),
+            offset_of!(gpio::RegisterBlock, sys_port)
+                + $index * size_of::<gpio::Port>()
+                + offset_of!(gpio::Port, $field),
+        )
+        .array($count, 4)
+    };
+    (@eint $port:literal, $index:literal, $field:ident, $count:literal) => {
+        Register::new(
+            concat!("p", $port, "_eint_", stringify!($field)),
+            offset_of!(gpio::RegisterBlock, sys_eint)
+                + $index * size_of::<gpio::Eint>()
+                + offset_of!(gpio::Eint, $field),
+        )
+        .array($count, 4)
+    };
+}
+
+const GPIO: &[Register] = gpio_ports![
+    ("a", 0),
+    ("b", 1),
+    ("c", 2),
+    ("d", 3),
+    ("e", 4),
+    ("f", 5),
+    ("g", 6),
+    ("h", 7),
+    ("i", 8),
+];
+
+/// 16550 compatible registers; fields of `allwinner_hal::uart::RegisterBlock`
+/// are private, so offsets follow its 32-bit register stride.
+const UART: &[Register] = &[
+    Register::new("rbr_thr_dll", 0x00),
+    Register::new("ier_dlh", 0x04),
+    Register::new("iir_fcr", 0x08),
+    Register::new("lcr", 0x0c),
+    Register::new("mcr", 0x10),
+    Register::new("lsr", 0x14),
+    Register::new("msr", 0x18),
+    Register::new("sch", 0x1c),
+    Register::new("usr", 0x7c),
+];
+
+const SPI: &[Register] = &[
+    field!(spi::RegisterBlock, gcr).decode(decode!(spi::GlobalControl {
+        is_software_reset_finished,
+        transmit_pause_enabled,
+        is_master_mode,
+        is_enabled,
+    })),
+    field!(spi::RegisterBlock, tcr).decode(decode!(spi::TransferControl { burst_finished })),
+    field!(spi::RegisterBlock, ier),
+    field!(spi::RegisterBlock, isr),
+    field!(spi::RegisterBlock, fcr),
+    field!(spi::RegisterBlock, fsr).decode(decode!(spi::FifoStatus {
+        transmit_buffer_write_enable,
+        transmit_buffer_counter,
+        transmit_fifo_counter,
+        receive_buffer_write_enable,
+        receive_buffer_counter,
+        receive_fifo_counter,
+    })),
+    field!(spi::RegisterBlock, wcr),
+    field!(spi::RegisterBlock, samp_dl),
+    field!(spi::RegisterBlock, mbc),
+    field!(spi::RegisterBlock, mtc),
+    field!(spi::RegisterBlock, bcc).decode(decode!(spi::BurstControl {
+        is_quad_mode_enabled,
+        master_dummy_burst_counter,
+        master_single_mode_transmit_counter,
+    })),
+    field!(spi::RegisterBlock, batcr),
+    field!(spi::RegisterBlock, ba_ccr),
+    field!(spi::RegisterBlock, tbr),
+    field!(spi::RegisterBlock, rbr),
+    field!(spi::RegisterBlock, ndma_mode_ctl),
+];
+
+const SMHC: &[Register] = &[
+    field!(smhc::RegisterBlock, global_control),
+    field!(smhc::RegisterBlock, clock_control),
+    field!(smhc::RegisterBlock, timeout),
+    field!(smhc::RegisterBlock, card_type),
+    field!(smhc::RegisterBlock, block_size),
+    field!(smhc::RegisterBlock, byte_count),
+    field!(smhc::RegisterBlock, command),
+    field!(smhc::RegisterBlock, argument),
+    field!(smhc::RegisterBlock, responses[4]),
+    field!(smhc::RegisterBlock, interrupt_mask),
+    field!(smhc::RegisterBlock, interrupt_state_masked),
+    field!(smhc::RegisterBlock, interrupt_state_raw),
+    field!(smhc::RegisterBlock, status).decode(decode!(smhc::Status {
+        fifo_level,
+        response_index,
+        fsm_busy,
+        card_busy,
+        card_present,
+    })),
+    field!(smhc::RegisterBlock, fifo_water_level),
+    field!(smhc::RegisterBlock, fifo_function),
+    field!(smhc::RegisterBlock, transferred_byte_count0),
+    field!(smhc::RegisterBlock, transferred_byte_count1),
+    field!(smhc::RegisterBlock, debug_control),
+    field!(smhc::RegisterBlock, crc_status_detect),
+    field!(smhc::RegisterBlock, auto_cmd12_arg),
+    field!(smhc::RegisterBlock, new_timing_set),
+    field!(smhc::RegisterBlock, hardware_reset),
+    field!(smhc::RegisterBlock, dma_control),
+    field!(smhc::RegisterBlock, dma_descriptor_base),
+    field!(smhc::RegisterBlock, dma_state),
+    field!(smhc::RegisterBlock, dma_interrupt_enable),
+    field!(smhc::RegisterBlock, card_threshold_control),
+    field!(smhc::RegisterBlock, sample_fifo_control),
+    field!(smhc::RegisterBlock, auto_cmd23_arg),
+    field!(smhc::RegisterBlock, ddr_start_bit_detection),
+    field!(smhc::RegisterBlock, extended_command),
+    field!(smhc::RegisterBlock, extended_response),
+    field!(smhc::RegisterBlock, drive_delay_control),
+    field!(smhc::RegisterBlock, sample_delay_control),
+    field!(smhc::RegisterBlock, data_strobe_delay_control),
+    field!(smhc::RegisterBlock, hs400_delay_control),
+    field!(smhc::RegisterBlock, skew_control),
+];
+
+/// Register of every DMA channel, named `channel_<field>`.
+macro_rules! dma_channel {
+    ($field:ident) => {
+        Register::new(
+            concat!("channel_", stringify!($field)),
+            offset_of!(dma::RegisterBlock, channels)
+                + offset_of!(dma::ChannelRegisterBlock, $field),
+        )
+        .array(16, size_of::<dma::ChannelRegisterBlock>())
+    };
+}
+
+const DMA: &[Register] = &[
+    field!(dma::RegisterBlock, irq_enable0),
+    field!(dma::RegisterBlock, irq_enable1),
+    field!(dma::RegisterBlock, irq_pending0),
+    field!(dma::RegisterBlock, irq_pending1),
+    field!(dma::RegisterBlock, auto_gating),
+    field!(dma::RegisterBlock, status),
+    dma_channel!(enable),
+    dma_channel!(pause),
+    dma_channel!(start_addr),
+    dma_channel!(config),
+    dma_channel!(current_src_addr),
+    dma_channel!(current_destination),
+    dma_channel!(byte_counter_left),
+    dma_channel!(parameter),
+    dma_channel!(mode),
+    dma_channel!(former_desc_addr),
+    dma_channel!(package_num),
+];
+
+const COM: &[Register] = &[
+    field!(com::RegisterBlock, work_mode_0),
+    field!(com::RegisterBlock, work_mode_1),
+    field!(com::RegisterBlock, dbgcr),
+    field!(com::RegisterBlock, tmr),
+    field!(com::RegisterBlock, cccr),
+    field!(com::RegisterBlock, maer0),
+    field!(com::RegisterBlock, maer1),
+    field!(com::RegisterBlock, maer2),
+    field!(com::RegisterBlock, remap0),
+    field!(com::RegisterBlock, remap1),
+    field!(com::RegisterBlock, remap2),
+    field!(com::RegisterBlock, remap3),
+];
+
+/// Registers of every DATX8 byte lane, named `datx_<field>`.
+macro_rules! phy_datx {
+    ($field:ident) => {
+        Register::new(
+            concat!("datx_", stringify!($field)),
+            offset_of!(phy::RegisterBlock, datx) + offset_of!(phy::Datx8, $field),
+        )
+        .array(4, size_of::<phy::Datx8>())
+    };
+}
+
+const PHY: &[Register] = &[
+    field!(phy::RegisterBlock, pir),
+    field!(phy::RegisterBlock, pwrctl),
+    field!(phy::RegisterBlock, mrctrl0),
+    field!(phy::RegisterBlock, clken),
+    field!(phy::RegisterBlock, pgsr[2]),
+    field!(phy::RegisterBlock, statr),
+    field!(phy::RegisterBlock, lp3mr11),
+    field!(phy::RegisterBlock, mr[4]),
+    field!(phy::RegisterBlock, ptr[5]),
+    field!(phy::RegisterBlock, dramtmg[9]),
+    field!(phy::RegisterBlock, odtcfg),
+    field!(phy::RegisterBlock, pitmg[2]),
+    field!(phy::RegisterBlock, lptpr),
+    field!(phy::RegisterBlock, rfshctl0),
+    field!(phy::RegisterBlock, rfshtmg),
+    field!(phy::RegisterBlock, rfshctl1),
+    field!(phy::RegisterBlock, pwrtmg),
+    field!(phy::RegisterBlock, asrc),
+    field!(phy::RegisterBlock, asrtc),
+    field!(phy::RegisterBlock, vtfcr),
+    field!(phy::RegisterBlock, dqsgmr),
+    field!(phy::RegisterBlock, dtcr),
+    field!(phy::RegisterBlock, dtar0),
+    field!(phy::RegisterBlock, pgcr[4]),
+    field!(phy::RegisterBlock, iovcr0),
+    field!(phy::RegisterBlock, iovcr1),
+    field!(phy::RegisterBlock, dxccr),
+    field!(phy::RegisterBlock, odtmap),
+    field!(phy::RegisterBlock, zqctl[2]),
+    field!(phy::RegisterBlock, zqcr),
+    field!(phy::RegisterBlock, zqsr),
+    field!(phy::RegisterBlock, zqdr[3]),
+    field!(phy::RegisterBlock, sched),
+    field!(phy::RegisterBlock, perfhpr[2]),
+    field!(phy::RegisterBlock, perflpr[2]),
+    field!(phy::RegisterBlock, perfwr[2]),
+    field!(phy::RegisterBlock, acmdlr),
+    field!(phy::RegisterBlock, acldlr),
+    field!(phy::RegisterBlock, aciocr0),
+    phy_datx!(mdlr),
+    phy_datx!(sdlr6),
+    phy_datx!(gtr),
+    phy_datx!(gcr),
+    phy_datx!(gsr0),
+    phy_datx!(gsr1),
+    phy_datx!(gsr2),
+    field!(phy::RegisterBlock, upd2),
+];
+
+/// Peripherals of D1 and T113, following `allwinner_rt::soc::d1`.
+pub(crate) const D1: &[Peripheral] = &[
+    Peripheral {
+        name: "gpio",
+        base: 0x0200_0000,
+        registers: GPIO,
+    },
+    Peripheral {
+        name: "ccu",
+        base: 0x0200_1000,
+        registers: CCU,
+    },
+    Peripheral {
+        name: "uart0",
+        base: 0x0250_0000,
+        registers: UART,
+    },
+    Peripheral {
+        name: "dma",
+        base: 0x0300_2000,
+        registers: DMA,
+    },
+    Peripheral {
+        name: "com",
+        base: 0x0310_2000,
+        registers: COM,
+    },
+    Peripheral {
+        name: "phy",
+        base: 0x0310_3000,
+        registers: PHY,
+    },
+    Peripheral {
+        name: "smhc0",
+        base: 0x0402_0000,
+        registers: SMHC,
+    },
+    Peripheral {
+        name: "smhc1",
+        base: 0x0402_1000,
+        registers: SMHC,
+    },
+    Peripheral {
+        name: "smhc2",
+        base: 0x0402_2000,
+        registers: SMHC,
+    },
+    Peripheral {
+        name: "spi0",
+        base: 0x0402_5000,
+        registers: SPI,
+    },
+];
+
+/// Peripherals of V853, following `allwinner_rt::soc::v853`.
+pub(crate) const V853: &[Peripheral] = &[Peripheral {
+    name: "gpio",
+    base: 0x4004_a400,
+    registers: GPIO,
+}];
+
+/// Peripherals of V821, following `allwinner_rt::soc::v821`.
+pub(crate) const V821: &[Peripheral] = &[
+    Peripheral {
+        name: "gpio",
+        base: 0x4200_0000,
+        registers: GPIO,
+    },
+    Peripheral {
+        name: "uart0",
+        base: 0x4250_0000,
+        registers: UART,
+    },
+    Peripheral {
+        name: "uart1",
+        base: 0x4250_0400,
+        registers: UART,
+    },
+    Peripheral {
+        name: "uart2",
+        base: 0x4250_0800,
+        registers: UART,
+    },
+    Peripheral {
+        name: "uart3",
+        base: 0x4250_0c00,
+        registers: UART,
+    },
+];
+
+#[cfg(test)]
+mod tests {
+    use super::{D1, resolve};
+
+    #[test]
+    fn regs_resolve() {
+        let pll = resolve(D1, "ccu.pll_cpu_control").unwrap();
+        assert_eq!(pll.address, 0x0200_1000);
+        let fields = (pll.decode.unwrap())(0xa000_2301);
+        assert!(fields.contains(&("is_pll_enabled", "true".to_string())));
+        assert!(fields.contains(&("pll_n", "35".to_string())));
+        let smhc_clk = resolve(D1, "CCU.smhc_clk[2]").unwrap();
+        assert_eq!(smhc_clk.name, "ccu.smhc_clk[2]");
+        assert_eq!(smhc_clk.address, 0x0200_1838);
+        assert!(resolve(D1, "ccu.smhc_clk").is_none());
+        assert!(resolve(D1, "ccu.smhc_clk[3]").is_none());
+        assert_eq!(resolve(D1, "gpio.pc_cfg[0]").unwrap().address, 0x0200_0060);
+        assert_eq!(
+            resolve(D1, "gpio.pb_eint_ctl").unwrap().address,
+            0x0200_0230
+        );
+        assert_eq!(resolve(D1, "uart0.usr").unwrap().address, 0x0250_007c);
+        assert_eq!(
+            resolve(D1, "dma.channel_enable[1]").unwrap().address,
+            0x0300_2140
+        );
+        assert_eq!(resolve(D1, "phy.datx_gtr[1]").unwrap().address, 0x0310_33c0);
+        assert_eq!(resolve(D1, "spi0.fsr").unwrap().address, 0x0402_501c);
+        assert!(resolve(D1, "spi0.nonexistent").is_none());
+    }
+}
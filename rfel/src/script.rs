@@ -0,0 +1,241 @@
+//! Scripts of FEL operations run in one session by `rfel run`.
+//!
+//! A script is a TOML file with optional `[vars]` and an array of `[[steps]]`,
+//! each selecting its operation with `action`. String fields may refer to
+//! variables as `${name}`; relative file paths are resolved against the
+//! directory of the script.
+//!
+//! ```toml
+//! [vars]
+//! spl = 0x20000
+//!
+//! [[steps]]
+//! action = "write"
+//! address = "${spl}"
+//! file = "boot0.bin"
+//!
+//! [[steps]]
+//! action = "exec"
+//! address = "${spl}"
+//! ```
+
+use core::fmt;
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+/// Script of FEL operations.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct Script {
+    /// Variables shared by steps.
+    #[serde(default)]
+    pub vars: BTreeMap<String, Var>,
+    /// Steps run in order.
+    pub steps: Vec<Step>,
+}
+
+/// Value of a script variable.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum Var {
+    /// Integer, substituted in hexadecimal.
+    Integer(u64),
+    /// String, substituted as is.
+    String(String),
+}
+
+/// One operation of a script.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(tag = "action", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum Step {
+    /// Write file content into chip memory.
+    Write { address: String, file: PathBuf },
+    /// Write a 32-bit value into chip memory.
+    Write32 { address: String, value: String },
+    /// Read chip memory into a file.
+    Read {
+        address: String,
+        length: String,
+        file: PathBuf,
+    },
+    /// Compare chip memory against file content, failing on mismatch.
+    Verify { address: String, file: PathBuf },
+    /// Call function at address.
+    Exec { address: String },
+    /// Reset chip through its watchdog.
+    Reset,
+    /// Wait until device re-enumerates in FEL mode, then reopen it.
+    WaitForReenumerate {
+        /// Seconds to wait, 10 if absent.
+        #[serde(default)]
+        timeout: Option<u64>,
+    },
+    /// Pause for given milliseconds.
+    Sleep { ms: u64 },
+}
+
+impl Script {
+    /// Parse script and substitute variables, resolving file paths against `base`.
+    pub fn parse(text: &str, base: &Path) -> Result<Self, ScriptError> {
+        let mut script: Script =
+            toml::from_str(text).map_err(|e| ScriptError::Parse(e.to_string()))?;
+        let vars = &script.vars;
+        let subst = |s: &mut String| -> Result<(), ScriptError> {
+            *s = substitute(s, vars)?;
+            Ok(())
+        };
+        let resolve = |path: &mut PathBuf| -> Result<(), ScriptError> {
+            let text = substitute(&path.to_string_lossy(), vars)?;
+            *path = base.join(text);
+            Ok(())
+        };
+        for step in &mut script.steps {
+            match step {
+                Step::Write { address, file } | Step::Verify { address, file } => {
+                    subst(address)?;
+                    resolve(file)?;
+                }
+                Step::Write32 { address, value } => {
+                    subst(address)?;
+                    subst(value)?;
+                }
+                Step::Read {
+                    address,
+                    length,
+                    file,
+                } => {
+                    subst(address)?;
+                    subst(length)?;
+                    resolve(file)?;
+                }
+                Step::Exec { address } => subst(address)?,
+                Step::Reset | Step::WaitForReenumerate { .. } | Step::Sleep { .. } => {}
+            }
+        }
+        Ok(script)
+    }
+}
+
+/// Replace each `${name}` in `s` with value of variable `name`.
+fn substitute(s: &str, vars: &BTreeMap<String, Var>) -> Result<String, ScriptError> {
+    let mut ans = String::new();
+    let mut rest = s;
+    while let Some(start) = rest.find("${") {
+        ans.push_str(&rest[..start]);
+        let Some(len) = rest[start + 2..].find('}') else {
+            return Err(ScriptError::UnclosedVar(s.to_string()));
+        };
+        let name = &rest[start + 2..start + 2 + len];
+        match vars.get(name) {
+            Some(Var::Integer(value)) => ans.push_str(&format!("0x{:x}", value)),
+            Some(Var::String(value)) => ans.push_str(value),
+            None => return Err(ScriptError::UndefinedVar(name.to_string())),
+        }
+        rest = &rest[start + 3 + len..];
+    }
+    ans.push_str(rest);
+    Ok(ans)
+}
+
+/// Error in script content.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ScriptError {
+    /// Script is not valid TOML or does not describe steps.
+    Parse(String),
+    /// Step refers to an undefined variable.
+    UndefinedVar(String),
+    /// `${` without closing `}` in given string.
+    UnclosedVar(String),
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScriptError::Parse(e) => write!(f, "invalid script: {}", e),
+            ScriptError::UndefinedVar(name) => write!(f, "undefined variable {:?}", name),
+            ScriptError::UnclosedVar(s) => write!(f, "unclosed variable in {:?}", s),
+        }
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+#[cfg(test)]
+mod tests {
+    use super::{Script, ScriptError, Step};
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn script_parse() {
+        let text = r#"
+            [vars]
+            spl = 0x20000
+            dram = "0x40000000"
+            name = "boot0"
+
+            [[steps]]
+            action = "write"
+            address = "${spl}"
+            file = "${name}.bin"
+
+            [[steps]]
+            action = "exec"
+            address = "${spl}"
+
+            [[steps]]
+            action = "wait-for-reenumerate"
+
+            [[steps]]
+            action = "sleep"
+            ms = 100
+
+            [[steps]]
+            action = "read"
+            address = "${dram}"
+            length = "0x100"
+            file = "/tmp/out.bin"
+        "#;
+        let script = Script::parse(text, Path::new("/board")).unwrap();
+        assert_eq!(
+            script.steps,
+            [
+                Step::Write {
+                    address: "0x20000".to_string(),
+                    file: PathBuf::from("/board/boot0.bin")
+                },
+                Step::Exec {
+                    address: "0x20000".to_string()
+                },
+                Step::WaitForReenumerate { timeout: None },
+                Step::Sleep { ms: 100 },
+                Step::Read {
+                    address: "0x40000000".to_string(),
+                    length: "0x100".to_string(),
+                    file: PathBuf::from("/tmp/out.bin")
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn script_errors() {
+        let undefined = "[[steps]]\naction = \"exec\"\naddress = \"${spl}\"\n";
+        assert_eq!(
+            Script::parse(undefined, Path::new(".")),
+            Err(ScriptError::UndefinedVar("spl".to_string()))
+        );
+        let unclosed = "[[steps]]\naction = \"exec\"\naddress = \"${spl\"\n";
+        assert!(matches!(
+            Script::parse(unclosed, Path::new(".")),
+            Err(ScriptError::UnclosedVar(_))
+        ));
+        let unknown = "[[steps]]\naction = \"format-disk\"\n";
+        assert!(matches!(
+            Script::parse(unknown, Path::new(".")),
+            Err(ScriptError::Parse(_))
+        ));
+    }
+}
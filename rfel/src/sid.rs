@@ -0,0 +1,68 @@
+use crate::Chip;
+use core::fmt;
+
+/// Security ID (SID) eFuse content of a chip.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Sid {
+    /// 128-bit chip ID, as four words in eFuse order.
+    pub chip_id: [u32; 4],
+    /// DRAM type fuse, selects DRAM PHY pin remapping on D1 and T113.
+    pub ddr_efuse_type: Option<u8>,
+}
+
+impl Sid {
+    /// Number of bytes read from SID address to decode all fields.
+    pub const READ_SIZE: usize = 0x2c;
+
+    /// Decode SID fields from bytes read at chip SID address.
+    pub fn parse(chip: Chip, buf: &[u8; Self::READ_SIZE]) -> Self {
+        let word = |offset: usize| u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap());
+        let chip_id = [word(0x0), word(0x4), word(0x8), word(0xc)];
+        let ddr_efuse_type = match chip {
+            Chip::D1 | Chip::T113 => Some(((word(0x28) >> 8) & 0xf) as u8),
+            _ => None,
+        };
+        Sid {
+            chip_id,
+            ddr_efuse_type,
+        }
+    }
+
+    /// Chip ID as one 128-bit number, first word most significant.
+    #[inline]
+    pub const fn chip_id_u128(&self) -> u128 {
+        (self.chip_id[0] as u128) << 96
+            | (self.chip_id[1] as u128) << 64
+            | (self.chip_id[2] as u128) << 32
+            | self.chip_id[3] as u128
+    }
+}
+
+impl fmt::Display for Sid {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:032x}", self.chip_id_u128())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Sid;
+    use crate::Chip;
+
+    #[test]
+    fn sid_parse() {
+        let mut buf = [0u8; Sid::READ_SIZE];
+        for (index, word) in [0x93404800u32, 0xc0004814, 0x010a4d84, 0x10731d0e]
+            .iter()
+            .enumerate()
+        {
+            buf[index * 4..index * 4 + 4].copy_from_slice(&word.to_le_bytes());
+        }
+        buf[0x28..0x2c].copy_from_slice(&0x0000_0b00u32.to_le_bytes());
+        let sid = Sid::parse(Chip::D1, &buf);
+        assert_eq!(sid.to_string(), "93404800c0004814010a4d8410731d0e");
+        assert_eq!(sid.ddr_efuse_type, Some(0xb));
+        assert_eq!(Sid::parse(Chip::H6, &buf).ddr_efuse_type, None);
+    }
+}
@@ -0,0 +1,460 @@
+//! SPI controller driven through FEL.
+//!
+//! On known chips a payload runs whole batches of transactions on chip;
+//! otherwise host drives controller registers by FEL reads and writes.
+
+use crate::{Error, Fel, FelTransport, Result, payload};
+use allwinner_hal::spi::RegisterBlock;
+use core::mem::offset_of;
+use std::time::{Duration, Instant};
+
+/// Half-duplex SPI bus, holding chip select during each transaction.
+pub trait SpiBus {
+    /// Select chip, send `write` then receive into `read`, and deselect chip.
+    fn transaction(&self, write: &[u8], read: &mut [u8]) -> Result<()>;
+
+    /// Run operations in order.
+    ///
+    /// Provided method runs them one transaction at a time; buses may run
+    /// many operations at once instead.
+    fn batch(&self, ops: &mut [SpiOp<'_>]) -> Result<()> {
+        run_batch(self, ops)
+    }
+
+    /// Largest transaction length that runs as fast as shorter ones; longer
+    /// transactions still work, but callers that can split them should.
+    fn max_transfer(&self) -> usize {
+        usize::MAX
+    }
+}
+
+/// Operation of a batch run by [`SpiBus::batch`].
+#[derive(Debug)]
+pub enum SpiOp<'a> {
+    /// Transaction as in [`SpiBus::transaction`].
+    Transaction(&'a [u8], &'a mut [u8]),
+    /// Repeat a transaction of `opcode` reading one status byte until none of
+    /// `mask` bits is set, failing with [`Error::Timeout`] if it takes too long.
+    WaitReady {
+        /// Status read opcode.
+        opcode: u8,
+        /// Busy bits of status.
+        mask: u8,
+    },
+}
+
+/// Longest time to wait for a device to become ready.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Run operations one transaction at a time.
+fn run_batch<B: SpiBus + ?Sized>(bus: &B, ops: &mut [SpiOp<'_>]) -> Result<()> {
+    for op in ops {
+        match op {
+            SpiOp::Transaction(write, read) => bus.transaction(write, read)?,
+            SpiOp::WaitReady { opcode, mask } => {
+                let deadline = Instant::now() + BUSY_TIMEOUT;
+                loop {
+                    let mut status = [0u8];
+                    bus.transaction(&[*opcode], &mut status)?;
+                    if status[0] & *mask == 0 {
+                        break;
+                    }
+                    if Instant::now() >= deadline {
+                        return Err(Error::Timeout);
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// SPI0 controller parameters of a chip.
+#[derive(Debug)]
+pub struct SpiInfo {
+    /// Base address of registers laid out as `allwinner_hal::spi::RegisterBlock`.
+    pub base: u32,
+    /// Read-modify-write operations as `(address, mask, value)` to enable
+    /// SPI0 pads, bus clock and module clock.
+    pub setup: &'static [(u32, u32, u32)],
+}
+
+const GCR: u32 = offset_of!(RegisterBlock, gcr) as u32;
+const TCR: u32 = offset_of!(RegisterBlock, tcr) as u32;
+const FCR: u32 = offset_of!(RegisterBlock, fcr) as u32;
+const FSR: u32 = offset_of!(RegisterBlock, fsr) as u32;
+const MBC: u32 = offset_of!(RegisterBlock, mbc) as u32;
+const MTC: u32 = offset_of!(RegisterBlock, mtc) as u32;
+const BCC: u32 = offset_of!(RegisterBlock, bcc) as u32;
+const TXD: u32 = offset_of!(RegisterBlock, txd) as u32;
+const RXD: u32 = offset_of!(RegisterBlock, rxd) as u32;
+
+const GCR_SRST: u32 = 1 << 31;
+const GCR_TP_EN: u32 = 1 << 7;
+const GCR_MODE_MASTER: u32 = 1 << 1;
+const GCR_EN: u32 = 1 << 0;
+const TCR_XCH: u32 = 1 << 31;
+const TCR_SS_LEVEL: u32 = 1 << 7;
+const TCR_SS_OWNER: u32 = 1 << 6;
+const TCR_SPOL: u32 = 1 << 2;
+const FCR_TX_FIFO_RST: u32 = 1 << 31;
+const FCR_RX_FIFO_RST: u32 = 1 << 15;
+const FSR_RF_CNT: u32 = 0xff;
+
+/// Depth of transmit and receive FIFO in bytes.
+const FIFO_SIZE: usize = 64;
+/// Times to poll a register before giving up.
+const POLL_LIMIT: usize = 1000;
+
+/// SPI controller in master mode, operated through FEL.
+pub struct Spi<'a, T> {
+    fel: &'a Fel<T>,
+    base: u32,
+    payload: Option<SpiPayload>,
+}
+
+/// SPI payload loaded in chip scratch region.
+struct SpiPayload {
+    /// Payload address.
+    base: u32,
+    /// Size of command buffer, and also of receive buffer following it.
+    buffer_size: usize,
+}
+
+impl SpiPayload {
+    fn params(&self) -> u32 {
+        self.base + payload::SPI_PARAMS_OFFSET
+    }
+
+    fn command_buffer(&self) -> u32 {
+        self.base + payload::SPI_BUFFER_OFFSET
+    }
+
+    fn receive_buffer(&self) -> u32 {
+        self.command_buffer() + self.buffer_size as u32
+    }
+}
+
+impl<'a, T: FelTransport> Spi<'a, T> {
+    /// Set up pads and clocks, then reset and configure SPI controller.
+    ///
+    /// Also loads SPI payload on known chips.
+    pub fn open(fel: &'a Fel<T>, info: &SpiInfo) -> Result<Self> {
+        for &(address, mask, value) in info.setup {
+            let mut buf = [0u8; 4];
+            fel.read_address(address, &mut buf)?;
+            let value = (u32::from_le_bytes(buf) & !mask) | value;
+            fel.write_address(address, &value.to_le_bytes())?;
+        }
+        let payload = match fel.chip()? {
+            Some(chip) => fel
+                .load_payload(&payload::SPI_ARM, &payload::SPI_RISCV)?
+                .map(|base| SpiPayload {
+                    base,
+                    buffer_size: (chip.info().scratch.size - payload::SPI_BUFFER_OFFSET) as usize
+                        / 2,
+                }),
+            None => None,
+        };
+        let spi = Spi {
+            fel,
+            base: info.base,
+            payload,
+        };
+        spi.write_reg(GCR, GCR_SRST | GCR_TP_EN | GCR_MODE_MASTER | GCR_EN)?;
+        spi.poll_reg(GCR, |gcr| gcr & GCR_SRST == 0)?;
+        // chip select is controlled by software, active low and deselected.
+        spi.write_reg(TCR, TCR_SS_OWNER | TCR_SS_LEVEL | TCR_SPOL)?;
+        spi.write_reg(FCR, FCR_TX_FIFO_RST | FCR_RX_FIFO_RST)?;
+        Ok(spi)
+    }
+
+    fn read_reg(&self, offset: u32) -> Result<u32> {
+        let mut buf = [0u8; 4];
+        self.fel.read_address(self.base + offset, &mut buf)?;
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    fn write_reg(&self, offset: u32, value: u32) -> Result<()> {
+        self.fel
+            .write_address(self.base + offset, &value.to_le_bytes())?;
+        Ok(())
+    }
+
+    fn poll_reg(&self, offset: u32, f: impl Fn(u32) -> bool) -> Result<u32> {
+        for _ in 0..POLL_LIMIT {
+            let value = self.read_reg(offset)?;
+            if f(value) {
+                return Ok(value);
+            }
+        }
+        Err(Error::Timeout)
+    }
+
+    fn set_chip_select(&self, selected: bool) -> Result<()> {
+        let tcr = self.read_reg(TCR)?;
+        let tcr = if selected {
+            tcr & !TCR_SS_LEVEL
+        } else {
+            tcr | TCR_SS_LEVEL
+        };
+        self.write_reg(TCR, tcr)
+    }
+
+    /// Run one burst of at most `FIFO_SIZE` bytes.
+    fn burst(&self, write: &[u8], read_len: usize) -> Result<()> {
+        let total = (write.len() + read_len) as u32;
+        self.write_reg(MBC, total)?;
+        self.write_reg(MTC, write.len() as u32)?;
+        self.write_reg(BCC, write.len() as u32)?;
+        // 32-bit accesses to data registers move 4 bytes through FIFO at once.
+        for chunk in write.chunks(4) {
+            if chunk.len() == 4 {
+                self.fel.write_address(self.base + TXD, chunk)?;
+            } else {
+                for byte in chunk {
+                    self.fel
+                        .write_address(self.base + TXD, core::slice::from_ref(byte))?;
+                }
+            }
+        }
+        let tcr = self.read_reg(TCR)?;
+        self.write_reg(TCR, tcr | TCR_XCH)?;
+        self.poll_reg(TCR, |tcr| tcr & TCR_XCH == 0)?;
+        Ok(())
+    }
+
+    fn write(&self, buf: &[u8]) -> Result<()> {
+        for chunk in buf.chunks(FIFO_SIZE) {
+            self.burst(chunk, 0)?;
+        }
+        // drop bytes received while transmitting.
+        self.write_reg(FCR, FCR_RX_FIFO_RST)
+    }
+
+    fn read(&self, buf: &mut [u8]) -> Result<()> {
+        for chunk in buf.chunks_mut(FIFO_SIZE) {
+            self.burst(&[], chunk.len())?;
+            let len = chunk.len() as u32;
+            self.poll_reg(FSR, |fsr| fsr & FSR_RF_CNT >= len)?;
+            for word in chunk.chunks_mut(4) {
+                if word.len() == 4 {
+                    self.fel.read_address(self.base + RXD, word)?;
+                } else {
+                    for byte in word {
+                        self.fel
+                            .read_address(self.base + RXD, core::slice::from_mut(byte))?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<T: FelTransport> Spi<'_, T> {
+    /// Run a transaction by register reads and writes from host.
+    fn host_transaction(&self, write: &[u8], read: &mut [u8]) -> Result<()> {
+        self.set_chip_select(true)?;
+        let ans = self.write(write).and_then(|_| self.read(read));
+        self.set_chip_select(false)?;
+        ans
+    }
+
+    /// Run leading operations that fit payload buffers in one payload run,
+    /// returning how many operations ran.
+    fn payload_batch(&self, payload: &SpiPayload, ops: &mut [SpiOp<'_>]) -> Result<usize> {
+        let mut commands = Vec::new();
+        let mut received = 0;
+        let mut count = 0;
+        for op in ops.iter() {
+            let (command_len, read_len) = match op {
+                SpiOp::Transaction(write, read) => (5 + write.len(), read.len()),
+                SpiOp::WaitReady { .. } => (3, 0),
+            };
+            // one byte left for end command.
+            if commands.len() + command_len >= payload.buffer_size
+                || received + read_len > payload.buffer_size
+            {
+                break;
+            }
+            match op {
+                SpiOp::Transaction(write, read) => {
+                    commands.push(payload::SPI_TRANSFER);
+                    commands.extend_from_slice(&(write.len() as u16).to_le_bytes());
+                    commands.extend_from_slice(&(read.len() as u16).to_le_bytes());
+                    commands.extend_from_slice(write);
+                }
+                SpiOp::WaitReady { opcode, mask } => {
+                    commands.extend_from_slice(&[payload::SPI_WAIT, *opcode, *mask]);
+                }
+            }
+            received += read_len;
+            count += 1;
+        }
+        if count == 0 {
+            return Ok(0);
+        }
+        commands.push(payload::SPI_END);
+        self.fel
+            .write_address(payload.command_buffer(), &commands)?;
+        let words = [
+            self.base,
+            payload.command_buffer(),
+            payload.receive_buffer(),
+            0,
+        ];
+        self.fel
+            .write_address(payload.params(), &payload::to_bytes(&words))?;
+        self.fel.exec(payload.base)?;
+        let mut status = [0u8; 4];
+        self.fel.read_address(payload.params() + 12, &mut status)?;
+        let mut buf = vec![0u8; received];
+        self.fel.read_address(payload.receive_buffer(), &mut buf)?;
+        let mut rest = &buf[..];
+        for op in ops[..count].iter_mut() {
+            if let SpiOp::Transaction(_, read) = op {
+                read.copy_from_slice(&rest[..read.len()]);
+                rest = &rest[read.len()..];
+            }
+        }
+        // transactions before a failed wait did run, but batch as a whole failed.
+        match u32::from_le_bytes(status) {
+            0 => Ok(count),
+            _ => Err(Error::Timeout),
+        }
+    }
+}
+
+impl<T: FelTransport> SpiBus for Spi<'_, T> {
+    fn transaction(&self, write: &[u8], read: &mut [u8]) -> Result<()> {
+        self.batch(&mut [SpiOp::Transaction(write, read)])
+    }
+
+    fn batch(&self, ops: &mut [SpiOp<'_>]) -> Result<()> {
+        let Some(payload) = &self.payload else {
+            return run_batch(self, ops);
+        };
+        let mut ops = ops;
+        while !ops.is_empty() {
+            let count = match self.payload_batch(payload, ops)? {
+                // operation too large for payload buffers.
+                0 => {
+                    if let SpiOp::Transaction(write, read) = &mut ops[0] {
+                        self.host_transaction(write, read)?;
+                    }
+                    1
+                }
+                count => count,
+            };
+            ops = &mut ops[count..];
+        }
+        Ok(())
+    }
+
+    fn max_transfer(&self) -> usize {
+        // payload buffers hold one transaction with its command head.
+        self.payload
+            .as_ref()
+            .map_or(usize::MAX, |payload| payload.buffer_size - 8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BCC, FSR, GCR, MBC, RXD, Spi, SpiBus, SpiOp, SpiPayload, TCR, TXD};
+    use crate::{Error, Fel, mock::MockDevice, payload};
+    use std::{cell::RefCell, rc::Rc};
+
+    #[test]
+    fn spi_register_offsets() {
+        assert_eq!(GCR, 0x04);
+        assert_eq!(TCR, 0x08);
+        assert_eq!(FSR, 0x1c);
+        assert_eq!(MBC, 0x30);
+        assert_eq!(BCC, 0x38);
+        assert_eq!(TXD, 0x200);
+        assert_eq!(RXD, 0x300);
+    }
+
+    #[test]
+    fn spi_payload_batch() {
+        let runs = Rc::new(RefCell::new(Vec::new()));
+        let runs_1 = runs.clone();
+        let device = MockDevice::new(0x00185900, 0x7e00);
+        // Simulate payload on a device answering each transaction by
+        // incrementing bytes from its first written byte on, and busy
+        // forever on status opcode 0x70.
+        device.set_exec_handler(move |address, memory| {
+            let params = address + payload::SPI_PARAMS_OFFSET;
+            let mut rx = memory.read_u32(params + 8);
+            let mut commands = [0u8; 32];
+            memory.read(memory.read_u32(params + 4), &mut commands);
+            let mut command = &commands[..];
+            let mut ops = Vec::new();
+            let mut status = 0;
+            loop {
+                match command[0] {
+                    payload::SPI_TRANSFER => {
+                        let write_len = u16::from_le_bytes([command[1], command[2]]) as usize;
+                        let read_len = u16::from_le_bytes([command[3], command[4]]);
+                        let first = command[5];
+                        ops.push(first);
+                        let reply: Vec<u8> =
+                            (0..read_len).map(|i| first.wrapping_add(i as u8)).collect();
+                        memory.write(rx, &reply);
+                        rx += read_len as u32;
+                        command = &command[5 + write_len..];
+                    }
+                    payload::SPI_WAIT => {
+                        ops.push(command[1]);
+                        if command[1] == 0x70 {
+                            status = 1;
+                            break;
+                        }
+                        command = &command[3..];
+                    }
+                    _ => break,
+                }
+            }
+            memory.write_u32(params + 12, status);
+            runs_1.borrow_mut().push(ops);
+            Ok(())
+        });
+        let fel = Fel::new(device);
+        let spi = Spi {
+            fel: &fel,
+            base: 0x0402_5000,
+            payload: Some(SpiPayload {
+                base: 0x0003_0000,
+                buffer_size: 32,
+            }),
+        };
+        assert_eq!(spi.max_transfer(), 24);
+
+        let (mut a, mut b, mut c) = ([0u8; 4], [0u8; 30], [0u8; 8]);
+        spi.batch(&mut [
+            SpiOp::Transaction(&[0x10], &mut a),
+            SpiOp::WaitReady {
+                opcode: 0x05,
+                mask: 1,
+            },
+            SpiOp::Transaction(&[0x20, 0, 0], &mut b),
+            SpiOp::Transaction(&[0x30; 20], &mut c),
+        ])
+        .unwrap();
+        assert_eq!(a, [0x10, 0x11, 0x12, 0x13]);
+        assert_eq!(b[29], 0x20 + 29);
+        assert_eq!(c, [0x30, 0x31, 0x32, 0x33, 0x34, 0x35, 0x36, 0x37]);
+        // operations run as many as fit buffers per payload run.
+        assert_eq!(*runs.borrow(), [vec![0x10, 0x05], vec![0x20], vec![0x30]]);
+
+        assert!(matches!(
+            spi.batch(&mut [SpiOp::WaitReady {
+                opcode: 0x70,
+                mask: 1
+            }]),
+            Err(Error::Timeout)
+        ));
+    }
+}
@@ -0,0 +1,590 @@
+//! SPI NAND flash detection, erase, program and read over an SPI bus.
+//!
+//! Data is stored in main area of pages with on-die ECC enabled; spare area is
+//! left to the flash, except for bad block markers which are honoured by
+//! skipping bad blocks. Blocks failing to erase or program are marked bad
+//! the same way as factory bad blocks.
+
+use crate::{Error, Result, spi::SpiBus};
+use std::time::{Duration, Instant};
+
+const RESET: u8 = 0xff;
+const READ_ID: u8 = 0x9f;
+const GET_FEATURE: u8 = 0x0f;
+const SET_FEATURE: u8 = 0x1f;
+const WRITE_ENABLE: u8 = 0x06;
+const PAGE_READ: u8 = 0x13;
+const READ_FROM_CACHE: u8 = 0x03;
+const PROGRAM_LOAD: u8 = 0x02;
+const PROGRAM_EXECUTE: u8 = 0x10;
+const BLOCK_ERASE: u8 = 0xd8;
+
+const FEATURE_PROTECTION: u8 = 0xa0;
+const FEATURE_CONFIG: u8 = 0xb0;
+const FEATURE_STATUS: u8 = 0xc0;
+
+const CONFIG_ECC_EN: u8 = 1 << 4;
+const STATUS_BUSY: u8 = 1 << 0;
+const STATUS_ERASE_FAIL: u8 = 1 << 2;
+const STATUS_PROGRAM_FAIL: u8 = 1 << 3;
+const STATUS_ECC_MASK: u8 = 0b11 << 4;
+const STATUS_ECC_UNCORRECTABLE: u8 = 0b10 << 4;
+
+/// Longest time of a block erase among supported flashes.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Parameters of an SPI NAND flash.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NandInfo {
+    /// Part name.
+    pub name: &'static str,
+    /// JEDEC manufacturer and device ID.
+    pub id: [u8; 3],
+    /// Main area size of a page in bytes.
+    pub page_size: u32,
+    /// Spare area size of a page in bytes.
+    pub spare_size: u32,
+    /// Number of pages in an erase block.
+    pub pages_per_block: u32,
+    /// Number of erase blocks.
+    pub blocks: u32,
+}
+
+impl NandInfo {
+    /// Main area size of an erase block in bytes.
+    #[inline]
+    pub const fn block_size(&self) -> u32 {
+        self.page_size * self.pages_per_block
+    }
+    /// Main area capacity in bytes.
+    #[inline]
+    pub const fn size(&self) -> u32 {
+        self.block_size() * self.blocks
+    }
+}
+
+const fn nand(name: &'static str, id: [u8; 3], spare_size: u32, blocks: u32) -> NandInfo {
+    NandInfo {
+        name,
+        id,
+        page_size: 2048,
+        spare_size,
+        pages_per_block: 64,
+        blocks,
+    }
+}
+
+/// Known flashes; IDs shorter than three bytes are padded by the flash, so
+/// only manufacturer and first device byte are compared when third byte is zero.
+const KNOWN_NAND: &[NandInfo] = &[
+    nand("W25N01GV", [0xef, 0xaa, 0x21], 64, 1024),
+    nand("W25N02KV", [0xef, 0xaa, 0x22], 128, 2048),
+    nand("GD5F1GQ4UB", [0xc8, 0xd1, 0x00], 128, 1024),
+    nand("GD5F2GQ4UB", [0xc8, 0xd2, 0x00], 128, 2048),
+    nand("GD5F1GQ5UE", [0xc8, 0x51, 0x00], 128, 1024),
+    nand("MX35LF1GE4AB", [0xc2, 0x12, 0x00], 64, 1024),
+    nand("MX35LF2GE4AB", [0xc2, 0x22, 0x00], 64, 2048),
+    nand("XT26G01A", [0x0b, 0xe1, 0x00], 64, 1024),
+    nand("DS35Q1GA", [0xe5, 0x71, 0x00], 64, 1024),
+    nand("F35SQA001G", [0xcd, 0x71, 0x71], 64, 1024),
+];
+
+/// SPI NAND flash connected to an SPI bus.
+pub struct SpiNand<B> {
+    bus: B,
+    info: NandInfo,
+}
+
+impl<B: SpiBus> SpiNand<B> {
+    /// Reset and identify flash by ID, then unlock all blocks and enable on-die ECC.
+    pub fn detect(bus: B) -> Result<Self> {
+        bus.transaction(&[RESET], &mut [])?;
+        let mut id = [0u8; 3];
+        bus.transaction(&[READ_ID, 0], &mut id)?;
+        let Some(info) = KNOWN_NAND
+            .iter()
+            .find(|info| info.id[..2] == id[..2] && (info.id[2] == 0 || info.id[2] == id[2]))
+        else {
+            return Err(Error::UnknownFlash { id });
+        };
+        let nand = SpiNand {
+            bus,
+            info: info.clone(),
+        };
+        nand.wait_ready()?;
+        nand.set_feature(FEATURE_PROTECTION, 0)?;
+        let config = nand.get_feature(FEATURE_CONFIG)?;
+        nand.set_feature(FEATURE_CONFIG, config | CONFIG_ECC_EN)?;
+        Ok(nand)
+    }
+
+    /// Parameters of this flash.
+    #[inline]
+    pub fn info(&self) -> &NandInfo {
+        &self.info
+    }
+
+    /// Check factory bad block marker in spare area of first page of `block`.
+    pub fn is_bad_block(&self, block: u32) -> Result<bool> {
+        self.load_page(block * self.info.pages_per_block)?;
+        let mut marker = [0u8];
+        self.read_cache(self.info.page_size as u16, &mut marker)?;
+        Ok(marker[0] != 0xff)
+    }
+
+    /// Read main area of `page` into `buf`, at most one page long.
+    pub fn read_page(&self, page: u32, buf: &mut [u8]) -> Result<()> {
+        let status = self.load_page(page)?;
+        if status & STATUS_ECC_MASK == STATUS_ECC_UNCORRECTABLE {
+            return Err(Error::EccUncorrectable {
+                address: page * self.info.page_size,
+            });
+        }
+        self.read_cache(0, buf)
+    }
+
+    /// Program `data`, at most one page long, into main area of erased `page`.
+    pub fn program_page(&self, page: u32, data: &[u8]) -> Result<()> {
+        self.program(page, 0, data)
+    }
+
+    /// Mark `block` bad by clearing bad block marker in spare area of its first page.
+    pub fn mark_bad_block(&self, block: u32) -> Result<()> {
+        // marker may fail to program on a worn block; nothing more can be done then.
+        match self.program(
+            block * self.info.pages_per_block,
+            self.info.page_size as u16,
+            &[0; 2],
+        ) {
+            Ok(()) | Err(Error::ProgramFailed { .. }) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Erase `block`.
+    pub fn erase_block(&self, block: u32) -> Result<()> {
+        self.write_enable()?;
+        self.bus.transaction(
+            &row_command(BLOCK_ERASE, block * self.info.pages_per_block),
+            &mut [],
+        )?;
+        if self.wait_ready()? & STATUS_ERASE_FAIL != 0 {
+            return Err(Error::EraseFailed {
+                address: block * self.info.block_size(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Good blocks starting from block at `address`, in order.
+    fn good_blocks(&self, address: u32) -> impl Iterator<Item = Result<u32>> + '_ {
+        (address / self.info.block_size()..self.info.blocks).filter_map(|block| {
+            match self.is_bad_block(block) {
+                Ok(true) => None,
+                Ok(false) => Some(Ok(block)),
+                Err(e) => Some(Err(e)),
+            }
+        })
+    }
+
+    /// Erase good blocks holding `length` bytes written by [`SpiNand::write`]
+    /// at block-aligned `address`.
+    ///
+    /// Bad blocks are skipped as by [`SpiNand::write`], so erased blocks may
+    /// extend beyond `address + length`. Blocks failing to erase are marked bad
+    /// and skipped too. `progress` is called with length erased after each
+    /// block. Returns number of bad blocks skipped.
+    pub fn erase(&self, address: u32, length: u32, mut progress: impl FnMut(usize)) -> Result<u32> {
+        let block_size = self.info.block_size();
+        let mut block = address / block_size;
+        let mut remaining = length.div_ceil(block_size);
+        let mut skipped = 0;
+        while remaining > 0 {
+            if block >= self.info.blocks {
+                return Err(Error::OutOfSpace);
+            }
+            if self.is_bad_block(block)? {
+                skipped += 1;
+            } else {
+                match self.erase_block(block) {
+                    Ok(()) => {
+                        remaining -= 1;
+                        progress(block_size as usize);
+                    }
+                    Err(Error::EraseFailed { .. }) => {
+                        self.mark_bad_block(block)?;
+                        skipped += 1;
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+            block += 1;
+        }
+        Ok(skipped)
+    }
+
+    /// Erase and program `data` into good blocks starting from block-aligned
+    /// `address`, verifying each page.
+    ///
+    /// Bad blocks are skipped, so data may extend beyond `address + data.len()`.
+    /// Blocks failing to erase, program or verify are marked bad, and their
+    /// data goes to the next good block. `progress` is called with length of
+    /// data written after each block.
+    pub fn write(&self, address: u32, data: &[u8], mut progress: impl FnMut(usize)) -> Result<()> {
+        let block_size = self.info.block_size() as usize;
+        let mut blocks = self.good_blocks(address);
+        for chunk in data.chunks(block_size) {
+            self.write_good_block(&mut blocks, chunk, self.info.page_size as usize)?;
+            progress(chunk.len());
+        }
+        Ok(())
+    }
+
+    /// Read data stored by [`SpiNand::write`] at block-aligned `address` into `buf`.
+    ///
+    /// `progress` is called with length of data read after each block.
+    pub fn read(
+        &self,
+        address: u32,
+        buf: &mut [u8],
+        mut progress: impl FnMut(usize),
+    ) -> Result<()> {
+        let block_size = self.info.block_size() as usize;
+        let page_size = self.info.page_size as usize;
+        let mut blocks = self.good_blocks(address);
+        for chunk in buf.chunks_mut(block_size) {
+            let Some(block) = blocks.next() else {
+                return Err(Error::OutOfSpace);
+            };
+            let first_page = block? * self.info.pages_per_block;
+            for (index, page) in chunk.chunks_mut(page_size).enumerate() {
+                self.read_page(first_page + index as u32, page)?;
+            }
+            progress(chunk.len());
+        }
+        Ok(())
+    }
+
+    /// Write `copies` copies of boot0 image into the first good blocks.
+    ///
+    /// BROM looks for boot0 at the start of each block and loads `split_size`
+    /// bytes from every following page; chips whose BROM reads only 1024 bytes
+    /// per page need `split_size` of 1024 instead of page size.
+    pub fn write_boot0(&self, image: &[u8], split_size: u32, copies: u32) -> Result<()> {
+        let mut blocks = self.good_blocks(0);
+        for _ in 0..copies {
+            self.write_good_block(&mut blocks, image, split_size as usize)?;
+        }
+        Ok(())
+    }
+
+    /// Largest boot0 image length fitting one block with `split_size` bytes per page.
+    #[inline]
+    pub fn boot0_capacity(&self, split_size: u32) -> u32 {
+        split_size.min(self.info.page_size) * self.info.pages_per_block
+    }
+
+    /// Erase and write `data` into next block of `blocks`, marking blocks bad
+    /// and moving on while they fail.
+    fn write_good_block(
+        &self,
+        blocks: &mut impl Iterator<Item = Result<u32>>,
+        data: &[u8],
+        split_size: usize,
+    ) -> Result<()> {
+        loop {
+            let Some(block) = blocks.next() else {
+                return Err(Error::OutOfSpace);
+            };
+            let block = block?;
+            let ans = self
+                .erase_block(block)
+                .and_then(|_| self.write_block(block, data, split_size));
+            match ans {
+                Ok(()) => return Ok(()),
+                Err(
+                    Error::EraseFailed { .. }
+                    | Error::ProgramFailed { .. }
+                    | Error::VerifyMismatch { .. },
+                ) => self.mark_bad_block(block)?,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn write_block(&self, block: u32, data: &[u8], split_size: usize) -> Result<()> {
+        let first_page = block * self.info.pages_per_block;
+        let mut buf = vec![0u8; split_size];
+        for (index, chunk) in data.chunks(split_size).enumerate() {
+            let page = first_page + index as u32;
+            self.program_page(page, chunk)?;
+            let buf = &mut buf[..chunk.len()];
+            self.read_page(page, buf)?;
+            if let Some(offset) = buf.iter().zip(chunk).position(|(a, b)| a != b) {
+                return Err(Error::VerifyMismatch {
+                    address: page * self.info.page_size + offset as u32,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Program `data` into `page` from `column` on.
+    fn program(&self, page: u32, column: u16, data: &[u8]) -> Result<()> {
+        self.write_enable()?;
+        let [high, low] = column.to_be_bytes();
+        let mut command = vec![PROGRAM_LOAD, high, low];
+        command.extend_from_slice(data);
+        self.bus.transaction(&command, &mut [])?;
+        self.bus
+            .transaction(&row_command(PROGRAM_EXECUTE, page), &mut [])?;
+        if self.wait_ready()? & STATUS_PROGRAM_FAIL != 0 {
+            return Err(Error::ProgramFailed {
+                address: page * self.info.page_size,
+            });
+        }
+        Ok(())
+    }
+
+    /// Load `page` into cache and return status after loading.
+    fn load_page(&self, page: u32) -> Result<u8> {
+        self.bus
+            .transaction(&row_command(PAGE_READ, page), &mut [])?;
+        self.wait_ready()
+    }
+
+    fn read_cache(&self, column: u16, buf: &mut [u8]) -> Result<()> {
+        let [high, low] = column.to_be_bytes();
+        self.bus.transaction(&[READ_FROM_CACHE, high, low, 0], buf)
+    }
+
+    fn get_feature(&self, feature: u8) -> Result<u8> {
+        let mut value = [0u8];
+        self.bus.transaction(&[GET_FEATURE, feature], &mut value)?;
+        Ok(value[0])
+    }
+
+    fn set_feature(&self, feature: u8, value: u8) -> Result<()> {
+        self.bus
+            .transaction(&[SET_FEATURE, feature, value], &mut [])
+    }
+
+    fn write_enable(&self) -> Result<()> {
+        self.bus.transaction(&[WRITE_ENABLE], &mut [])
+    }
+
+    /// Wait until flash is idle and return its status.
+    fn wait_ready(&self) -> Result<u8> {
+        let deadline = Instant::now() + BUSY_TIMEOUT;
+        loop {
+            let status = self.get_feature(FEATURE_STATUS)?;
+            if status & STATUS_BUSY == 0 {
+                return Ok(status);
+            }
+            if Instant::now() >= deadline {
+                return Err(Error::Timeout);
+            }
+        }
+    }
+}
+
+/// Command followed by 24-bit page (row) address.
+#[inline]
+fn row_command(opcode: u8, page: u32) -> [u8; 4] {
+    let [_, a, b, c] = page.to_be_bytes();
+    [opcode, a, b, c]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SpiNand;
+    use crate::{Error, Result, spi::SpiBus};
+    use core::cell::{Cell, RefCell};
+    use std::collections::{HashMap, HashSet};
+
+    const PAGE: usize = 2048 + 64;
+
+    /// Simulated W25N01GV following common SPI NAND commands.
+    struct SimNand {
+        pages: RefCell<HashMap<u32, Vec<u8>>>,
+        cache: RefCell<Vec<u8>>,
+        status: Cell<u8>,
+        write_enabled: Cell<bool>,
+        ecc_failures: HashSet<u32>,
+        /// Blocks failing to program, though programming takes effect.
+        program_failures: HashSet<u32>,
+        /// Blocks failing to erase.
+        erase_failures: HashSet<u32>,
+        erased: RefCell<Vec<u32>>,
+    }
+
+    impl SimNand {
+        fn new(bad_blocks: &[u32]) -> Self {
+            let mut pages = HashMap::new();
+            for block in bad_blocks {
+                let mut page = vec![0xff; PAGE];
+                page[2048] = 0x00;
+                pages.insert(block * 64, page);
+            }
+            SimNand {
+                pages: RefCell::new(pages),
+                cache: RefCell::new(vec![0xff; PAGE]),
+                status: Cell::new(0),
+                write_enabled: Cell::new(false),
+                ecc_failures: HashSet::new(),
+                program_failures: HashSet::new(),
+                erase_failures: HashSet::new(),
+                erased: RefCell::new(Vec::new()),
+            }
+        }
+    }
+
+    impl SpiBus for &SimNand {
+        fn transaction(&self, write: &[u8], read: &mut [u8]) -> Result<()> {
+            let row = || u32::from_be_bytes([0, write[1], write[2], write[3]]);
+            let mut pages = self.pages.borrow_mut();
+            let mut cache = self.cache.borrow_mut();
+            match write[0] {
+                0xff | 0x1f => {}
+                0x9f => read.copy_from_slice(&[0xef, 0xaa, 0x21][..read.len()]),
+                0x0f => {
+                    read[0] = if write[1] == 0xc0 {
+                        self.status.get()
+                    } else {
+                        0
+                    }
+                }
+                0x06 => self.write_enabled.set(true),
+                0x13 => {
+                    *cache = pages.get(&row()).cloned().unwrap_or(vec![0xff; PAGE]);
+                    let ecc = if self.ecc_failures.contains(&row()) {
+                        0b10 << 4
+                    } else {
+                        0
+                    };
+                    self.status.set(ecc);
+                }
+                0x03 => {
+                    let column = u16::from_be_bytes([write[1], write[2]]) as usize;
+                    read.copy_from_slice(&cache[column..column + read.len()]);
+                }
+                0x02 => {
+                    let column = u16::from_be_bytes([write[1], write[2]]) as usize;
+                    cache.fill(0xff);
+                    cache[column..column + write.len() - 3].copy_from_slice(&write[3..]);
+                }
+                0x10 => {
+                    assert!(self.write_enabled.replace(false));
+                    let page = pages.entry(row()).or_insert(vec![0xff; PAGE]);
+                    for (byte, new) in page.iter_mut().zip(cache.iter()) {
+                        *byte &= new;
+                    }
+                    let failed = self.program_failures.contains(&(row() / 64));
+                    self.status.set(if failed { 1 << 3 } else { 0 });
+                }
+                0xd8 => {
+                    assert!(self.write_enabled.replace(false));
+                    assert_eq!(row() % 64, 0);
+                    if self.erase_failures.contains(&(row() / 64)) {
+                        self.status.set(1 << 2);
+                        return Ok(());
+                    }
+                    for page in row()..row() + 64 {
+                        pages.remove(&page);
+                    }
+                    self.erased.borrow_mut().push(row() / 64);
+                    self.status.set(0);
+                }
+                opcode => panic!("unexpected opcode 0x{:02x}", opcode),
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn spinand_write_read_skip_bad() {
+        let flash = SimNand::new(&[1]);
+        let nand = SpiNand::detect(&flash).unwrap();
+        assert_eq!(nand.info().name, "W25N01GV");
+        assert_eq!(nand.info().size(), 128 << 20);
+        assert!(nand.is_bad_block(1).unwrap());
+        assert!(!nand.is_bad_block(2).unwrap());
+
+        let data: Vec<u8> = (0..300_000u32).map(|i| (i % 251) as u8).collect();
+        nand.write(0, &data, |_| {}).unwrap();
+        assert_eq!(*flash.erased.borrow(), [0, 2, 3]);
+        let mut buf = vec![0u8; data.len()];
+        let mut total = 0;
+        nand.read(0, &mut buf, |len| total += len).unwrap();
+        assert_eq!(total, data.len());
+        assert_eq!(buf, data);
+
+        flash.erased.borrow_mut().clear();
+        let mut total = 0;
+        assert_eq!(nand.erase(0, 0x60000, |len| total += len).unwrap(), 1);
+        assert_eq!(total, 0x60000);
+        // erase covers same blocks as write, past bad block 1.
+        assert_eq!(*flash.erased.borrow(), [0, 2, 3]);
+    }
+
+    #[test]
+    fn spinand_mark_failing_blocks() {
+        let mut flash = SimNand::new(&[1]);
+        flash.program_failures.insert(2);
+        flash.erase_failures.insert(4);
+        let nand = SpiNand::detect(&flash).unwrap();
+
+        // blocks 0, 3 and 5 take data; 2 fails to program and 4 to erase.
+        let data: Vec<u8> = (0..300_000u32).map(|i| (i % 241) as u8).collect();
+        nand.write(0, &data, |_| {}).unwrap();
+        assert_eq!(*flash.erased.borrow(), [0, 2, 3, 5]);
+        for block in 0..6 {
+            assert_eq!(
+                nand.is_bad_block(block).unwrap(),
+                [1, 2, 4].contains(&block)
+            );
+        }
+        let mut buf = vec![0u8; data.len()];
+        nand.read(0, &mut buf, |_| {}).unwrap();
+        assert_eq!(buf, data);
+
+        flash.erased.borrow_mut().clear();
+        assert_eq!(nand.erase(0, 0x60000, |_| {}).unwrap(), 3);
+        assert_eq!(*flash.erased.borrow(), [0, 3, 5]);
+
+        flash.erased.borrow_mut().clear();
+        nand.write_boot0(&data[..3000], 1024, 2).unwrap();
+        assert_eq!(*flash.erased.borrow(), [0, 3]);
+    }
+
+    #[test]
+    fn spinand_boot0_copies() {
+        let flash = SimNand::new(&[0]);
+        let nand = SpiNand::detect(&flash).unwrap();
+        assert_eq!(nand.boot0_capacity(1024), 64 * 1024);
+        let image: Vec<u8> = (0..3000u32).map(|i| (i % 253) as u8).collect();
+        nand.write_boot0(&image, 1024, 2).unwrap();
+        assert_eq!(*flash.erased.borrow(), [1, 2]);
+        for block in [1, 2] {
+            let pages = flash.pages.borrow();
+            for (index, chunk) in image.chunks(1024).enumerate() {
+                let page = &pages[&(block * 64 + index as u32)];
+                assert_eq!(&page[..chunk.len()], chunk);
+                assert!(page[chunk.len()..2048].iter().all(|byte| *byte == 0xff));
+            }
+        }
+    }
+
+    #[test]
+    fn spinand_ecc_error() {
+        let mut flash = SimNand::new(&[]);
+        flash.ecc_failures.insert(5);
+        let nand = SpiNand::detect(&flash).unwrap();
+        let mut buf = [0u8; 16];
+        nand.read_page(4, &mut buf).unwrap();
+        assert!(matches!(
+            nand.read_page(5, &mut buf),
+            Err(Error::EccUncorrectable { address: 0x2800 })
+        ));
+    }
+}
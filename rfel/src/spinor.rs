@@ -0,0 +1,489 @@
+//! SPI NOR flash detection, erase, program and read over an SPI bus.
+
+use crate::{
+    Error, Result,
+    spi::{SpiBus, SpiOp},
+};
+
+const READ_ID: u8 = 0x9f;
+const READ_SFDP: u8 = 0x5a;
+const READ_STATUS: u8 = 0x05;
+const WRITE_ENABLE: u8 = 0x06;
+const READ_DATA: u8 = 0x03;
+const PAGE_PROGRAM: u8 = 0x02;
+const READ_DATA_4BYTE: u8 = 0x13;
+const PAGE_PROGRAM_4BYTE: u8 = 0x12;
+/// Erase opcodes with 3-byte address and their 4-byte address counterparts.
+const ERASE_4BYTE: [(u8, u8); 3] = [(0x20, 0x21), (0x52, 0x5c), (0xd8, 0xdc)];
+
+const STATUS_BUSY: u8 = 1 << 0;
+/// Largest length of one page program command.
+const PAGE_SIZE: u32 = 256;
+
+/// Erase command supported by a flash.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EraseType {
+    /// Size of erased region in bytes, also its alignment.
+    pub size: u32,
+    /// Command opcode.
+    pub opcode: u8,
+}
+
+/// 4 KiB sector erase and 64 KiB block erase, supported by nearly all flashes.
+const ERASE_DEFAULT: [EraseType; 2] = [
+    EraseType {
+        size: 0x1000,
+        opcode: 0x20,
+    },
+    EraseType {
+        size: 0x10000,
+        opcode: 0xd8,
+    },
+];
+/// [`ERASE_DEFAULT`] with 4-byte address opcodes.
+const ERASE_DEFAULT_4BYTE: [EraseType; 2] = [
+    EraseType {
+        size: 0x1000,
+        opcode: 0x21,
+    },
+    EraseType {
+        size: 0x10000,
+        opcode: 0xdc,
+    },
+];
+
+/// Known flashes as JEDEC ID, name and size in bytes.
+const KNOWN_FLASH: &[([u8; 3], &str, u32)] = &[
+    ([0xef, 0x40, 0x16], "W25Q32", 4 << 20),
+    ([0xef, 0x40, 0x17], "W25Q64", 8 << 20),
+    ([0xef, 0x40, 0x18], "W25Q128", 16 << 20),
+    ([0xef, 0x40, 0x19], "W25Q256", 32 << 20),
+    ([0xc8, 0x40, 0x17], "GD25Q64", 8 << 20),
+    ([0xc8, 0x40, 0x18], "GD25Q128", 16 << 20),
+    ([0xc2, 0x20, 0x17], "MX25L6433F", 8 << 20),
+    ([0xc2, 0x20, 0x18], "MX25L12835F", 16 << 20),
+    ([0xc2, 0x20, 0x19], "MX25L25645G", 32 << 20),
+    ([0x0b, 0x40, 0x17], "XT25F64B", 8 << 20),
+    ([0x0b, 0x40, 0x18], "XT25F128B", 16 << 20),
+    ([0x1c, 0x70, 0x18], "EN25QH128", 16 << 20),
+    ([0x20, 0xba, 0x18], "N25Q128", 16 << 20),
+];
+
+/// Parameters of a detected SPI NOR flash.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FlashInfo {
+    /// Part name, or `"SFDP"` for unlisted flashes described by SFDP.
+    pub name: &'static str,
+    /// JEDEC manufacturer and device ID.
+    pub id: [u8; 3],
+    /// Capacity in bytes.
+    pub size: u32,
+    /// Supported erase commands, smallest first, with 4-byte address opcodes
+    /// on flashes larger than 16 MiB.
+    pub erase: Vec<EraseType>,
+}
+
+/// SPI NOR flash connected to an SPI bus.
+pub struct SpiNor<B> {
+    bus: B,
+    info: FlashInfo,
+    address_bytes: usize,
+}
+
+impl<B: SpiBus> SpiNor<B> {
+    /// Identify flash by JEDEC ID and SFDP tables.
+    ///
+    /// Flashes larger than 16 MiB are accessed by dedicated 4-byte address
+    /// opcodes, leaving flash in 3-byte address mode the boot ROM expects.
+    pub fn detect(bus: B) -> Result<Self> {
+        let mut id = [0u8; 3];
+        bus.transaction(&[READ_ID], &mut id)?;
+        let known = KNOWN_FLASH.iter().find(|(known, _, _)| *known == id);
+        let mut info = match (read_sfdp(&bus)?, known) {
+            (Some((size, erase)), known) => FlashInfo {
+                name: known.map_or("SFDP", |(_, name, _)| name),
+                id,
+                size,
+                erase,
+            },
+            (None, Some(&(_, name, size))) => FlashInfo {
+                name,
+                id,
+                size,
+                erase: ERASE_DEFAULT.to_vec(),
+            },
+            (None, None) => return Err(Error::UnknownFlash { id }),
+        };
+        let address_bytes = if info.size > 1 << 24 {
+            info.erase = info
+                .erase
+                .iter()
+                .filter_map(|erase| {
+                    let (_, opcode) = ERASE_4BYTE.iter().find(|(op, _)| *op == erase.opcode)?;
+                    Some(EraseType {
+                        size: erase.size,
+                        opcode: *opcode,
+                    })
+                })
+                .collect();
+            if info.erase.is_empty() {
+                info.erase = ERASE_DEFAULT_4BYTE.to_vec();
+            }
+            4
+        } else {
+            3
+        };
+        Ok(SpiNor {
+            bus,
+            info,
+            address_bytes,
+        })
+    }
+
+    /// Parameters of this flash.
+    #[inline]
+    pub fn info(&self) -> &FlashInfo {
+        &self.info
+    }
+
+    /// Read flash content starting from `address` into `buf`.
+    pub fn read(&self, address: u32, buf: &mut [u8]) -> Result<()> {
+        let opcode = match self.address_bytes {
+            4 => READ_DATA_4BYTE,
+            _ => READ_DATA,
+        };
+        let chunk_size = self.bus.max_transfer().max(PAGE_SIZE as usize);
+        let commands: Vec<_> = (0..buf.len())
+            .step_by(chunk_size)
+            .map(|offset| self.command(opcode, address + offset as u32))
+            .collect();
+        let mut ops: Vec<_> = commands
+            .iter()
+            .zip(buf.chunks_mut(chunk_size))
+            .map(|(command, chunk)| SpiOp::Transaction(command, chunk))
+            .collect();
+        self.bus.batch(&mut ops)
+    }
+
+    /// Erase all erase units overlapping `length` bytes from `address`.
+    ///
+    /// Uses the largest erase command aligned to each position.
+    pub fn erase(&self, address: u32, length: u32) -> Result<()> {
+        let min = self.info.erase[0].size;
+        let end = (address + length).div_ceil(min) * min;
+        let mut address = address / min * min;
+        let mut commands = Vec::new();
+        while address < end {
+            let erase = self
+                .info
+                .erase
+                .iter()
+                .rev()
+                .find(|erase| address.is_multiple_of(erase.size) && address + erase.size <= end)
+                .unwrap_or(&self.info.erase[0]);
+            commands.push(self.command(erase.opcode, address));
+            address += erase.size;
+        }
+        self.write_batch(&commands)
+    }
+
+    /// Program `data` at `address` into erased flash.
+    pub fn program(&self, address: u32, data: &[u8]) -> Result<()> {
+        let opcode = match self.address_bytes {
+            4 => PAGE_PROGRAM_4BYTE,
+            _ => PAGE_PROGRAM,
+        };
+        let mut address = address;
+        let mut data = data;
+        let mut commands = Vec::new();
+        while !data.is_empty() {
+            let len = ((PAGE_SIZE - address % PAGE_SIZE) as usize).min(data.len());
+            let mut command = self.command(opcode, address);
+            command.extend_from_slice(&data[..len]);
+            commands.push(command);
+            address += len as u32;
+            data = &data[len..];
+        }
+        self.write_batch(&commands)
+    }
+
+    /// Read back flash content at `address` and compare with `data`.
+    pub fn verify(&self, address: u32, data: &[u8]) -> Result<()> {
+        let mut buf = vec![0u8; data.len()];
+        self.read(address, &mut buf)?;
+        match buf.iter().zip(data).position(|(a, b)| a != b) {
+            Some(offset) => Err(Error::VerifyMismatch {
+                address: address + offset as u32,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    fn command(&self, opcode: u8, address: u32) -> Vec<u8> {
+        let mut command = vec![opcode];
+        command.extend_from_slice(&address.to_be_bytes()[4 - self.address_bytes..]);
+        command
+    }
+
+    /// Run each erase or program command after write enable, waiting until
+    /// flash finishes it, all in one batch.
+    fn write_batch(&self, commands: &[Vec<u8>]) -> Result<()> {
+        let mut ops = Vec::with_capacity(commands.len() * 3);
+        for command in commands {
+            ops.push(SpiOp::Transaction(&[WRITE_ENABLE], &mut []));
+            ops.push(SpiOp::Transaction(command, &mut []));
+            ops.push(SpiOp::WaitReady {
+                opcode: READ_STATUS,
+                mask: STATUS_BUSY,
+            });
+        }
+        self.bus.batch(&mut ops)
+    }
+}
+
+/// Read size and erase commands from JEDEC basic flash parameter table.
+fn read_sfdp(bus: &impl SpiBus) -> Result<Option<(u32, Vec<EraseType>)>> {
+    let mut header = [0u8; 16];
+    bus.transaction(&[READ_SFDP, 0, 0, 0, 0], &mut header)?;
+    // first parameter header always describes JEDEC basic flash parameters.
+    if header[..4] != *b"SFDP" || header[8] != 0x00 || header[11] < 2 {
+        return Ok(None);
+    }
+    let dwords = (header[11] as usize).min(16);
+    let pointer = [header[14], header[13], header[12]];
+    let mut table = vec![0u8; dwords * 4];
+    bus.transaction(
+        &[READ_SFDP, pointer[0], pointer[1], pointer[2], 0],
+        &mut table,
+    )?;
+    let dword =
+        |index: usize| u32::from_le_bytes(table[index * 4..index * 4 + 4].try_into().unwrap());
+    let density = dword(1);
+    let bits = if density & (1 << 31) == 0 {
+        density as u64 + 1
+    } else {
+        match 1u64.checked_shl(density & 0x7fff_ffff) {
+            Some(bits) => bits,
+            None => return Ok(None),
+        }
+    };
+    let Ok(size) = u32::try_from(bits / 8) else {
+        return Ok(None);
+    };
+    let mut erase = Vec::new();
+    if dwords >= 9 {
+        for index in 0..4 {
+            let value = dword(7 + index / 2) >> (index % 2 * 16);
+            let exponent = value & 0xff;
+            if exponent != 0 && exponent < 32 {
+                erase.push(EraseType {
+                    size: 1 << exponent,
+                    opcode: (value >> 8) as u8,
+                });
+            }
+        }
+    }
+    if erase.is_empty() && dword(0) & 0b11 == 0b01 {
+        erase.push(EraseType {
+            size: 0x1000,
+            opcode: (dword(0) >> 8) as u8,
+        });
+    }
+    if erase.is_empty() {
+        erase = ERASE_DEFAULT.to_vec();
+    }
+    erase.sort_by_key(|erase| erase.size);
+    Ok(Some((size, erase)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EraseType, SpiNor};
+    use crate::{Error, Result, spi::SpiBus};
+    use core::cell::{Cell, RefCell};
+
+    /// Simulated flash chip following common SPI NOR commands.
+    struct SimFlash {
+        id: [u8; 3],
+        sfdp: Vec<u8>,
+        memory: RefCell<Vec<u8>>,
+        write_enabled: Cell<bool>,
+        erased: RefCell<Vec<(u8, u32)>>,
+        opcodes: RefCell<Vec<u8>>,
+    }
+
+    impl SimFlash {
+        fn new(id: [u8; 3], size: usize) -> Self {
+            SimFlash {
+                id,
+                sfdp: Vec::new(),
+                memory: RefCell::new(vec![0xa5; size]),
+                write_enabled: Cell::new(false),
+                erased: RefCell::new(Vec::new()),
+                opcodes: RefCell::new(Vec::new()),
+            }
+        }
+    }
+
+    impl SpiBus for &SimFlash {
+        fn transaction(&self, write: &[u8], read: &mut [u8]) -> Result<()> {
+            let four_byte = matches!(write[0], 0x13 | 0x12 | 0x21 | 0xdc);
+            let address = || match four_byte {
+                true => u32::from_be_bytes([write[1], write[2], write[3], write[4]]) as usize,
+                false => u32::from_be_bytes([0, write[1], write[2], write[3]]) as usize,
+            };
+            let header = if four_byte { 5 } else { 4 };
+            self.opcodes.borrow_mut().push(write[0]);
+            let mut memory = self.memory.borrow_mut();
+            match write[0] {
+                0x9f => read.copy_from_slice(&self.id[..read.len()]),
+                0x5a => {
+                    for (offset, byte) in read.iter_mut().enumerate() {
+                        *byte = self.sfdp.get(address() + offset).copied().unwrap_or(0xff);
+                    }
+                }
+                0x05 => read.fill(0),
+                0x06 => self.write_enabled.set(true),
+                0x03 | 0x13 => read.copy_from_slice(&memory[address()..address() + read.len()]),
+                opcode @ (0x20 | 0xd8 | 0x21 | 0xdc) => {
+                    assert!(self.write_enabled.replace(false));
+                    let size = match opcode {
+                        0x20 | 0x21 => 0x1000,
+                        _ => 0x10000,
+                    };
+                    assert_eq!(address() % size, 0);
+                    memory[address()..address() + size].fill(0xff);
+                    self.erased.borrow_mut().push((opcode, address() as u32));
+                }
+                0x02 | 0x12 => {
+                    assert!(self.write_enabled.replace(false));
+                    let data = &write[header..];
+                    assert!(address() % 256 + data.len() <= 256);
+                    for (offset, byte) in data.iter().enumerate() {
+                        memory[address() + offset] &= byte;
+                    }
+                }
+                opcode => panic!("unexpected opcode 0x{:02x}", opcode),
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn spinor_detect() {
+        let flash = SimFlash::new([0xef, 0x40, 0x18], 0);
+        let nor = SpiNor::detect(&flash).unwrap();
+        assert_eq!(nor.info().name, "W25Q128");
+        assert_eq!(nor.info().size, 16 << 20);
+
+        let flash = SimFlash::new([0x12, 0x34, 0x56], 0);
+        assert!(matches!(
+            SpiNor::detect(&flash),
+            Err(Error::UnknownFlash {
+                id: [0x12, 0x34, 0x56]
+            })
+        ));
+
+        let mut flash = SimFlash::new([0x12, 0x34, 0x56], 0);
+        let mut sfdp = vec![0xff; 0x80];
+        sfdp[..16].copy_from_slice(&[
+            b'S', b'F', b'D', b'P', 0x06, 0x01, 0x00, 0xff, 0x00, 0x06, 0x01, 0x09, 0x30, 0x00,
+            0x00, 0xff,
+        ]);
+        let table: [u32; 9] = [
+            0xfff1_20e5,
+            0x01ff_ffff,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0x520f_200c,
+            0x0000_d810,
+        ];
+        for (index, dword) in table.iter().enumerate() {
+            sfdp[0x30 + index * 4..0x34 + index * 4].copy_from_slice(&dword.to_le_bytes());
+        }
+        flash.sfdp = sfdp;
+        let nor = SpiNor::detect(&flash).unwrap();
+        assert_eq!(nor.info().name, "SFDP");
+        assert_eq!(nor.info().size, 4 << 20);
+        assert_eq!(
+            nor.info().erase,
+            [
+                EraseType {
+                    size: 0x1000,
+                    opcode: 0x20
+                },
+                EraseType {
+                    size: 0x8000,
+                    opcode: 0x52
+                },
+                EraseType {
+                    size: 0x10000,
+                    opcode: 0xd8
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn spinor_erase_program_read() {
+        let flash = SimFlash::new([0xef, 0x40, 0x17], 0x40000);
+        let nor = SpiNor::detect(&flash).unwrap();
+        nor.erase(0x1800, 0x20000).unwrap();
+        let erased = flash.erased.borrow().clone();
+        assert_eq!(erased.len(), 15 + 1 + 2);
+        assert_eq!(erased[0], (0x20, 0x1000));
+        assert_eq!(erased[15], (0xd8, 0x10000));
+        assert_eq!(erased[17], (0x20, 0x21000));
+
+        let data: Vec<u8> = (0..1000u32).map(|i| (i * 7) as u8).collect();
+        nor.program(0x1f80, &data).unwrap();
+        nor.verify(0x1f80, &data).unwrap();
+        let mut buf = vec![0u8; 0x100];
+        nor.read(0x1e80, &mut buf).unwrap();
+        assert!(buf.iter().all(|byte| *byte == 0xff));
+
+        // programming without erase leaves old content.
+        nor.program(0x30000, &data).unwrap();
+        assert!(matches!(
+            nor.verify(0x30000, &data),
+            Err(Error::VerifyMismatch { address: 0x30001 })
+        ));
+    }
+
+    #[test]
+    fn spinor_4byte_address() {
+        // W25Q256 backed by 4 MiB of memory; only its low part is accessed.
+        let flash = SimFlash::new([0xef, 0x40, 0x19], 4 << 20);
+        let nor = SpiNor::detect(&flash).unwrap();
+        assert_eq!(nor.info().size, 32 << 20);
+        assert_eq!(
+            nor.info().erase,
+            [
+                EraseType {
+                    size: 0x1000,
+                    opcode: 0x21
+                },
+                EraseType {
+                    size: 0x10000,
+                    opcode: 0xdc
+                },
+            ]
+        );
+        nor.erase(0x31000, 0x10000).unwrap();
+        assert_eq!(flash.erased.borrow()[0], (0x21, 0x31000));
+        let data: Vec<u8> = (0..600u32).map(|i| (i * 3) as u8).collect();
+        nor.program(0x31080, &data).unwrap();
+        nor.verify(0x31080, &data).unwrap();
+        // flash never leaves 3-byte address mode.
+        assert!(
+            !flash
+                .opcodes
+                .borrow()
+                .iter()
+                .any(|op| matches!(op, 0xb7 | 0x03 | 0x02))
+        );
+    }
+}
@@ -0,0 +1,419 @@
+//! TOC0 and TOC1 packages used by secure boot.
+//!
+//! TOC0 replaces eGON.BT0 as boot0 image on chips with secure boot enabled;
+//! its items carry the signing certificate and firmware. TOC1 packs later
+//! boot stages like OpenSBI, U-Boot and device tree for boot0 to load.
+//! Both are checksummed like eGON.BT0 with [`EgonHead::STAMP_VALUE`](crate::EgonHead::STAMP_VALUE).
+
+use crate::egon::stamp_checksum;
+use core::fmt;
+
+/// Magic number in head of TOC0 and TOC1 packages.
+pub const TOC_MAGIC: u32 = 0x8911_9800;
+/// End marker of package head.
+const MAIN_END: [u8; 4] = *b"MIE;";
+/// End marker of each item.
+const ITEM_END: [u8; 4] = *b"IIE;";
+
+/// TOC0 boot0 package.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Toc0 {
+    pub serial: u32,
+    pub status: u32,
+    pub platform: [u8; 4],
+    pub variant: [u8; 4],
+    pub items: Vec<Toc0Item>,
+}
+
+/// Item of a TOC0 package.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Toc0Item {
+    /// Item name, one of `Toc0::ITEM_*`.
+    pub name: u32,
+    pub status: u32,
+    pub kind: u32,
+    /// Address where BROM loads firmware item.
+    pub load_address: u32,
+    pub data: Vec<u8>,
+}
+
+impl Toc0 {
+    /// Name in TOC0 head.
+    pub const NAME: [u8; 8] = *b"TOC0.GLH";
+    /// Certificate item, a DER encoded X.509 certificate signing firmware.
+    pub const ITEM_CERTIFICATE: u32 = 0x0001_0101;
+    /// Firmware item, loaded and run by BROM.
+    pub const ITEM_FIRMWARE: u32 = 0x0001_0202;
+    /// Key item, holding public keys for later stages.
+    pub const ITEM_KEY: u32 = 0x0001_0303;
+    /// Size of zero-filled certificate reserved before image is signed.
+    pub const CERTIFICATE_PLACEHOLDER_SIZE: usize = 0x400;
+
+    const HEAD_SIZE: usize = 44;
+    const ITEM_SIZE: usize = 32;
+    const CHECKSUM_OFFSET: usize = 12;
+    const ITEM_ALIGN: usize = 32;
+
+    /// Parse TOC0 package, checking its head, items and checksum.
+    pub fn parse(image: &[u8]) -> Result<Self, TocError> {
+        if image.len() < Self::HEAD_SIZE || image[..8] != Self::NAME {
+            return Err(TocError::InvalidMagic);
+        }
+        let word = |offset: usize| read_u32(image, offset);
+        if word(8) != TOC_MAGIC || image[40..44] != MAIN_END {
+            return Err(TocError::InvalidMagic);
+        }
+        let length = check_length(image, word(28) as usize, Self::CHECKSUM_OFFSET)?;
+        let image = &image[..length];
+        let count = word(24) as usize;
+        let mut items = Vec::new();
+        for index in 0..count {
+            let base = Self::HEAD_SIZE + index * Self::ITEM_SIZE;
+            let Some(item) = image.get(base..base + Self::ITEM_SIZE) else {
+                return Err(TocError::InvalidItem(index));
+            };
+            let field = |offset: usize| read_u32(item, offset);
+            if item[28..32] != ITEM_END {
+                return Err(TocError::InvalidItem(index));
+            }
+            let (offset, size) = (field(4) as usize, field(8) as usize);
+            let Some(data) = image.get(offset..offset + size) else {
+                return Err(TocError::InvalidItem(index));
+            };
+            items.push(Toc0Item {
+                name: field(0),
+                status: field(12),
+                kind: field(16),
+                load_address: field(20),
+                data: data.to_vec(),
+            });
+        }
+        Ok(Toc0 {
+            serial: word(16),
+            status: word(20),
+            platform: image[32..36].try_into().unwrap(),
+            variant: image[36..40].try_into().unwrap(),
+            items,
+        })
+    }
+
+    /// Build TOC0 package, padded to a multiple of `align` bytes.
+    pub fn build(&self, align: usize) -> Vec<u8> {
+        let table_end = Self::HEAD_SIZE + self.items.len() * Self::ITEM_SIZE;
+        let mut image = vec![0u8; table_end];
+        image[..8].copy_from_slice(&Self::NAME);
+        write_u32(&mut image, 8, TOC_MAGIC);
+        write_u32(&mut image, 16, self.serial);
+        write_u32(&mut image, 20, self.status);
+        write_u32(&mut image, 24, self.items.len() as u32);
+        image[32..36].copy_from_slice(&self.platform);
+        image[36..40].copy_from_slice(&self.variant);
+        image[40..44].copy_from_slice(&MAIN_END);
+        for (index, item) in self.items.iter().enumerate() {
+            let offset = append_aligned(&mut image, &item.data, Self::ITEM_ALIGN);
+            let base = Self::HEAD_SIZE + index * Self::ITEM_SIZE;
+            write_u32(&mut image, base, item.name);
+            write_u32(&mut image, base + 4, offset as u32);
+            write_u32(&mut image, base + 8, item.data.len() as u32);
+            write_u32(&mut image, base + 12, item.status);
+            write_u32(&mut image, base + 16, item.kind);
+            write_u32(&mut image, base + 20, item.load_address);
+            image[base + 28..base + 32].copy_from_slice(&ITEM_END);
+        }
+        finish(&mut image, align, 28, Self::CHECKSUM_OFFSET);
+        image
+    }
+
+    /// Human readable name of a TOC0 item name.
+    pub fn item_name(name: u32) -> &'static str {
+        match name {
+            Self::ITEM_CERTIFICATE => "certificate",
+            Self::ITEM_FIRMWARE => "firmware",
+            Self::ITEM_KEY => "key",
+            _ => "unknown",
+        }
+    }
+}
+
+/// TOC1 package of later boot stages.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Toc1 {
+    /// Package name, e.g. [`Toc1::DEFAULT_NAME`].
+    pub name: String,
+    pub serial: u32,
+    pub status: u32,
+    pub version_main: u32,
+    pub version_sub: u32,
+    pub items: Vec<Toc1Item>,
+}
+
+/// Item of a TOC1 package.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Toc1Item {
+    /// Item name like `opensbi`, `u-boot` or `dtb`.
+    pub name: String,
+    pub encrypt: u32,
+    pub kind: u32,
+    /// Address where boot0 loads and runs this item.
+    pub run_address: u32,
+    pub index: u32,
+    pub data: Vec<u8>,
+}
+
+impl Toc1 {
+    /// Name of packages built by Allwinner packing tools.
+    pub const DEFAULT_NAME: &'static str = "sunxi-package";
+
+    const HEAD_SIZE: usize = 64;
+    const ITEM_SIZE: usize = 368;
+    const CHECKSUM_OFFSET: usize = 20;
+    const ITEM_ALIGN: usize = 512;
+
+    /// Parse TOC1 package, checking its head, items and checksum.
+    pub fn parse(image: &[u8]) -> Result<Self, TocError> {
+        if image.len() < Self::HEAD_SIZE {
+            return Err(TocError::InvalidMagic);
+        }
+        let word = |offset: usize| read_u32(image, offset);
+        if word(16) != TOC_MAGIC || image[60..64] != MAIN_END {
+            return Err(TocError::InvalidMagic);
+        }
+        let length = check_length(image, word(36) as usize, Self::CHECKSUM_OFFSET)?;
+        let image = &image[..length];
+        let count = word(32) as usize;
+        let mut items = Vec::new();
+        for index in 0..count {
+            let base = Self::HEAD_SIZE + index * Self::ITEM_SIZE;
+            let Some(item) = image.get(base..base + Self::ITEM_SIZE) else {
+                return Err(TocError::InvalidItem(index));
+            };
+            let field = |offset: usize| read_u32(item, offset);
+            if item[364..368] != ITEM_END {
+                return Err(TocError::InvalidItem(index));
+            }
+            let (offset, size) = (field(64) as usize, field(68) as usize);
+            let Some(data) = image.get(offset..offset + size) else {
+                return Err(TocError::InvalidItem(index));
+            };
+            items.push(Toc1Item {
+                name: read_name(&item[..64]),
+                encrypt: field(72),
+                kind: field(76),
+                run_address: field(80),
+                index: field(84),
+                data: data.to_vec(),
+            });
+        }
+        Ok(Toc1 {
+            name: read_name(&image[..16]),
+            serial: word(24),
+            status: word(28),
+            version_main: word(40),
+            version_sub: word(44),
+            items,
+        })
+    }
+
+    /// Build TOC1 package, padded to a multiple of `align` bytes.
+    ///
+    /// Package and item names longer than their fields are truncated.
+    pub fn build(&self, align: usize) -> Vec<u8> {
+        let table_end = Self::HEAD_SIZE + self.items.len() * Self::ITEM_SIZE;
+        let mut image = vec![0u8; table_end];
+        write_name(&mut image[..16], &self.name);
+        write_u32(&mut image, 16, TOC_MAGIC);
+        write_u32(&mut image, 24, self.serial);
+        write_u32(&mut image, 28, self.status);
+        write_u32(&mut image, 32, self.items.len() as u32);
+        write_u32(&mut image, 40, self.version_main);
+        write_u32(&mut image, 44, self.version_sub);
+        image[60..64].copy_from_slice(&MAIN_END);
+        for (index, item) in self.items.iter().enumerate() {
+            let offset = append_aligned(&mut image, &item.data, Self::ITEM_ALIGN);
+            let base = Self::HEAD_SIZE + index * Self::ITEM_SIZE;
+            write_name(&mut image[base..base + 64], &item.name);
+            write_u32(&mut image, base + 64, offset as u32);
+            write_u32(&mut image, base + 68, item.data.len() as u32);
+            write_u32(&mut image, base + 72, item.encrypt);
+            write_u32(&mut image, base + 76, item.kind);
+            write_u32(&mut image, base + 80, item.run_address);
+            write_u32(&mut image, base + 84, item.index);
+            image[base + 364..base + 368].copy_from_slice(&ITEM_END);
+        }
+        finish(&mut image, align, 36, Self::CHECKSUM_OFFSET);
+        image
+    }
+}
+
+/// Error found when parsing a TOC0 or TOC1 package.
+#[derive(Debug, PartialEq, Eq)]
+pub enum TocError {
+    /// Name, magic number or end marker of head mismatches.
+    InvalidMagic,
+    /// Length in head is not word aligned or exceeds the file.
+    InvalidLength {
+        /// Length in head.
+        length: usize,
+        /// Length of file.
+        file: usize,
+    },
+    /// Checksum in head mismatches checksum of package.
+    ChecksumMismatch {
+        /// Checksum in head.
+        head: u32,
+        /// Checksum computed from package.
+        computed: u32,
+    },
+    /// Item entry of given index is truncated, lacks end marker or points outside package.
+    InvalidItem(usize),
+}
+
+impl fmt::Display for TocError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TocError::InvalidMagic => write!(f, "not a TOC0 or TOC1 package"),
+            TocError::InvalidLength { length, file } => write!(
+                f,
+                "invalid package length {} for file of {} bytes",
+                length, file
+            ),
+            TocError::ChecksumMismatch { head, computed } => write!(
+                f,
+                "package checksum mismatch, head has 0x{:08x} but package sums to 0x{:08x}",
+                head, computed
+            ),
+            TocError::InvalidItem(index) => write!(f, "invalid item {}", index),
+        }
+    }
+}
+
+impl std::error::Error for TocError {}
+
+#[inline]
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+#[inline]
+fn write_u32(buf: &mut [u8], offset: usize, value: u32) {
+    buf[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+fn read_name(field: &[u8]) -> String {
+    let end = field.iter().position(|b| *b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+fn write_name(field: &mut [u8], name: &str) {
+    // keep a terminating zero.
+    let len = name.len().min(field.len() - 1);
+    field[..len].copy_from_slice(&name.as_bytes()[..len]);
+}
+
+/// Check length in head against file and checksum of package, returning the length.
+fn check_length(image: &[u8], length: usize, checksum_offset: usize) -> Result<usize, TocError> {
+    if length > image.len() || !length.is_multiple_of(4) || length < checksum_offset + 4 {
+        return Err(TocError::InvalidLength {
+            length,
+            file: image.len(),
+        });
+    }
+    let head = read_u32(image, checksum_offset);
+    let computed = stamp_checksum(&image[..length], checksum_offset);
+    if head != computed {
+        return Err(TocError::ChecksumMismatch { head, computed });
+    }
+    Ok(length)
+}
+
+/// Append data at next multiple of `align`, returning its offset.
+fn append_aligned(image: &mut Vec<u8>, data: &[u8], align: usize) -> usize {
+    let offset = image.len().next_multiple_of(align);
+    image.resize(offset, 0);
+    image.extend_from_slice(data);
+    offset
+}
+
+/// Pad package and fill its length and checksum fields.
+fn finish(image: &mut Vec<u8>, align: usize, length_offset: usize, checksum_offset: usize) {
+    image.resize(image.len().next_multiple_of(align.max(4)), 0);
+    let length = image.len() as u32;
+    write_u32(image, length_offset, length);
+    let checksum = stamp_checksum(image, checksum_offset);
+    write_u32(image, checksum_offset, checksum);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Toc0, Toc0Item, Toc1, Toc1Item, TocError};
+
+    #[test]
+    fn toc0_build_parse() {
+        let toc0 = Toc0 {
+            serial: 0,
+            status: 0,
+            platform: [0; 4],
+            variant: [0; 4],
+            items: vec![
+                Toc0Item {
+                    name: Toc0::ITEM_CERTIFICATE,
+                    status: 0,
+                    kind: 0,
+                    load_address: 0,
+                    data: vec![0; Toc0::CERTIFICATE_PLACEHOLDER_SIZE],
+                },
+                Toc0Item {
+                    name: Toc0::ITEM_FIRMWARE,
+                    status: 0,
+                    kind: 0,
+                    load_address: 0x2_0060,
+                    data: (0..1001u32).map(|i| i as u8).collect(),
+                },
+            ],
+        };
+        let mut image = toc0.build(0x2000);
+        assert_eq!(image.len(), 0x2000);
+        assert_eq!(&image[..8], b"TOC0.GLH");
+        assert_eq!(Toc0::parse(&image), Ok(toc0));
+        image[0x500] ^= 1;
+        assert!(matches!(
+            Toc0::parse(&image),
+            Err(TocError::ChecksumMismatch { .. })
+        ));
+        assert_eq!(
+            Toc0::parse(&image[..0x1000]).unwrap_err(),
+            TocError::InvalidLength {
+                length: 0x2000,
+                file: 0x1000
+            }
+        );
+    }
+
+    #[test]
+    fn toc1_build_parse() {
+        let item = |name: &str, run_address: u32, len: u32| Toc1Item {
+            name: name.to_string(),
+            encrypt: 0,
+            kind: 0,
+            run_address,
+            index: 0,
+            data: (0..len).map(|i| (i * 3) as u8).collect(),
+        };
+        let toc1 = Toc1 {
+            name: Toc1::DEFAULT_NAME.to_string(),
+            serial: 0,
+            status: 0,
+            version_main: 1,
+            version_sub: 0,
+            items: vec![
+                item("opensbi", 0x4000_0000, 3000),
+                item("u-boot", 0x4a00_0000, 70000),
+                item("dtb", 0x4400_0000, 100),
+            ],
+        };
+        let image = toc1.build(0x4000);
+        assert_eq!(image.len() % 0x4000, 0);
+        assert_eq!(Toc1::parse(&image), Ok(toc1));
+        assert_eq!(Toc0::parse(&image), Err(TocError::InvalidMagic));
+    }
+}
@@ -0,0 +1,264 @@
+use crate::{Error, Result};
+use core::{
+    future::Future,
+    pin::{Pin, pin},
+    task::{Context, Poll, Waker},
+};
+use log::{debug, error};
+use nusb::transfer::{EndpointType, RequestBuffer};
+use std::{
+    sync::{Arc, OnceLock, mpsc},
+    task::Wake,
+    thread::{self, Thread},
+    time::{Duration, Instant},
+};
+
+/// Bulk transfer channel to an FEL device.
+///
+/// FEL protocol is built upon one bulk in and one bulk out endpoint;
+/// `AsyncFel` frames its requests over any type implementing this trait.
+pub trait FelTransport {
+    /// Receive at most `length` bytes from bulk in endpoint.
+    fn bulk_in(&self, length: usize) -> impl Future<Output = Result<Vec<u8>>>;
+    /// Send all bytes in `buf` through bulk out endpoint.
+    fn bulk_out(&self, buf: &[u8]) -> impl Future<Output = Result<()>>;
+    /// Run transfers in order, returning data received by each `In` transfer.
+    ///
+    /// Provided method waits for each transfer before starting the next one;
+    /// transports may keep several transfers in flight instead.
+    fn pipeline(
+        &self,
+        transfers: impl IntoIterator<Item = Transfer>,
+    ) -> impl Future<Output = Result<Vec<Vec<u8>>>> {
+        async move {
+            let mut received = Vec::new();
+            for transfer in transfers {
+                match transfer {
+                    Transfer::Out(buf) => self.bulk_out(&buf).await?,
+                    Transfer::In(length) => received.push(self.bulk_in(length).await?),
+                }
+            }
+            Ok(received)
+        }
+    }
+}
+
+/// One transfer of a pipelined batch.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Transfer {
+    /// Send bytes through bulk out endpoint.
+    Out(Vec<u8>),
+    /// Receive at most given number of bytes from bulk in endpoint.
+    In(usize),
+}
+
+/// FEL transport over USB interface of a device in FEL mode.
+pub struct UsbTransport<'a> {
+    iface: &'a mut nusb::Interface,
+    endpoint_in: u8,
+    endpoint_out: u8,
+    timeout: Duration,
+}
+
+/// Default timeout of each USB transfer.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Most transfers in flight on each endpoint when pipelining.
+const QUEUE_DEPTH: usize = 8;
+
+impl<'a> UsbTransport<'a> {
+    #[inline]
+    pub fn open_interface(iface: &'a mut nusb::Interface) -> Result<Self> {
+        let mut endpoint_in = None;
+        let mut endpoint_out = None;
+        for descriptor in iface.descriptors() {
+            for endpoint in descriptor.endpoints() {
+                if endpoint.transfer_type() != EndpointType::Bulk {
+                    continue;
+                }
+                match endpoint.direction() {
+                    nusb::transfer::Direction::In => endpoint_in = Some(endpoint.address()),
+                    nusb::transfer::Direction::Out => endpoint_out = Some(endpoint.address()),
+                }
+            }
+        }
+        let (Some(endpoint_in), Some(endpoint_out)) = (endpoint_in, endpoint_out) else {
+            error!(
+                "Malformed device. Allwinner USB FEL device should include exactly one bulk in and one bulk out endpoint."
+            );
+            return Err(Error::MalformedDevice);
+        };
+        debug!(
+            "Endpoint in ID 0x{:x}, out ID 0x{:x}",
+            endpoint_in, endpoint_out
+        );
+        Ok(Self {
+            iface,
+            endpoint_in,
+            endpoint_out,
+            timeout: DEFAULT_TIMEOUT,
+        })
+    }
+
+    /// Set timeout of each USB transfer.
+    ///
+    /// Operations like `exec` hold the status transfer until called code
+    /// returns; increase timeout for long running payloads.
+    #[inline]
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Wrap USB transfer future so that it fails once timeout elapses.
+    fn timeout<F: Future + Unpin>(&self, future: F) -> Timeout<F> {
+        Timeout {
+            future,
+            deadline: Instant::now() + self.timeout,
+            waker: None,
+        }
+    }
+}
+
+impl FelTransport for UsbTransport<'_> {
+    async fn bulk_in(&self, length: usize) -> Result<Vec<u8>> {
+        let buf = RequestBuffer::new(length);
+        let ans = self
+            .timeout(self.iface.bulk_in(self.endpoint_in, buf))
+            .await?;
+        Ok(ans.into_result()?)
+    }
+
+    async fn bulk_out(&self, buf: &[u8]) -> Result<()> {
+        let ans = self
+            .timeout(self.iface.bulk_out(self.endpoint_out, buf.to_vec()))
+            .await?;
+        ans.status?;
+        Ok(())
+    }
+
+    async fn pipeline(
+        &self,
+        transfers: impl IntoIterator<Item = Transfer>,
+    ) -> Result<Vec<Vec<u8>>> {
+        // FEL device serves requests strictly in order, so transfers queued on
+        // both endpoints complete in submission order. Dropping queues on error
+        // cancels transfers still in flight.
+        let mut out_queue = self.iface.bulk_out_queue(self.endpoint_out);
+        let mut in_queue = self.iface.bulk_in_queue(self.endpoint_in);
+        let mut received = Vec::new();
+        for transfer in transfers {
+            match transfer {
+                Transfer::Out(buf) => {
+                    if out_queue.pending() >= QUEUE_DEPTH {
+                        self.timeout(out_queue.next_complete()).await?.status?;
+                    }
+                    out_queue.submit(buf);
+                }
+                Transfer::In(length) => {
+                    if in_queue.pending() >= QUEUE_DEPTH {
+                        let completion = self.timeout(in_queue.next_complete()).await?;
+                        received.push(completion.into_result()?);
+                    }
+                    in_queue.submit(RequestBuffer::new(length));
+                }
+            }
+        }
+        while out_queue.pending() > 0 {
+            self.timeout(out_queue.next_complete()).await?.status?;
+        }
+        while in_queue.pending() > 0 {
+            let completion = self.timeout(in_queue.next_complete()).await?;
+            received.push(completion.into_result()?);
+        }
+        Ok(received)
+    }
+}
+
+/// Future failing with [`Error::Timeout`] unless inner future completes before deadline.
+///
+/// Dropping a pending nusb transfer future cancels the transfer.
+struct Timeout<F> {
+    future: F,
+    deadline: Instant,
+    waker: Option<Waker>,
+}
+
+impl<F: Future + Unpin> Future for Timeout<F> {
+    type Output = Result<F::Output>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        if let Poll::Ready(ans) = Pin::new(&mut self.future).poll(cx) {
+            return Poll::Ready(Ok(ans));
+        }
+        if Instant::now() >= self.deadline {
+            return Poll::Ready(Err(Error::Timeout));
+        }
+        if !self.waker.as_ref().is_some_and(|w| w.will_wake(cx.waker())) {
+            self.waker = Some(cx.waker().clone());
+            wake_at(self.deadline, cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+/// Wake `waker` once `deadline` passes, from a timer thread shared by all transports.
+fn wake_at(deadline: Instant, waker: Waker) {
+    static TIMER: OnceLock<mpsc::Sender<(Instant, Waker)>> = OnceLock::new();
+    let timer = TIMER.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel::<(Instant, Waker)>();
+        thread::spawn(move || {
+            let mut pending = Vec::new();
+            loop {
+                let now = Instant::now();
+                pending.retain(|(deadline, waker): &(Instant, Waker)| {
+                    let due = *deadline <= now;
+                    if due {
+                        waker.wake_by_ref();
+                    }
+                    !due
+                });
+                let next = match pending.iter().map(|(deadline, _)| *deadline).min() {
+                    Some(deadline) => receiver.recv_timeout(deadline - now).ok(),
+                    None => receiver.recv().ok(),
+                };
+                pending.extend(next);
+            }
+        });
+        sender
+    });
+    // timer thread never exits, so sending always succeeds.
+    let _ = timer.send((deadline, waker));
+}
+
+/// Run future to completion on current thread.
+pub(crate) fn block_on<F: Future>(future: F) -> F::Output {
+    block_on_until(future, None).expect("no deadline to miss")
+}
+
+/// Run future on current thread until it completes, or return `None` once
+/// `deadline` passes.
+pub(crate) fn block_on_until<F: Future>(future: F, deadline: Option<Instant>) -> Option<F::Output> {
+    struct ThreadWaker(Thread);
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    let mut future = pin!(future);
+    loop {
+        if let Poll::Ready(ans) = future.as_mut().poll(&mut cx) {
+            return Some(ans);
+        }
+        match deadline {
+            Some(deadline) => {
+                let now = Instant::now();
+                if now >= deadline {
+                    return None;
+                }
+                thread::park_timeout(deadline - now);
+            }
+            None => thread::park(),
+        }
+    }
+}